@@ -0,0 +1,100 @@
+/*
+ * Benchmarks for the parser, on the generated program
+ * that match_large_input checks for correctness.
+ * parse_moved runs the parser as is, parse_cloned adds
+ * the token clones the parser made before tokens were
+ * moved into nodes, one as each token came in and one
+ * for every node, to show what moving them saves.
+ */
+#![feature(test)]
+
+extern crate rustares;
+extern crate test;
+
+use rustares::intermediate::Node;
+use rustares::module::Module;
+use rustares::parser::*;
+use rustares::scanner::scanner::*;
+use rustares::token::Token;
+use test::{black_box, Bencher};
+
+fn program(repeats: usize) -> String
+{
+    let mut input = String::new();
+
+    for i in 0..repeats {
+        input.push_str(format!("def f(a, b = {}) {{\n\
+                                \x20   if a > b {{\n\
+                                \x20       return a * (b - 1)\n\
+                                \x20   }}\n\
+                                \x20   return [a, b, \"s\"][0]\n\
+                                }}\n\
+                                x = f(1, 2) + {}\n", i, i)
+                       .as_str());
+    }
+    return input;
+}
+
+// Hands the parser a copy of every token, keeping none.
+struct CloningSource<'a> {
+    scanner: Scanner<'a>,
+}
+
+impl<'a> TokenSource for CloningSource<'a>
+{
+    fn next_token(&mut self) -> Token
+    {
+        let token = self.scanner.next_token();
+        return black_box(&token).clone();
+    }
+
+    fn peek_token(&mut self, n: usize) -> &Token
+    {
+        return self.scanner.peek_token(n);
+    }
+}
+
+fn clone_tokens(node: &Node) -> usize
+{
+    let mut count = 1;
+
+    black_box(node.token.clone());
+
+    for child in &node.children {
+        count += clone_tokens(child);
+    }
+    return count;
+}
+
+#[bench]
+fn parse_moved(b: &mut Bencher)
+{
+    let input = program(50);
+    let module = Module::new("bench".to_string());
+
+    b.bytes = input.len() as u64;
+    b.iter(|| {
+        let mut scanner = Scanner::new(input.as_str(), &module);
+        let mut parser = Parser::new(&mut scanner, &module);
+
+        return parser.program().unwrap();
+    });
+}
+
+#[bench]
+fn parse_cloned(b: &mut Bencher)
+{
+    let input = program(50);
+    let module = Module::new("bench".to_string());
+
+    b.bytes = input.len() as u64;
+    b.iter(|| {
+        let mut source = CloningSource {
+            scanner: Scanner::new(input.as_str(), &module),
+        };
+        let mut parser = Parser::new(&mut source, &module);
+        let program = parser.program().unwrap();
+
+        return clone_tokens(&program);
+    });
+}
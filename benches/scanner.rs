@@ -0,0 +1,55 @@
+/*
+ * Benchmarks for the scanner. scan_interned runs the
+ * scanner as is, scan_copied adds the String per token
+ * that the scanner allocated before words and operators
+ * were interned, to show what interning saves.
+ */
+#![feature(test)]
+
+extern crate rustares;
+extern crate test;
+
+use rustares::scanner::scanner::*;
+use rustares::token::*;
+use rustares::module::Module;
+use test::Bencher;
+
+fn program(lines: usize) -> String
+{
+    let mut input = String::new();
+
+    for i in 0..lines {
+        input.push_str(format!("total = total + step * {}\n", i)
+                       .as_str());
+    }
+    return input;
+}
+
+#[bench]
+fn scan_interned(b: &mut Bencher)
+{
+    let input = program(2000);
+    let module = Module::new("bench".to_string());
+
+    b.iter(|| {
+        let mut scanner = Scanner::new(input.as_str(), &module);
+        let tokens: Vec<Token> = scanner.by_ref().collect();
+
+        // total, =, +, step, * and the newline.
+        assert_eq!(scanner.interner().len(), 6);
+        return tokens;
+    });
+}
+
+#[bench]
+fn scan_copied(b: &mut Bencher)
+{
+    let input = program(2000);
+    let module = Module::new("bench".to_string());
+
+    b.iter(|| {
+        let scanner = Scanner::new(input.as_str(), &module);
+        return scanner.map(|token| token.string().to_string())
+                      .collect::<Vec<String>>();
+    });
+}
@@ -0,0 +1,63 @@
+/*
+ * Test that the environment resolves names through
+ * nested scopes.
+ */
+use token::Value::*;
+use env::*;
+
+pub struct EnvMatcher;
+
+impl EnvMatcher
+{
+    pub fn match_shadowing()
+    {
+        println!("Starting match_shadowing() test..");
+        let mut env = Environment::new();
+
+        env.define("x", IntegerValue(1));
+        env.push_scope();
+        env.define("x", IntegerValue(2));
+        if env.get("x") != Some(&IntegerValue(2)) {
+            println!("inner x is not the shadowing binding");
+        }
+        env.pop_scope();
+        if env.get("x") != Some(&IntegerValue(1)) {
+            println!("outer x changed by the shadowing binding");
+        }
+        println!("Ending match_shadowing() test..");
+    }
+
+    pub fn match_nested_blocks()
+    {
+        println!("Starting match_nested_blocks() test..");
+        let mut env = Environment::new();
+
+        env.define("a", IntegerValue(1));
+        env.push_scope();
+        env.push_scope();
+        env.define("b", IntegerValue(2));
+        if !env.set("a", IntegerValue(3)) {
+            println!("set could not reach a in the global frame");
+        }
+        if env.set("c", IntegerValue(4)) || env.get("c").is_some() {
+            println!("set of undefined c was not reported");
+        }
+        env.pop_scope();
+        if env.get("b").is_some() {
+            println!("b outlived its scope");
+        }
+        env.pop_scope();
+        env.pop_scope();
+        if env.depth() != 1 || env.get("a") != Some(&IntegerValue(3)) {
+            println!("global frame lost, depth({}) a({})", env.depth(),
+                     env.get("a").is_some());
+        }
+        println!("Ending match_nested_blocks() test..");
+    }
+
+    pub fn match_all()
+    {
+        EnvMatcher::match_shadowing();
+        EnvMatcher::match_nested_blocks();
+    }
+}
@@ -0,0 +1,82 @@
+/*
+ * Lexically scoped variables. The environment is a stack
+ * of frames, the innermost is the last one. Lookups walk
+ * outwards so an inner frame can shadow an outer binding,
+ * and the outermost (global) frame is never popped.
+ */
+use std::collections::HashMap;
+use token::*;
+
+pub mod env_test;
+
+pub struct Environment {
+    frames: Vec<HashMap<String, Value>>,
+}
+
+impl Environment
+{
+    pub fn new() -> Environment
+    {
+        return Environment {
+            frames: vec![HashMap::new()],
+        };
+    }
+
+    /*
+     * Blocks push a frame on entry and pop it on exit, a
+     * subroutine body gets a fresh one the same way.
+     */
+    pub fn push_scope(&mut self)
+    {
+        self.frames.push(HashMap::new());
+    }
+
+    pub fn pop_scope(&mut self)
+    {
+        if self.frames.len() > 1 {
+            self.frames.pop();
+        }
+    }
+
+    pub fn depth(&self) -> usize
+    {
+        return self.frames.len();
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Value>
+    {
+        for frame in self.frames.iter().rev() {
+            if let Some(value) = frame.get(name) {
+                return Some(value);
+            }
+        }
+        return None;
+    }
+
+    /*
+     * Binds name in the innermost frame, shadowing any outer
+     * binding of the same name.
+     */
+    pub fn define(&mut self, name: &str, value: Value)
+    {
+        let last = self.frames.len() - 1;
+        self.frames[last].insert(name.to_string(), value);
+    }
+
+    /*
+     * Updates the nearest existing binding of name. Returns
+     * false, leaving everything untouched, when name is not
+     * bound anywhere so the caller can decide whether that
+     * is an error or a definition.
+     */
+    pub fn set(&mut self, name: &str, value: Value) -> bool
+    {
+        for frame in self.frames.iter_mut().rev() {
+            if let Some(slot) = frame.get_mut(name) {
+                *slot = value;
+                return true;
+            }
+        }
+        return false;
+    }
+}
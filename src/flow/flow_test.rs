@@ -0,0 +1,55 @@
+/*
+ * Test that check_returns tells exhaustive returns from
+ * subroutines which may fall through.
+ */
+use parser::*;
+use flow::*;
+
+pub struct FlowMatcher;
+
+impl FlowMatcher
+{
+    pub fn match_returns()
+    {
+        let tests = [
+            ("def f(x) {\nif x {\nreturn 1\n} elif x > 1 {\nreturn 2\n} \
+              else {\nreturn 3\n}\n}", 0),
+            ("def f(x) {\nswitch x {\ncase 1: return 1\n\
+              default: return 2\n}\n}", 0),
+            ("def f(x) {\nwhile true {\nif x {\nreturn 1\n}\n}\n}", 0),
+            ("def f(x) {\ndebug x\nreturn\n}", 0),
+            ("def f(x) {\nif x {\nreturn 1\n}\n}", 1),
+            ("def f(x) {\nif x {\nreturn 1\n} elif x > 1 {\ndebug x\n} \
+              else {\nreturn 3\n}\n}", 1),
+            ("def f(x) {\nswitch x {\ncase 1: return 1\n}\n}", 1),
+            ("g = def(x) {\nif x {\nreturn 1\n}\n}", 1),
+        ];
+        println!("Starting match_returns() test..");
+        for (i, &(input, expected)) in tests.iter().enumerate() {
+            let program = match parse(input, "flowmatcher") {
+                Ok(program) => program,
+                Err(errors) => {
+                    println!("{}. unexpected errors({:?})", i, errors);
+                    continue;
+                },
+            };
+            let warnings = check_returns(&program);
+            if warnings.len() != expected {
+                println!("{}. warnings({:?}) != expected warnings({})",
+                         i, warnings, expected);
+            }
+            for warning in warnings {
+                if warning.message != "not all code paths return a value" ||
+                   warning.line_num != 1 {
+                    println!("{}. unexpected warning({:?})", i, warning);
+                }
+            }
+        }
+        println!("Ending match_returns() test..");
+    }
+
+    pub fn match_all()
+    {
+        FlowMatcher::match_returns();
+    }
+}
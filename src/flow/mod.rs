@@ -0,0 +1,120 @@
+/*
+ * Control flow checks over subroutine bodies. A subroutine
+ * which returns a value on some path but can fall off the
+ * end of its body on another is most likely a bug, the
+ * caller would silently get nil on that path.
+ */
+use token::TokenType::*;
+use intermediate::*;
+
+pub mod flow_test;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct FlowWarning {
+    pub line_num: i32,
+    pub line_pos: i32,
+    pub message: String,
+}
+
+/*
+ * Warns about every subroutine (declared or literal) in
+ * the program which returns a value, but not on all paths.
+ */
+pub fn check_returns(program: &Node) -> Vec<FlowWarning>
+{
+    let mut warnings: Vec<FlowWarning> = Vec::new();
+
+    check_subroutines(program, &mut warnings);
+
+    return warnings;
+}
+
+fn check_subroutines(node: &Node, warnings: &mut Vec<FlowWarning>)
+{
+    for child in &node.children {
+        check_subroutines(child, warnings);
+    }
+    let body = match node.get_type() {
+        SUB_DECL    => &node.children[2],
+        SUB_LITERAL => &node.children[1],
+        _ => return,
+    };
+    if returns_value(body) && !always_returns(body) {
+        warnings.push(FlowWarning {
+            line_num: node.token.line_num,
+            line_pos: node.token.line_pos,
+            message: "not all code paths return a value".to_string(),
+        });
+    }
+}
+
+/*
+ * Whether any 'return <expr>' belongs to this body, those
+ * in nested subroutines return from the nested one.
+ */
+fn returns_value(node: &Node) -> bool
+{
+    for child in &node.children {
+        match child.get_type() {
+            RETURN => {
+                if child.children.len() != 0 {
+                    return true;
+                }
+            },
+            SUB_DECL | SUB_LITERAL => (),
+            _ => {
+                if returns_value(child) {
+                    return true;
+                }
+            },
+        }
+    }
+    return false;
+}
+
+/*
+ * Whether control can never reach the end of node. An
+ * if only counts with an else and every branch returning,
+ * a switch only with a default, and a loop only when its
+ * condition is a constant which keeps it running forever.
+ */
+fn always_returns(node: &Node) -> bool
+{
+    match node.get_type() {
+        RETURN => return true,
+        BLOCK  => return node.children.iter().any(|c| always_returns(c)),
+        IF => {
+            if node.children.len() < 4 || !always_returns(&node.children[1]) {
+                return false;
+            }
+            let elif_root = &node.children[2];
+            let mut i = 1;
+            while i < elif_root.children.len() {
+                if !always_returns(&elif_root.children[i]) {
+                    return false;
+                }
+                i += 2;
+            }
+            return always_returns(&node.children[3]);
+        },
+        SWITCH => {
+            let mut has_default = false;
+            for branch in &node.children[1..] {
+                let block = match branch.get_type() {
+                    SWITCH_BRANCH => &branch.children[1],
+                    _ => {
+                        has_default = true;
+                        &**branch
+                    },
+                };
+                if !always_returns(block) {
+                    return false;
+                }
+            }
+            return has_default;
+        },
+        WHILE => return node.children[0].get_type() == TRUE,
+        UNTIL => return node.children[0].get_type() == FALSE,
+        _ => return false,
+    }
+}
@@ -0,0 +1,102 @@
+/*
+ * Test that formatted source parses back into the tree
+ * it was formatted from.
+ */
+use parser::*;
+use formatter::*;
+
+pub struct FormatMatcher;
+
+impl FormatMatcher
+{
+    pub fn match_round_trip()
+    {
+        let tests = [
+            "x = 1 + 2 * 3\ny = (1 + 2) * 3\nz = 1 - (2 - 3)",
+            "a = b = -(1 + 2)\nc = !a || b && ~c",
+            "a, b = b, a\na[0], b.c, d = x = 1, a == t, f(a, b)",
+            "x = +1 + +-a - -+b\ny = +(a + b)",
+            "x = a ? b : c ? d : e\ny = (a ? b : c) ? d : e",
+            "x = (a = 1) + 1\nr = 1..10\ns = a + 1..(1..2)..-c",
+            "def f(a, b = 10, *rest) {\nreturn a + b\n}\ng = def(x) {\nx\n}",
+            "f(1, [2, 3], {\"a\" => 4})[0].size(1)\n(a + b)[1]\nh = {}",
+            "f(a, (b = 1), c = d = 2)\ng(x = a ? b : c)",
+            "a = [x * 2 for x in (b in c) if x]\nh = {k => v for k, v in p}",
+            "x = a < b <= c + 1\ny = (a < b) < c\nz = a < (b < c)",
+            "def f(a: Int, b: Str = 'x', *c: Array) {\nx: Int = a\n}",
+            "a[-1]\na[1..3]\na[..n - 1]\na[n..]\na[..]\na[(1..2)]\na[..n..2]",
+            "if a {\nb\n} elif c {\nd\n} else {\n}",
+            "while a < 10 {\na = a + 1\n}\nuntil a {\n}",
+            "for i in 1..10 step 2 {\ndebug i\n} else {\ndebug 0\n}",
+            "while a {\n} else {\nb\n}",
+            "const LIMIT = 1 + 2",
+            "assert a\nassert a == b, 'a is ' + a",
+            "with open(p) as f, wrap(f) as g {\ndebug g\n}",
+            "x |= 1\nx &= a ^ b\nx <<= y >>= 2\nx += (y = 1)\nx %= 3",
+            "b = x in (1..3) == (y in z)\nfor x in (a in b) == c {\n}",
+            "switch x {\ncase 1, 2..5:\nfoo\ncase 6:\ndefault:\nbar\nbaz\n}",
+            "match x {\n[a, {'k' => _}] => debug a\n-1 => {}\n}",
+            "x = do {\n1\n}\nimport 'module'\nimport lib\nimport 'a/b c'",
+            "s = 'tab\\there \\\"quoted\\\" \\\\ \\x01'\nr = /ab+\\/c/i\nn = 1_000.5",
+        ];
+        println!("Starting match_round_trip() test..");
+        for (i, input) in tests.iter().enumerate() {
+            let program = match parse(input, "formatmatcher") {
+                Ok(program) => program,
+                Err(errors) => {
+                    println!("{}. unexpected errors({:?})", i, errors);
+                    continue;
+                },
+            };
+            let source = Formatter::unparse(&program);
+            match parse(source.as_str(), "formatmatcher") {
+                Ok(reparsed) => {
+                    if !reparsed.structurally_eq(&program) {
+                        println!("{}. formatted source({}) parses to a \
+                                  different tree", i, source);
+                    }
+                },
+                Err(errors) => println!("{}. formatted source({}) gave \
+                                         errors({:?})", i, source, errors),
+            }
+        }
+        println!("Ending match_round_trip() test..");
+    }
+
+    pub fn match_layout()
+    {
+        let input = "def f(a){if a>1{return a*(a-1)}else{return 1}}\n\
+                     switch f(3) {case 1..-1:debug 1\ndefault: debug 2}";
+        let expected = "def f(a) {\n\
+                        \x20   if a > 1 {\n\
+                        \x20       return a * (a - 1)\n\
+                        \x20   } else {\n\
+                        \x20       return 1\n\
+                        \x20   }\n\
+                        }\n\
+                        switch f(3) {\n\
+                        \x20   case 1..-1:\n\
+                        \x20       debug 1\n\
+                        \x20   default:\n\
+                        \x20       debug 2\n\
+                        }\n";
+        println!("Starting match_layout() test..");
+        match parse(input, "formatmatcher") {
+            Ok(program) => {
+                let source = Formatter::unparse(&program);
+                if source != expected {
+                    println!("source({:?}) != expected source({:?})",
+                             source, expected);
+                }
+            },
+            Err(errors) => println!("unexpected errors({:?})", errors),
+        }
+        println!("Ending match_layout() test..");
+    }
+
+    pub fn match_all()
+    {
+        FormatMatcher::match_round_trip();
+        FormatMatcher::match_layout();
+    }
+}
@@ -0,0 +1,550 @@
+/*
+ * Turns a tree back into source. The output is laid out
+ * the one way the formatter knows, blocks indented by four
+ * spaces and binary operators spaced out, with parentheses
+ * only where the tree could not be read back otherwise.
+ */
+use token::TokenType::*;
+use token::Value::*;
+use intermediate::*;
+
+pub mod formatter_test;
+
+const INDENT: &'static str = "    ";
+
+pub struct Formatter {
+    buf: String,
+    level: usize,
+    // Within a for loop's iterable 'in' has to be bracketed.
+    no_in: bool,
+}
+
+/*
+ * Binding strength of a node in expression position, this
+ * mirrors the ladder of expression methods in the parser.
+ * Atoms and anything bracketed bind the tightest.
+ */
+fn precedence(node: &Node) -> u8
+{
+    return match node.get_type() {
+        token_type if token_type.is_assignment() => 1,
+        TERNARY     => 2,
+        RANGE       => 3,
+        LOGICAL_OR  => 4,
+        LOGICAL_AND => 5,
+        EQL | NOT_EQL => 6,
+        IN_TEST     => 7,
+        LT | LE | GT | GE | CHAIN_CMP => 8,
+        BITWISE_OR  => 9,
+        BITWISE_XOR => 10,
+        BITWISE_AND => 11,
+        LEFT_SHIFT | RIGHT_SHIFT => 12,
+        PLUS | MINUS => 13,
+        MUL | DIV | FLOOR_DIV | MODULO => 14,
+        NEGATE | UNARY_PLUS | BANG | COMPL => 15,
+        CALL | SUBSCRIPT | SLICE | MEMBER => 16,
+        _ => 17,
+    }
+}
+
+impl Formatter
+{
+    pub fn new() -> Formatter
+    {
+        return Formatter {
+            buf: String::new(),
+            level: 0,
+            no_in: false,
+        };
+    }
+
+    /*
+     * Formats a whole program, the root BLOCK's statements
+     * are written one per line without braces.
+     */
+    pub fn unparse(program: &Node) -> String
+    {
+        let mut formatter = Formatter::new();
+
+        if program.get_type() == BLOCK {
+            for statement in &program.children {
+                formatter.statement(statement);
+            }
+        }
+        else {
+            formatter.statement(program);
+        }
+        return formatter.buf;
+    }
+
+    fn indent(&mut self)
+    {
+        for _ in 0..self.level {
+            self.buf.push_str(INDENT);
+        }
+    }
+
+    fn statement(&mut self, node: &Node)
+    {
+        self.indent();
+        match node.get_type() {
+            IF     => self.if_statement(node),
+            WHILE | UNTIL => {
+                self.buf.push_str(node.string());
+                self.buf.push(' ');
+                self.expr(&node.children[0]);
+                self.buf.push(' ');
+                self.block(&node.children[1]);
+                self.loop_else(node);
+            },
+            FOR    => self.for_statement(node),
+            WITH   => self.with_statement(node),
+            SWITCH => self.switch_statement(node),
+            MATCH  => self.match_statement(node),
+            SUB_DECL => self.subroutine(node),
+            CONST_DECL => {
+                self.buf.push_str("const ");
+                self.buf.push_str(node.children[0].string());
+                self.buf.push_str(" = ");
+                self.expr(&node.children[1]);
+            },
+            ASSERT => {
+                self.buf.push_str("assert ");
+                self.list(&node.children);
+            },
+            IMPORT | DEBUG | RETURN | YIELD => {
+                self.buf.push_str(node.string());
+                if node.children.len() != 0 {
+                    self.buf.push(' ');
+                    self.expr(&node.children[0]);
+                }
+            },
+            ERROR => (),
+            _ => self.expr(node),
+        }
+        self.buf.push('\n');
+    }
+
+    fn block(&mut self, node: &Node)
+    {
+        if node.children.len() == 0 {
+            self.buf.push_str("{}");
+            return;
+        }
+        self.buf.push_str("{\n");
+        self.level += 1;
+        for statement in &node.children {
+            self.statement(statement);
+        }
+        self.level -= 1;
+        self.indent();
+        self.buf.push('}');
+    }
+
+    fn if_statement(&mut self, node: &Node)
+    {
+        self.buf.push_str("if ");
+        self.expr(&node.children[0]);
+        self.buf.push(' ');
+        self.block(&node.children[1]);
+
+        let elif_root = &node.children[2];
+        let mut i = 0;
+        while i < elif_root.children.len() {
+            self.buf.push_str(" elif ");
+            self.expr(&elif_root.children[i]);
+            self.buf.push(' ');
+            self.block(&elif_root.children[i + 1]);
+            i += 2;
+        }
+        if node.children.len() > 3 {
+            self.buf.push_str(" else ");
+            self.block(&node.children[3]);
+        }
+    }
+
+    // The loop variables, iterable and filter of a comprehension.
+    fn comprehension(&mut self, parts: &[Box<Node>])
+    {
+        self.buf.push_str(" for ");
+        for (i, var) in parts[0].children.iter().enumerate() {
+            if i > 0 {
+                self.buf.push_str(", ");
+            }
+            self.buf.push_str(var.string());
+        }
+        self.buf.push_str(" in ");
+        self.no_in = true;
+        self.expr(&parts[1]);
+        self.no_in = false;
+        if parts.len() > 2 {
+            self.buf.push_str(" if ");
+            self.expr(&parts[2].children[0]);
+        }
+    }
+
+    fn for_statement(&mut self, node: &Node)
+    {
+        self.buf.push_str("for ");
+        self.buf.push_str(node.children[0].string());
+        self.buf.push_str(" in ");
+        self.no_in = true;
+        self.expr(&node.children[1]);
+        self.no_in = false;
+        if let Some(step) = node.child(STEP) {
+            self.buf.push_str(" step ");
+            self.expr(&step.children[0]);
+        }
+        self.buf.push(' ');
+        self.block(&node.children[2]);
+        self.loop_else(node);
+    }
+
+    fn with_statement(&mut self, node: &Node)
+    {
+        let last = node.children.len() - 1;
+        self.buf.push_str("with ");
+        for (i, pair) in node.children[..last].chunks(2).enumerate() {
+            if i > 0 {
+                self.buf.push_str(", ");
+            }
+            self.expr(&pair[0]);
+            self.buf.push_str(" as ");
+            self.buf.push_str(pair[1].string());
+        }
+        self.buf.push(' ');
+        self.block(&node.children[last]);
+    }
+
+    fn loop_else(&mut self, node: &Node)
+    {
+        if let Some(else_node) = node.child(ELSE) {
+            self.buf.push_str(" else ");
+            self.block(&else_node.children[0]);
+        }
+    }
+
+    fn switch_statement(&mut self, node: &Node)
+    {
+        self.buf.push_str("switch ");
+        self.expr(&node.children[0]);
+        self.buf.push_str(" {\n");
+        self.level += 1;
+        for branch in &node.children[1..] {
+            self.indent();
+            let block = match branch.get_type() {
+                SWITCH_BRANCH => {
+                    self.buf.push_str("case ");
+                    self.list(&branch.children[0].children);
+                    &branch.children[1]
+                },
+                _ => {
+                    self.buf.push_str("default");
+                    &**branch
+                },
+            };
+            self.buf.push_str(":\n");
+            self.level += 1;
+            for statement in &block.children {
+                self.statement(statement);
+            }
+            self.level -= 1;
+        }
+        self.level -= 1;
+        self.indent();
+        self.buf.push('}');
+    }
+
+    // Patterns are written as the literals they match.
+    fn match_statement(&mut self, node: &Node)
+    {
+        self.buf.push_str("match ");
+        self.expr(&node.children[0]);
+        self.buf.push_str(" {\n");
+        self.level += 1;
+        for arm in &node.children[1..] {
+            self.indent();
+            self.expr(&arm.children[0]);
+            self.buf.push_str(" => ");
+            self.block(&arm.children[1]);
+            self.buf.push('\n');
+        }
+        self.level -= 1;
+        self.indent();
+        self.buf.push('}');
+    }
+
+    fn subroutine(&mut self, node: &Node)
+    {
+        self.buf.push_str("def");
+        let mut i = 0;
+        if node.get_type() == SUB_DECL {
+            self.buf.push(' ');
+            self.buf.push_str(node.children[0].string());
+            i = 1;
+        }
+        self.buf.push('(');
+        for (j, param) in node.children[i].children.iter().enumerate() {
+            if j > 0 {
+                self.buf.push_str(", ");
+            }
+            if param.get_type() == REST_PARAM {
+                self.buf.push('*');
+                self.expr(&param.children[0]);
+                if param.children.len() > 1 {
+                    self.annotation(&param.children[1]);
+                }
+                continue;
+            }
+            self.buf.push_str(param.string());
+            for child in &param.children {
+                if child.get_type() == TYPE_ANNOT {
+                    self.annotation(child);
+                }
+                else {
+                    self.buf.push_str(" = ");
+                    self.expr(child);
+                }
+            }
+        }
+        self.buf.push_str(") ");
+        self.block(&node.children[i + 1]);
+    }
+
+    fn annotation(&mut self, node: &Node)
+    {
+        self.buf.push_str(": ");
+        self.buf.push_str(node.children[0].string());
+    }
+
+    fn list(&mut self, nodes: &[Box<Node>])
+    {
+        for (i, node) in nodes.iter().enumerate() {
+            if i > 0 {
+                self.buf.push_str(", ");
+            }
+            self.expr(node);
+        }
+    }
+
+    /*
+     * Writes node where an operand binding at least as
+     * tight as min is expected, parenthesized otherwise.
+     */
+    fn operand(&mut self, node: &Node, min: u8)
+    {
+        if precedence(node) < min {
+            self.buf.push('(');
+            self.expr(node);
+            self.buf.push(')');
+        }
+        else {
+            self.expr(node);
+        }
+    }
+
+    fn expr(&mut self, node: &Node)
+    {
+        let prec = precedence(node);
+
+        if node.get_type() == IN_TEST && self.no_in {
+            self.no_in = false;
+            self.buf.push('(');
+            self.expr(node);
+            self.buf.push(')');
+            self.no_in = true;
+            return;
+        }
+
+        match node.get_type() {
+            STRING => self.string(node),
+            MODULE_REF => {
+                let bare = node.string().chars().enumerate().all(|(i, c)| {
+                    c.is_ascii_alphabetic() || c == '_' ||
+                    (i > 0 && c.is_ascii_digit())
+                });
+                if bare && node.string().len() != 0 {
+                    self.buf.push_str(node.string());
+                }
+                else {
+                    self.string(node);
+                }
+            },
+            MULTI_ASSIGN => {
+                self.list(&node.children[0].children);
+                self.buf.push_str(" = ");
+                self.list(&node.children[1].children);
+            },
+            token_type if token_type.is_assignment() => {
+                self.operand(&node.children[0], prec + 1);
+                self.buf.push(' ');
+                self.buf.push_str(node.string());
+                self.buf.push(' ');
+                self.operand(&node.children[1], prec);
+            },
+            TERNARY => {
+                self.operand(&node.children[0], prec + 1);
+                self.buf.push_str(" ? ");
+                self.operand(&node.children[1], prec);
+                self.buf.push_str(" : ");
+                self.operand(&node.children[2], prec);
+            },
+            NEGATE | UNARY_PLUS | BANG | COMPL => {
+                self.buf.push(match node.get_type() {
+                    NEGATE => '-',
+                    UNARY_PLUS => '+',
+                    BANG   => '!',
+                    _      => '~',
+                });
+                self.operand(&node.children[0], prec);
+            },
+            CALL => {
+                self.operand(&node.children[0], prec);
+                self.buf.push('(');
+                for (i, arg) in node.children[1..].iter().enumerate() {
+                    if i > 0 {
+                        self.buf.push_str(", ");
+                    }
+                    /*
+                     * Assignments are parenthesized, 'f(a = 1)'
+                     * would pass a keyword argument.
+                     */
+                    self.operand(arg, 2);
+                }
+                self.buf.push(')');
+            },
+            KWARG => {
+                self.buf.push_str(node.children[0].string());
+                self.buf.push_str(" = ");
+                self.expr(&node.children[1]);
+            },
+            SUBSCRIPT | SLICE => {
+                self.operand(&node.children[0], prec);
+                self.buf.push('[');
+                self.expr(&node.children[1]);
+                self.buf.push(']');
+            },
+            IDENT => {
+                self.buf.push_str(node.string());
+                // The target of an annotated assignment.
+                if node.children.len() > 0 {
+                    self.annotation(&node.children[0]);
+                }
+            },
+            // Written as nothing, leaving 'a[..n]'.
+            OPEN_BOUND => (),
+            MEMBER => {
+                self.operand(&node.children[0], prec);
+                self.buf.push('.');
+                self.buf.push_str(node.children[1].string());
+            },
+            ARRAY_DECL => {
+                self.buf.push('[');
+                self.list(&node.children);
+                self.buf.push(']');
+            },
+            ARRAY_COMP => {
+                self.buf.push('[');
+                self.expr(&node.children[0]);
+                self.comprehension(&node.children[1..]);
+                self.buf.push(']');
+            },
+            HASH_COMP => {
+                self.buf.push_str("{ ");
+                self.expr(&node.children[0]);
+                self.buf.push_str(" => ");
+                self.expr(&node.children[1]);
+                self.comprehension(&node.children[2..]);
+                self.buf.push_str(" }");
+            },
+            HASH_DECL => {
+                self.buf.push('{');
+                for (i, elem) in node.children.iter().enumerate() {
+                    self.buf.push_str(if i > 0 { ", " } else { " " });
+                    self.expr(&elem.children[0]);
+                    self.buf.push_str(" => ");
+                    self.expr(&elem.children[1]);
+                }
+                self.buf.push_str(if node.children.len() > 0 { " }" }
+                                  else { "}" });
+            },
+            SUB_LITERAL => self.subroutine(node),
+            DO_EXPR => {
+                self.buf.push_str("do ");
+                self.block(&node.children[0]);
+            },
+            RANGE => {
+                // Ranges do not chain, so every part is bracketed.
+                for (i, part) in node.children.iter().enumerate() {
+                    if i > 0 {
+                        self.buf.push_str("..");
+                    }
+                    self.operand(part, prec + 1);
+                }
+            },
+            CHAIN_CMP => {
+                self.operand(&node.children[0], prec + 1);
+                for op in &node.children[1..] {
+                    self.buf.push(' ');
+                    self.buf.push_str(op.string());
+                    self.buf.push(' ');
+                    self.operand(&op.children[0], prec + 1);
+                }
+            },
+            BLOCK => self.block(node),
+            _ => {
+                if node.children.len() == 2 {
+                    /*
+                     * Binary operators associate to the left,
+                     * except comparisons which chain instead.
+                     */
+                    let left = match node.get_type() {
+                        LT | LE | GT | GE => prec + 1,
+                        _ => prec,
+                    };
+                    self.operand(&node.children[0], left);
+                    self.buf.push(' ');
+                    if node.get_type() == IN_TEST {
+                        self.buf.push_str("in");
+                    }
+                    else {
+                        self.buf.push_str(node.string());
+                    }
+                    self.buf.push(' ');
+                    self.operand(&node.children[1], prec + 1);
+                }
+                else {
+                    self.buf.push_str(node.string());
+                }
+            },
+        }
+    }
+
+    /*
+     * Token text of a string is the unescaped contents, so
+     * it is quoted and escaped again here.
+     */
+    fn string(&mut self, node: &Node)
+    {
+        let text = match node.get_value() {
+            StringValue(s) => s,
+            _ => node.string().to_string(),
+        };
+        self.buf.push('"');
+        for ch in text.chars() {
+            match ch {
+                '"'  => self.buf.push_str("\\\""),
+                '\\' => self.buf.push_str("\\\\"),
+                '\n' => self.buf.push_str("\\n"),
+                '\r' => self.buf.push_str("\\r"),
+                '\t' => self.buf.push_str("\\t"),
+                _ if (ch as u32) < 0x20 => {
+                    self.buf.push_str(format!("\\x{:02x}", ch as u32)
+                                      .as_str());
+                },
+                _ => self.buf.push(ch),
+            }
+        }
+        self.buf.push('"');
+    }
+}
@@ -0,0 +1,283 @@
+/*
+ * Test that the Visitor reaches every node of a tree,
+ * whatever its kind, and that very deep trees can be
+ * printed.
+ */
+use parser::*;
+use intermediate::*;
+
+struct DepthCounter {
+    depth: usize,
+    max_depth: usize,
+    nodes: usize,
+}
+
+impl Visitor for DepthCounter
+{
+    fn walk_children(&mut self, node: &Node)
+    {
+        self.depth += 1;
+        self.nodes += 1;
+        if self.depth > self.max_depth {
+            self.max_depth = self.depth;
+        }
+        for child in &node.children {
+            self.walk(child);
+        }
+        self.depth -= 1;
+    }
+}
+
+fn count_nodes(node: &Node) -> usize
+{
+    return 1 + node.children.iter().map(|c| count_nodes(c)).sum::<usize>();
+}
+
+pub struct VisitorMatcher;
+
+impl VisitorMatcher
+{
+    pub fn match_depth()
+    {
+        let tests = [("x = 1 + 2 * 3", 5),
+                     ("x", 2),
+                     ("def f(a) {\nif a {\nreturn g(a, [1, {2 => 3}])\n}\n}", 11),
+                     ("for i in 1..3 step 2 {\nswitch i {\ncase 1: debug i\n\
+                       default: while !i { i = do { -i } }\n}\n}", 12)];
+        println!("Starting match_depth() test..");
+        for (i, &(input, depth)) in tests.iter().enumerate() {
+            let program = match parse(input, "visitormatcher") {
+                Ok(program) => program,
+                Err(errors) => {
+                    println!("{}. unexpected errors({:?})", i, errors);
+                    continue;
+                },
+            };
+            let mut counter = DepthCounter {
+                depth: 0,
+                max_depth: 0,
+                nodes: 0,
+            };
+            counter.walk(&program);
+            if counter.max_depth != depth {
+                println!("{}. depth({}) != expected depth({})", i,
+                         counter.max_depth, depth);
+            }
+            if counter.nodes != count_nodes(&program) {
+                println!("{}. visited({}) != nodes in tree({})", i,
+                         counter.nodes, count_nodes(&program));
+            }
+        }
+        println!("Ending match_depth() test..");
+    }
+
+    pub fn match_all()
+    {
+        VisitorMatcher::match_depth();
+    }
+}
+
+pub struct NodeMatcher;
+
+impl NodeMatcher
+{
+    /*
+     * Renders a left leaning chain of 50,000 '+' nodes,
+     * ((1 + 1) + 1) + ..., which is far deeper than a
+     * recursive printer could go.
+     */
+    pub fn match_deep_tree()
+    {
+        let depth = 50000;
+        println!("Starting match_deep_tree() test..");
+        let mut root = leaf("1", INTEGER);
+        for _ in 0..depth {
+            let mut plus = leaf("+", PLUS);
+            plus.add_child(root);
+            plus.add_child(leaf("1", INTEGER));
+            root = plus;
+        }
+        let tree = root.to_string_tree();
+        let expected = format!("{}1{}", "(+ ".repeat(depth),
+                               " 1)".repeat(depth));
+        if tree != expected {
+            println!("deep tree rendered wrong, {} bytes != expected {}",
+                     tree.len(), expected.len());
+        }
+        // Dropping the chain would recurse just as deep.
+        while root.children.len() != 0 {
+            let next = root.children.remove(0);
+            root = next;
+        }
+        println!("Ending match_deep_tree() test..");
+    }
+
+    pub fn match_structurally_eq()
+    {
+        let tests = [("1+2", "1 +\n 2", true),
+                     ("f(a, [1])", "f(\na,\n[1]\n)", true),
+                     ("x = 1.5", "x =   1.50", false),
+                     ("1 + 2", "1 - 2", false),
+                     ("a = b", "a = b = c", false)];
+        println!("Starting match_structurally_eq() test..");
+        for (i, &(a, b, expected)) in tests.iter().enumerate() {
+            let (tree_a, tree_b) = match (parse(a, "nodematcher"),
+                                          parse(b, "nodematcher")) {
+                (Ok(tree_a), Ok(tree_b)) => (tree_a, tree_b),
+                _ => {
+                    println!("{}. unexpected parse errors", i);
+                    continue;
+                },
+            };
+            if tree_a.structurally_eq(&tree_b) != expected {
+                println!("{}. structurally_eq({}, {}) != {}", i, a, b,
+                         expected);
+            }
+            if expected && tree_a == tree_b {
+                println!("{}. {} and {} compared equal with positions",
+                         i, a, b);
+            }
+        }
+        println!("Ending match_structurally_eq() test..");
+    }
+
+    pub fn match_diff()
+    {
+        let tests = [("x = 1 + 2", "x = 1 - 2",
+                      vec!["at children[0].children[1]: type PLUS vs MINUS"]),
+                     ("f(a, b)", "f(a, c)",
+                      vec!["at children[0].children[2]: text 'b' vs 'c'"]),
+                     ("x = 1.5", "x = 2.5",
+                      vec!["at children[0].children[1]: text '1.5' vs '2.5'"]),
+                     ("[1, 2]", "[1, 2, 3]",
+                      vec!["at children[0]: 2 children vs 3"]),
+                     ("a = 1\nb = 2\nc = 3", "a = 0\nb = 2\nc = 4",
+                      vec!["at children[0].children[1]: text '1' vs '0'",
+                           "at children[2].children[1]: text '3' vs '4'"]),
+                     ("f(a,\n  b)", "f(a, b)", vec![])];
+        println!("Starting match_diff() test..");
+        for (i, &(a, b, ref expected)) in tests.iter().enumerate() {
+            let (tree_a, tree_b) = match (parse(a, "nodematcher"),
+                                          parse(b, "nodematcher")) {
+                (Ok(tree_a), Ok(tree_b)) => (tree_a, tree_b),
+                _ => {
+                    println!("{}. unexpected parse errors", i);
+                    continue;
+                },
+            };
+            let diffs: Vec<String> = tree_a.diff(&tree_b)
+                                           .iter()
+                                           .map(|d| d.to_string())
+                                           .collect();
+            if diffs != *expected {
+                println!("{}. diff({:?}) != expected diff({:?})", i, diffs,
+                         expected);
+            }
+        }
+
+        // Same text, told apart by value alone.
+        let (mut a, mut b) = (leaf("1", INTEGER), leaf("1", INTEGER));
+        a.token.value = Value::IntegerValue(1);
+        b.token.value = Value::IntegerValue(2);
+        let diffs = a.diff(&b);
+        if diffs.len() != 1 || diffs[0].path.len() != 0 ||
+           diffs[0].to_string() != "at root: value 1 vs 2" {
+            println!("diff({:?}) != expected diff at root", diffs);
+        }
+        println!("Ending match_diff() test..");
+    }
+
+    pub fn match_metrics()
+    {
+        println!("Starting match_metrics() test..");
+        // (BLOCK (= x (+ 1 (* 2 y))) (debug x))
+        let program = match parse("x = 1 + 2 * y\ndebug x", "nodematcher") {
+            Ok(program) => program,
+            Err(errors) => {
+                println!("unexpected errors({:?})", errors);
+                return;
+            },
+        };
+        let metrics = program.metrics();
+        if metrics.nodes != 10 || metrics.max_depth != 5 {
+            println!("nodes({}), max_depth({}) != expected nodes(10), \
+                      max_depth(5)", metrics.nodes, metrics.max_depth);
+        }
+        let counts = [(BLOCK, 1), (ASSIGN, 1), (PLUS, 1), (MUL, 1),
+                      (IDENT, 3), (INTEGER, 2), (DEBUG, 1), (CALL, 0)];
+        for &(token_type, expected) in counts.iter() {
+            if metrics.count(token_type) != expected {
+                println!("count({:?}) = {} != expected count({})",
+                         token_type, metrics.count(token_type), expected);
+            }
+        }
+        if metrics.counts.values().sum::<usize>() != metrics.nodes {
+            println!("counts({:?}) do not add up to nodes({})",
+                     metrics.counts, metrics.nodes);
+        }
+
+        let mut root = leaf("1", INTEGER);
+        for _ in 0..100000 {
+            let mut negate = leaf("-", NEGATE);
+            negate.add_child(root);
+            root = negate;
+        }
+        let metrics = root.metrics();
+        if metrics.nodes != 100001 || metrics.max_depth != 100001 {
+            println!("nodes({}), max_depth({}) != expected 100001",
+                     metrics.nodes, metrics.max_depth);
+        }
+        // Dropping the chain would recurse just as deep.
+        while root.children.len() != 0 {
+            let next = root.children.remove(0);
+            root = next;
+        }
+        println!("Ending match_metrics() test..");
+    }
+
+    #[cfg(feature = "serde")]
+    pub fn match_serde()
+    {
+        let input = "def f(a, b = 2.5, *c) {\nreturn [a, {'k' => b}]\n}\n\
+                     s = /a+/i\nswitch f(1) {\ncase 1..3: debug nil\n}";
+        println!("Starting match_serde() test..");
+        let program = match parse(input, "nodematcher") {
+            Ok(program) => program,
+            Err(errors) => {
+                println!("unexpected errors({:?})", errors);
+                return;
+            },
+        };
+        let json = match ::serde_json::to_string(&program) {
+            Ok(json) => json,
+            Err(error) => {
+                println!("cannot serialize tree: {}", error);
+                return;
+            },
+        };
+        match ::serde_json::from_str::<Box<Node>>(&json) {
+            Ok(tree) => {
+                if !tree.structurally_eq(&program) || tree != program {
+                    println!("tree read back differs from the tree written");
+                }
+            },
+            Err(error) => println!("cannot deserialize tree: {}", error),
+        }
+        println!("Ending match_serde() test..");
+    }
+
+    pub fn match_all()
+    {
+        NodeMatcher::match_deep_tree();
+        NodeMatcher::match_structurally_eq();
+        NodeMatcher::match_metrics();
+        NodeMatcher::match_diff();
+        #[cfg(feature = "serde")]
+        NodeMatcher::match_serde();
+    }
+}
+
+fn leaf(text: &str, token_type: TokenType) -> Box<Node>
+{
+    return Node::new(Token::new_imag(text.to_string(), token_type, 1, 1, 0));
+}
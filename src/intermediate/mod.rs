@@ -5,14 +5,72 @@
  * if they are tendersome to interpret instead of a
  * subtree.
  */
+use std::collections::HashMap;
+use std::fmt;
 use token::*;
+use token::TokenType::*;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+pub mod intermediate_test;
 
 #[derive(Clone, PartialEq, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Node {
     pub token: Token,
     pub children: Vec<Box<Node>>,
 }
 
+/*
+ * Size of a tree, see Node::metrics. The root is at
+ * depth 1, a lone node has a max_depth of 1.
+ */
+#[derive(Clone, Debug, PartialEq)]
+pub struct NodeMetrics {
+    pub nodes: usize,
+    pub max_depth: usize,
+    pub counts: HashMap<TokenType, usize>,
+}
+
+impl NodeMetrics
+{
+    // Number of nodes of the given type.
+    pub fn count(&self, token_type: TokenType) -> usize
+    {
+        return *self.counts.get(&token_type).unwrap_or(&0);
+    }
+}
+
+/*
+ * Where two trees part, see Node::diff. The path holds the
+ * child indices leading from the root to the differing node.
+ */
+#[derive(Clone, Debug, PartialEq)]
+pub struct NodeDiff {
+    pub path: Vec<usize>,
+    pub message: String,
+}
+
+impl fmt::Display for NodeDiff
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+    {
+        if self.path.len() == 0 {
+            return write!(f, "at root: {}", self.message);
+        }
+        let steps: Vec<String> = self.path.iter()
+                                          .map(|i| format!("children[{}]", i))
+                                          .collect();
+        return write!(f, "at {}: {}", steps.join("."), self.message);
+    }
+}
+
+enum TreeStep<'a> {
+    Visit(&'a Node),
+    Space,
+    Close,
+}
+
 impl Node
 {
     pub fn new(token: Token) -> Box<Node>
@@ -36,9 +94,9 @@ impl Node
         return node;
     }
 
-    pub fn string(&self) -> String
+    pub fn string(&self) -> &str
     {
-        return self.token.text.clone();
+        return self.token.string();
     }
 
     pub fn get_type(&self) -> TokenType
@@ -51,24 +109,244 @@ impl Node
         return self.token.value.clone();
     }
 
+    // The first child of the given type, for optional parts.
+    pub fn child(&self, token_type: TokenType) -> Option<&Node>
+    {
+        return self.children.iter()
+                            .find(|c| c.get_type() == token_type)
+                            .map(|c| &**c);
+    }
+
+    /*
+     * Compares two trees by shape, token types, text and
+     * values only. Positions are ignored, so the same program
+     * formatted two ways gives structurally equal trees even
+     * though they are not equal under PartialEq.
+     */
+    pub fn structurally_eq(&self, other: &Node) -> bool
+    {
+        let mut stack: Vec<(&Node, &Node)> = vec![(self, other)];
+
+        while let Some((a, b)) = stack.pop() {
+            if a.token.token_type != b.token.token_type ||
+               a.token.text != b.token.text ||
+               a.token.value != b.token.value ||
+               a.children.len() != b.children.len() {
+                return false;
+            }
+            for (x, y) in a.children.iter().zip(b.children.iter()) {
+                stack.push((x, y));
+            }
+        }
+        return true;
+    }
+
+    /*
+     * Lists where the trees differ, by the same measure as
+     * structurally_eq, in the order the nodes appear. Below
+     * a node which differs nothing more is compared, so a
+     * single changed operator gives a single entry, as in
+     * 'at children[0].children[1]: type PLUS vs MINUS'.
+     */
+    pub fn diff(&self, other: &Node) -> Vec<NodeDiff>
+    {
+        let mut diffs: Vec<NodeDiff> = Vec::new();
+        let mut stack: Vec<(&Node, &Node, Vec<usize>)> =
+            vec![(self, other, Vec::new())];
+
+        while let Some((a, b, path)) = stack.pop() {
+            let message = if a.get_type() != b.get_type() {
+                format!("type {:?} vs {:?}", a.get_type(), b.get_type())
+            }
+            else if a.token.text != b.token.text {
+                format!("text '{}' vs '{}'", a.string(), b.string())
+            }
+            else if a.token.value != b.token.value {
+                format!("value {:?} vs {:?}", a.token.value, b.token.value)
+            }
+            else if a.children.len() != b.children.len() {
+                format!("{} children vs {}", a.children.len(),
+                        b.children.len())
+            }
+            else {
+                // Reversed, so the first child comes off the stack first.
+                for (i, (x, y)) in a.children.iter()
+                                    .zip(b.children.iter())
+                                    .enumerate()
+                                    .rev() {
+                    let mut child_path = path.clone();
+                    child_path.push(i);
+                    stack.push((x, y, child_path));
+                }
+                continue;
+            };
+            diffs.push(NodeDiff {
+                path: path,
+                message: message,
+            });
+        }
+        return diffs;
+    }
+
+    /*
+     * Counts the nodes of the tree, in total and by type,
+     * and finds its depth. Like to_string_tree it keeps
+     * its own stack, so it works on trees of any depth.
+     */
+    pub fn metrics(&self) -> NodeMetrics
+    {
+        let mut metrics = NodeMetrics {
+            nodes: 0,
+            max_depth: 0,
+            counts: HashMap::new(),
+        };
+        let mut stack: Vec<(&Node, usize)> = vec![(self, 1)];
+
+        while let Some((node, depth)) = stack.pop() {
+            metrics.nodes += 1;
+            if depth > metrics.max_depth {
+                metrics.max_depth = depth;
+            }
+            *metrics.counts.entry(node.get_type()).or_insert(0) += 1;
+            for child in &node.children {
+                stack.push((child, depth + 1));
+            }
+        }
+        return metrics;
+    }
+
+    /*
+     * Renders the tree as an S-expression, '(+ 1 2)'. Uses
+     * an explicit stack of pending steps rather than
+     * recursion, so no tree is too deep to print.
+     */
     pub fn to_string_tree(&mut self) -> String
     {
-        if self.children.len() != 0 {
-            let mut buf = String::new();
-            buf.push('(');
-            buf.push_str((self.string() + " ").as_str());
-            
-            let mut i = 0;
-            while i < self.children.len() {
-                if i > 0 {
+        let mut buf = String::new();
+        let mut stack: Vec<TreeStep> = vec![TreeStep::Visit(self)];
+
+        while let Some(step) = stack.pop() {
+            match step {
+                TreeStep::Visit(node) => {
+                    if node.children.len() == 0 {
+                        buf.push_str(node.string());
+                        continue;
+                    }
+                    buf.push('(');
+                    buf.push_str(node.string());
                     buf.push(' ');
-                }
-                buf.push_str(self.children[i].to_string_tree().as_str());
-                i += 1;
+
+                    stack.push(TreeStep::Close);
+                    let mut i = node.children.len();
+                    while i > 0 {
+                        i -= 1;
+                        stack.push(TreeStep::Visit(&node.children[i]));
+                        if i > 0 {
+                            stack.push(TreeStep::Space);
+                        }
+                    }
+                },
+                TreeStep::Space => buf.push(' '),
+                TreeStep::Close => buf.push(')'),
             }
-            buf.push(')');
-            return buf;
         }
-        return self.string();
+        return buf;
+    }
+}
+
+/*
+ * Walks a tree without every pass reaching into the
+ * children itself. walk dispatches on the node type to
+ * a visit method, and each of those recurses into the
+ * children through walk_children unless overridden, so
+ * an implementor only writes the methods it cares about.
+ */
+pub trait Visitor
+{
+    fn walk(&mut self, node: &Node)
+    {
+        match node.get_type() {
+            BLOCK => self.visit_block(node),
+            IF    => self.visit_if(node),
+            WHILE | UNTIL => self.visit_loop(node),
+            FOR    => self.visit_for(node),
+            SWITCH => self.visit_switch(node),
+            SUB_DECL | SUB_LITERAL => self.visit_subroutine(node),
+            CALL   => self.visit_call(node),
+            ASSIGN => self.visit_assign(node),
+            RETURN => self.visit_return(node),
+            IDENT  => self.visit_ident(node),
+            STRING | INTEGER | FLOAT | TRUE | FALSE | NIL |
+            REGEX  => self.visit_literal(node),
+            _      => self.visit_other(node),
+        }
+    }
+
+    fn walk_children(&mut self, node: &Node)
+    {
+        for child in &node.children {
+            self.walk(child);
+        }
+    }
+
+    fn visit_block(&mut self, node: &Node)
+    {
+        self.walk_children(node);
+    }
+
+    fn visit_if(&mut self, node: &Node)
+    {
+        self.walk_children(node);
+    }
+
+    fn visit_loop(&mut self, node: &Node)
+    {
+        self.walk_children(node);
+    }
+
+    fn visit_for(&mut self, node: &Node)
+    {
+        self.walk_children(node);
     }
-}
\ No newline at end of file
+
+    fn visit_switch(&mut self, node: &Node)
+    {
+        self.walk_children(node);
+    }
+
+    fn visit_subroutine(&mut self, node: &Node)
+    {
+        self.walk_children(node);
+    }
+
+    fn visit_call(&mut self, node: &Node)
+    {
+        self.walk_children(node);
+    }
+
+    fn visit_assign(&mut self, node: &Node)
+    {
+        self.walk_children(node);
+    }
+
+    fn visit_return(&mut self, node: &Node)
+    {
+        self.walk_children(node);
+    }
+
+    fn visit_ident(&mut self, node: &Node)
+    {
+        self.walk_children(node);
+    }
+
+    fn visit_literal(&mut self, node: &Node)
+    {
+        self.walk_children(node);
+    }
+
+    // Operators and every other imaginary node.
+    fn visit_other(&mut self, node: &Node)
+    {
+        self.walk_children(node);
+    }
+}
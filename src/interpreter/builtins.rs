@@ -0,0 +1,166 @@
+/*
+ * Subroutines implemented in Rust. A call whose callee
+ * names one of these runs it directly, ahead of looking
+ * for a subroutine of that name in the program. Adding a
+ * builtin takes an entry in BUILTINS and an arm in call.
+ */
+use std::io::Write;
+use token::*;
+use token::Value::*;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Builtin {
+    Len,
+    Type,
+    Print,
+    Str,
+    Int,
+    Float,
+    IsA,
+    IsString,
+    IsInt,
+    IsFloat,
+    IsBool,
+    IsRegex,
+    IsArray,
+    IsNil,
+}
+
+// The name and the number of arguments, None takes any number.
+const BUILTINS: &'static [(&'static str, Builtin, Option<usize>)] = &[
+    ("len",   Builtin::Len,   Some(1)),
+    ("type",  Builtin::Type,  Some(1)),
+    ("print", Builtin::Print, None),
+    ("str",   Builtin::Str,   Some(1)),
+    ("int",   Builtin::Int,   Some(1)),
+    ("float", Builtin::Float, Some(1)),
+    ("is_a",      Builtin::IsA,      Some(2)),
+    ("is_string", Builtin::IsString, Some(1)),
+    ("is_int",    Builtin::IsInt,    Some(1)),
+    ("is_float",  Builtin::IsFloat,  Some(1)),
+    ("is_bool",   Builtin::IsBool,   Some(1)),
+    ("is_regex",  Builtin::IsRegex,  Some(1)),
+    ("is_array",  Builtin::IsArray,  Some(1)),
+    ("is_nil",    Builtin::IsNil,    Some(1)),
+];
+
+impl Builtin
+{
+    pub fn lookup(name: &str) -> Option<Builtin>
+    {
+        return BUILTINS.iter()
+                       .find(|&&(n, _, _)| n == name)
+                       .map(|&(_, builtin, _)| builtin);
+    }
+
+    pub fn name(&self) -> &'static str
+    {
+        return BUILTINS.iter()
+                       .find(|&&(_, b, _)| b == *self)
+                       .map(|&(n, _, _)| n)
+                       .unwrap();
+    }
+
+    fn arity(&self) -> Option<usize>
+    {
+        return BUILTINS.iter()
+                       .find(|&&(_, b, _)| b == *self)
+                       .and_then(|&(_, _, arity)| arity);
+    }
+
+    /*
+     * Errors come back as a bare message, the interpreter
+     * places it at the call. Output goes to out.
+     */
+    pub fn call(&self, args: Vec<Value>, out: &mut dyn Write)
+        -> Result<Value, String>
+    {
+        if let Some(arity) = self.arity() {
+            if args.len() != arity {
+                return Err(format!("{}() takes {} argument(s), got {}",
+                                   self.name(), arity, args.len()));
+            }
+        }
+        return match *self {
+            Builtin::Len => {
+                match args[0] {
+                    StringValue(ref s) => Ok(IntegerValue(s.chars().count()
+                                                          as i64)),
+                    ArrayValue(ref a) => Ok(IntegerValue(a.len() as i64)),
+                    ref value => Err(format!("{} has no length",
+                                             value.type_name())),
+                }
+            },
+            Builtin::Type => Ok(StringValue(args[0].type_name().to_string())),
+            Builtin::Print => {
+                let texts: Vec<String> = args.iter()
+                                             .map(|a| format!("{}", a))
+                                             .collect();
+                match writeln!(out, "{}", texts.join(" ")) {
+                    Ok(()) => Ok(NilValue),
+                    Err(error) => Err(format!("cannot write output: {}",
+                                              error)),
+                }
+            },
+            Builtin::Str => Ok(StringValue(format!("{}", args[0]))),
+            Builtin::Int => to_int(&args[0]),
+            Builtin::Float => to_float(&args[0]),
+            // The names are those type() returns.
+            Builtin::IsA => {
+                match args[1] {
+                    StringValue(ref name) => Ok(BoolValue(args[0].is_a(name))),
+                    ref value => Err(format!("is_a() expects a type name, \
+                                              got {}", value.type_name())),
+                }
+            },
+            Builtin::IsString => Ok(BoolValue(args[0].is_string())),
+            Builtin::IsInt => Ok(BoolValue(args[0].is_int())),
+            Builtin::IsFloat => Ok(BoolValue(args[0].is_float())),
+            Builtin::IsBool => Ok(BoolValue(args[0].is_bool())),
+            Builtin::IsRegex => Ok(BoolValue(args[0].is_regex())),
+            Builtin::IsArray => Ok(BoolValue(args[0].is_array())),
+            Builtin::IsNil => Ok(BoolValue(args[0].is_nil())),
+        }
+    }
+}
+
+fn to_int(value: &Value) -> Result<Value, String>
+{
+    return match *value {
+        IntegerValue(i) => Ok(IntegerValue(i)),
+        // Truncates towards zero, as long as the result fits.
+        FloatValue(f) => {
+            if f.is_finite() && f.trunc() >= i64::min_value() as f64 &&
+               f.trunc() < i64::max_value() as f64 {
+                Ok(IntegerValue(f.trunc() as i64))
+            }
+            else {
+                Err(format!("cannot convert {} to int", f))
+            }
+        },
+        BoolValue(b) => Ok(IntegerValue(b as i64)),
+        StringValue(ref s) => {
+            match s.trim().parse::<i64>() {
+                Ok(i) => Ok(IntegerValue(i)),
+                Err(_) => Err(format!("invalid int {:?}", s)),
+            }
+        },
+        _ => Err(format!("cannot convert {} to int", value.type_name())),
+    }
+}
+
+fn to_float(value: &Value) -> Result<Value, String>
+{
+    return match *value {
+        IntegerValue(i) => Ok(FloatValue(i as f64)),
+        FloatValue(f) => Ok(FloatValue(f)),
+        BoolValue(b) => Ok(FloatValue(b as i64 as f64)),
+        StringValue(ref s) => {
+            match s.trim().parse::<f64>() {
+                Ok(f) => Ok(FloatValue(f)),
+                Err(_) => Err(format!("invalid float {:?}", s)),
+            }
+        },
+        _ => Err(format!("cannot convert {} to float", value.type_name())),
+    }
+}
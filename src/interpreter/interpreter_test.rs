@@ -0,0 +1,399 @@
+/*
+ * Test that the interpreter evaluates programs to the
+ * expected values.
+ */
+use std::cell::RefCell;
+use std::io;
+use std::io::Write;
+use std::rc::Rc;
+use parser::*;
+use interpreter::*;
+
+macro_rules! create_tests {
+    ($($input:expr, $value:expr),+) => (
+        {
+            let tests = [
+            $(
+                EvalMatcher::new($input, $value),
+            )+
+            ];
+            tests
+        }
+    );
+}
+
+/*
+ * Collects what the interpreter writes, the test keeps
+ * one handle and gives the interpreter the other.
+ */
+struct SharedOutput(Rc<RefCell<Vec<u8>>>);
+
+impl Write for SharedOutput
+{
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize>
+    {
+        return self.0.borrow_mut().write(buf);
+    }
+
+    fn flush(&mut self) -> io::Result<()>
+    {
+        return Ok(());
+    }
+}
+
+pub struct EvalMatcher {
+    input: &'static str,
+    expected_value: Value,
+}
+
+impl EvalMatcher
+{
+    fn new(input: &'static str, expected_value: Value) -> EvalMatcher
+    {
+        return EvalMatcher {
+            input: input,
+            expected_value: expected_value,
+        };
+    }
+
+    pub fn match_arithmetic()
+    {
+        let tests = create_tests!(
+            "1 + 2 * 3", IntegerValue(7),
+            "(1 + 2) * 3", IntegerValue(9),
+            "7 / 2", FloatValue(3.5),
+            "7 // 2", IntegerValue(3),
+            "-7 // 2 * 2", IntegerValue(-8),
+            "7 % 3", IntegerValue(1),
+            "1 + 0.5", FloatValue(1.5),
+            "-(2 - 5)", IntegerValue(3),
+            "+5 - -+2.5", FloatValue(7.5),
+            "~0", IntegerValue(-1),
+            "1 << 3 | 1", IntegerValue(9));
+        println!("Starting match_arithmetic() test..");
+        EvalMatcher::__match(&tests);
+        println!("Ending match_arithmetic() test..");
+    }
+
+    pub fn match_logic()
+    {
+        let tests = create_tests!(
+            "1 < 2 && 2 <= 2", BoolValue(true),
+            "'a' > 'b' || 1 == 1.0", BoolValue(true),
+            "!nil", BoolValue(true),
+            "nil || 5", IntegerValue(5),
+            "0 && 'zero is true'", StringValue("zero is true".to_string()),
+            "1 != 1 ? 'a' : 'b'", StringValue("b".to_string()),
+            "5 in (1..10) && !(0 in (1..10))", BoolValue(true),
+            "'ell' in 'hello'", BoolValue(true),
+            "5 in (1..9..2) && !(4 in (1..9..2))", BoolValue(true),
+            "9223372036854775807 in (-9223372036854775807 - 1..\
+             9223372036854775807)", BoolValue(true),
+            "9223372036854775807 in (-9223372036854775807 - 1..\
+             9223372036854775807..2)", BoolValue(false),
+            "9007199254740993 == 9007199254740992", BoolValue(false),
+            "9007199254740993 != 9007199254740992", BoolValue(true),
+            "9007199254740992 < 9007199254740993", BoolValue(true),
+            "9007199254740993 <= 9007199254740992", BoolValue(false),
+            "9007199254740993 > 9007199254740992 > 9007199254740991",
+            BoolValue(true),
+            "9007199254740993 == 9007199254740992.0", BoolValue(true),
+            "n = 0\nx = false && (n = n + 1)\nn", IntegerValue(0),
+            "n = 0\nx = 1 || (n = n + 1)\nn", IntegerValue(0),
+            "n = 0\nx = nil || (n = n + 1)\nn", IntegerValue(1),
+            "n = 0\nx = 0 & (n = n + 1)\nn", IntegerValue(1));
+        println!("Starting match_logic() test..");
+        EvalMatcher::__match(&tests);
+        println!("Ending match_logic() test..");
+    }
+
+    pub fn match_chained_comparison()
+    {
+        let tests = create_tests!(
+            "1 < 2 < 3", BoolValue(true),
+            "3 > 2 > 2", BoolValue(false),
+            "1 < 2 <= 2 < 3.5", BoolValue(true),
+            "1 < 3 < 2", BoolValue(false),
+            "n = 0\nx = 0 < (n = n + 1) < 2\nn", IntegerValue(1),
+            "n = 0\nx = 2 < 1 < (n = 1)\nn", IntegerValue(0),
+            "(1 < 2) == (2 < 3)", BoolValue(true));
+        println!("Starting match_chained_comparison() test..");
+        EvalMatcher::__match(&tests);
+        println!("Ending match_chained_comparison() test..");
+    }
+
+    pub fn match_statements()
+    {
+        let tests = create_tests!(
+            "x = 1\nx = x + 1\nx", IntegerValue(2),
+            "s = 'ab' + 'c'\ns * 2", StringValue("abcabc".to_string()),
+            "const N = 4\nN * 2", IntegerValue(8),
+            "a = b = 3\na + b", IntegerValue(6),
+            "x = 5\nif x < 3 {\n'low'\n} elif x < 10 {\n'mid'\n} \
+             else {\n'high'\n}", StringValue("mid".to_string()),
+            "if false {\n1\n}", NilValue,
+            "i = 0\nwhile i < 10 {\ni = i + 1\n}\ni", IntegerValue(10),
+            "i = 10\nuntil i == 0 {\ni = i - 2\n}\ni", IntegerValue(0),
+            "sum = 0\nfor i in 1..4 {\nsum = sum + i\n}\nsum", IntegerValue(10),
+            "switch 7 {\ncase 1, 2: 'small'\ncase 3..9: 'medium'\n\
+             default: 'large'\n}", StringValue("medium".to_string()),
+            "switch 'z' {\ncase 'a': 1\ndefault: 2\n}", IntegerValue(2),
+            "x = do {\n1 + 1\n}", IntegerValue(2),
+            "sum = 0\nfor i in 1..9 step 4 {\nsum = sum + i\n}\nsum",
+            IntegerValue(15),
+            "sum = 0\nfor i in 1..9..4 {\nsum = sum + i\n}\nsum",
+            IntegerValue(15),
+            "switch 4 {\ncase 1..9..2: 'odd'\ndefault: 'even'\n}",
+            StringValue("even".to_string()),
+            "step = 3\nsum = 0\nfor i in 0..step step step {\n\
+             sum = sum + i\n}\nsum + step", IntegerValue(6),
+            "n = 0\nwhile n < 3 {\nn = n + 1\n} else {\nn = n * 10\n}\nn",
+            IntegerValue(30),
+            "n = 0\nfor i in 1..5 step 2 {\nn = n + i\n} else {\nn = -n\n}\nn",
+            IntegerValue(-9));
+        println!("Starting match_statements() test..");
+        EvalMatcher::__match(&tests);
+        println!("Ending match_statements() test..");
+    }
+
+    pub fn match_multi_assign()
+    {
+        let tests = create_tests!(
+            "a, b = 1, 2\na * 10 + b", IntegerValue(12),
+            "a, b = 1, 2\na, b = b, a\na * 10 + b", IntegerValue(21),
+            "a, b, c = 1, 2, 3\na, b, c = c, a, b\na * 100 + b * 10 + c",
+            IntegerValue(312));
+        println!("Starting match_multi_assign() test..");
+        EvalMatcher::__match(&tests);
+        println!("Ending match_multi_assign() test..");
+    }
+
+    pub fn match_compound_assign()
+    {
+        let tests = create_tests!(
+            "x = 4\nx |= 1", IntegerValue(5),
+            "x = 6\nx &= 3\nx", IntegerValue(2),
+            "x = 5\nx ^= 3\nx", IntegerValue(6),
+            "x = 1\nx <<= 4\nx", IntegerValue(16),
+            "x = -16\nx >>= 2\nx", IntegerValue(-4),
+            "x = 1\nx += 2\nx *= 3\nx -= 1\nx", IntegerValue(8),
+            "x = 7\nx /= 2\nx", FloatValue(3.5),
+            "x = 7\nx %= 4\nx", IntegerValue(3),
+            "s = 'a'\ns += 'b'\ns", StringValue("ab".to_string()),
+            "x = 1\nif true {\nx <<= 1\n}\nx", IntegerValue(2));
+        println!("Starting match_compound_assign() test..");
+        EvalMatcher::__match(&tests);
+        println!("Ending match_compound_assign() test..");
+    }
+
+    pub fn match_scoping()
+    {
+        let tests = create_tests!(
+            "x = 1\nif true {\nx = 2\n}\nx", IntegerValue(2),
+            "x = 1\nif true {\ny = x + 1\nx = y\n}\nx", IntegerValue(2),
+            "for i in 1..2 {\n}\ni = 0", IntegerValue(0),
+            "n = 0\nwhile n < 3 {\nt = n\nn = t + 1\n}\nn", IntegerValue(3));
+        println!("Starting match_scoping() test..");
+        EvalMatcher::__match(&tests);
+
+        let tests = ["if true {\ny = 1\n}\ny", "for i in 1..2 {\n}\ni"];
+        for (i, input) in tests.iter().enumerate() {
+            let program = parse(input, "evalmatcher").unwrap();
+            match Interpreter::new().run(&program) {
+                Ok(value) => println!("{}. value({:?}) leaked out of its \
+                                       block", i, value),
+                Err(error) => {
+                    if !error.message.starts_with("undefined variable") {
+                        println!("{}. unexpected error({})", i, error);
+                    }
+                },
+            }
+        }
+        println!("Ending match_scoping() test..");
+    }
+
+    pub fn match_builtins()
+    {
+        let tests = create_tests!(
+            "len([1, 2, 3])", IntegerValue(3),
+            "len('hello')", IntegerValue(5),
+            "len('h\u{e9}llo')", IntegerValue(5),
+            "len([])", IntegerValue(0),
+            "type(1.5)", StringValue("float".to_string()),
+            "type([1])", StringValue("array".to_string()),
+            "type(/ab+/i)", StringValue("regex".to_string()),
+            "str(/a.c/m)", StringValue("/a.c/m".to_string()),
+            "str([1, 'a'])", StringValue("[1, \"a\"]".to_string()),
+            "int('42') + int(-2.7)", IntegerValue(40),
+            "float(1) + float(' 0.5 ')", FloatValue(1.5));
+        println!("Starting match_builtins() test..");
+        EvalMatcher::__match(&tests);
+        println!("Ending match_builtins() test..");
+    }
+
+    /*
+     * Every predicate against a value of every kind, each
+     * holds for its own kind alone. is_a takes the names
+     * type() gives.
+     */
+    pub fn match_type_predicates()
+    {
+        let values = ["'s'", "1", "1.5", "true", "/a+/", "[1]", "nil"];
+        let names = ["string", "int", "float", "bool", "regex", "array",
+                     "nil"];
+        println!("Starting match_type_predicates() test..");
+        for (i, value) in values.iter().enumerate() {
+            for (j, name) in names.iter().enumerate() {
+                let calls = [format!("is_{}({})", name, value),
+                             format!("is_a({}, '{}')", value, name),
+                             format!("is_a({}, type({}))", value, value)];
+                let expected = [i == j, i == j, true];
+                for (call, &expected) in calls.iter().zip(expected.iter()) {
+                    let program = match parse(call.as_str(), "evalmatcher") {
+                        Ok(program) => program,
+                        Err(errors) => {
+                            println!("{} unexpected errors({:?})", call,
+                                     errors);
+                            continue;
+                        },
+                    };
+                    match Interpreter::new().run(&program) {
+                        Ok(BoolValue(b)) if b == expected => (),
+                        Ok(value) => println!("{} value({:?}) != expected \
+                                               value({})", call, value,
+                                              expected),
+                        Err(error) => println!("{} unexpected error({})",
+                                               call, error),
+                    }
+                }
+            }
+        }
+        println!("Ending match_type_predicates() test..");
+    }
+
+    pub fn match_output()
+    {
+        let tests = [("debug 1 + 2", "3\n"),
+                     ("debug [1, 'a']\ndebug nil\ndebug 1.0",
+                      "[1, \"a\"]\nnil\n1.0\n"),
+                     ("print('a', 1, [2])\nprint()", "a 1 [2]\n\n"),
+                     ("x = false && print('and')\nprint(1 || print('or'))",
+                      "1\n")];
+        println!("Starting match_output() test..");
+        for (i, &(input, expected)) in tests.iter().enumerate() {
+            let program = match parse(input, "evalmatcher") {
+                Ok(program) => program,
+                Err(errors) => {
+                    println!("{}. unexpected errors({:?})", i, errors);
+                    continue;
+                },
+            };
+            let output = Rc::new(RefCell::new(Vec::new()));
+            let mut interpreter = Interpreter::with_output(
+                Box::new(SharedOutput(output.clone())));
+            match interpreter.run(&program) {
+                Ok(NilValue) => (),
+                Ok(value) => println!("{}. value({:?}) != expected value(nil)",
+                                      i, value),
+                Err(error) => println!("{}. unexpected error({})", i, error),
+            }
+            let written = String::from_utf8(output.borrow().clone()).unwrap();
+            if written != expected {
+                println!("{}. output({:?}) != expected output({:?})", i,
+                         written, expected);
+            }
+        }
+        println!("Ending match_output() test..");
+    }
+
+    pub fn match_runtime_errors()
+    {
+        let tests = [("x = y", "undefined variable 'y'"),
+                     ("1 / 0", "division by zero"),
+                     ("1.5 % 0", "division by zero"),
+                     ("'a' - 1", "bad operand types for '-': string and int"),
+                     ("+'a'", "bad operand type for '+': string"),
+                     ("9223372036854775807 + 1", "integer overflow"),
+                     ("1 << 64", "invalid shift amount 64"),
+                     ("debug 'a' * 9223372036854775807",
+                      "repeated string is too long"),
+                     ("x |= 1", "undefined variable 'x'"),
+                     ("x = 1\nx <<= -1", "invalid shift amount -1"),
+                     ("for i in 'ab' {\ni\n}", "expected a range"),
+                     ("for i in 1..2 step 0 {\n}",
+                      "step must be a positive integer"),
+                     ("for i in 1..2..1 step 1 {\n}",
+                      "range already has a step"),
+                     ("a, b = 1, 2, 3", "cannot assign 3 values to 2 targets"),
+                     ("len(1)", "int has no length"),
+                     ("len(1, 2)", "len() takes 1 argument(s), got 2"),
+                     ("int('x')", "invalid int \"x\""),
+                     ("len(x = 1)", "len() takes no keyword arguments"),
+                     ("foo(1)", "can only call a builtin"),
+                     ("is_a(1, 2)", "is_a() expects a type name, got int"),
+                     ("is_nil()", "is_nil() takes 1 argument(s), got 0")];
+        println!("Starting match_runtime_errors() test..");
+        for (i, &(input, message)) in tests.iter().enumerate() {
+            let program = match parse(input, "evalmatcher") {
+                Ok(program) => program,
+                Err(errors) => {
+                    println!("{}. unexpected errors({:?})", i, errors);
+                    continue;
+                },
+            };
+            match Interpreter::new().run(&program) {
+                Ok(value) => println!("{}. value({:?}) != expected error({})",
+                                      i, value, message),
+                Err(error) => {
+                    if error.message != message {
+                        println!("{}. error({}) != expected error({})",
+                                 i, error, message);
+                    }
+                },
+            }
+        }
+        println!("Ending match_runtime_errors() test..");
+    }
+
+    pub fn match_all()
+    {
+        EvalMatcher::match_arithmetic();
+        EvalMatcher::match_logic();
+        EvalMatcher::match_chained_comparison();
+        EvalMatcher::match_statements();
+        EvalMatcher::match_multi_assign();
+        EvalMatcher::match_compound_assign();
+        EvalMatcher::match_scoping();
+        EvalMatcher::match_builtins();
+        EvalMatcher::match_type_predicates();
+        EvalMatcher::match_output();
+        EvalMatcher::match_runtime_errors();
+    }
+
+    fn __match(tests: &[EvalMatcher])
+    {
+        let mut i = 0;
+        for tt in tests {
+            let program = match parse(tt.input, "evalmatcher") {
+                Ok(program) => program,
+                Err(errors) => {
+                    for error in errors {
+                        println!("{}. {}", i, error);
+                    }
+                    i += 1;
+                    continue;
+                },
+            };
+            match Interpreter::new().run(&program) {
+                Ok(value) => {
+                    if value != tt.expected_value {
+                        println!("{}. value({:?}) != expected value({:?})", i,
+                                 value, tt.expected_value);
+                    }
+                },
+                Err(error) => println!("{}. {}", i, error),
+            }
+            i += 1;
+        }
+    }
+}
@@ -0,0 +1,652 @@
+/*
+ * A tree-walking interpreter, it evaluates the tree
+ * produced by the parser directly instead of compiling
+ * it any further. Every node evaluates to a value,
+ * statements which have no sensible value give nil.
+ */
+use std::cmp::Ordering;
+use std::fmt;
+use std::io;
+use std::io::Write;
+use token::*;
+use token::TokenType::*;
+use token::Value::*;
+use intermediate::*;
+use env::Environment;
+use self::builtins::Builtin;
+
+pub mod builtins;
+pub mod interpreter_test;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct RuntimeError {
+    pub line_num: i32,
+    pub line_pos: i32,
+    pub message: String,
+}
+
+impl fmt::Display for RuntimeError
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+    {
+        return write!(f, "{}:{}: {}", self.line_num, self.line_pos,
+                      self.message);
+    }
+}
+
+pub type EvalResult = Result<Value, RuntimeError>;
+
+pub struct Interpreter {
+    env: Environment,
+    // Where debug and print write to.
+    out: Box<dyn Write>,
+}
+
+impl Interpreter
+{
+    pub fn new() -> Interpreter
+    {
+        return Interpreter::with_output(Box::new(io::stdout()));
+    }
+
+    /*
+     * An interpreter writing its output to out instead of
+     * stdout, as when the output is to be captured.
+     */
+    pub fn with_output(out: Box<dyn Write>) -> Interpreter
+    {
+        return Interpreter {
+            env: Environment::new(),
+            out: out,
+        };
+    }
+
+    /*
+     * Evaluates a program, the result is the value of its
+     * last statement. Its statements run in the global frame,
+     * so running several programs in a row, as the repl
+     * does, shares their variables.
+     */
+    pub fn run(&mut self, program: &Node) -> EvalResult
+    {
+        return self.statements(program);
+    }
+
+    pub fn lookup(&self, name: &str) -> Option<&Value>
+    {
+        return self.env.get(name);
+    }
+
+    fn error(&self, node: &Node, message: String) -> RuntimeError
+    {
+        return RuntimeError {
+            line_num: node.token.line_num,
+            line_pos: node.token.line_pos,
+            message: message,
+        };
+    }
+
+    fn eval(&mut self, node: &Node) -> EvalResult
+    {
+        return match node.get_type() {
+            STRING | INTEGER | FLOAT | TRUE | FALSE | NIL |
+            REGEX   => Ok(node.get_value()),
+            IDENT   => self.variable(node),
+            BLOCK   => self.block(node),
+            DO_EXPR => self.eval(&node.children[0]),
+            ASSIGN  => self.assignment(node),
+            token_type if token_type.is_assignment() => {
+                self.compound_assignment(node)
+            },
+            MULTI_ASSIGN => self.multi_assignment(node),
+            CONST_DECL => {
+                let value = self.eval(&node.children[1])?;
+                self.env.define(node.children[0].string(), value.clone());
+                Ok(value)
+            },
+            IF      => self.if_statement(node),
+            WHILE | UNTIL => self.control_statement(node),
+            FOR     => self.for_statement(node),
+            SWITCH  => self.switch_statement(node),
+            CALL    => self.call(node),
+            ARRAY_DECL => {
+                let mut elements: Vec<Value> = Vec::new();
+                for child in &node.children {
+                    elements.push(self.eval(child)?);
+                }
+                Ok(ArrayValue(elements))
+            },
+            DEBUG   => self.debug_statement(node),
+            TERNARY => {
+                if truthy(&self.eval(&node.children[0])?) {
+                    self.eval(&node.children[1])
+                }
+                else {
+                    self.eval(&node.children[2])
+                }
+            },
+            LOGICAL_OR | LOGICAL_AND => self.logical(node),
+            BANG    => {
+                let value = self.eval(&node.children[0])?;
+                Ok(BoolValue(!truthy(&value)))
+            },
+            NEGATE | UNARY_PLUS | COMPL => self.unary(node),
+            EQL | NOT_EQL => {
+                let left = self.eval(&node.children[0])?;
+                let right = self.eval(&node.children[1])?;
+                let equal = values_equal(&left, &right);
+                Ok(BoolValue(if node.get_type() == EQL { equal }
+                             else { !equal }))
+            },
+            LT | LE | GT | GE => self.comparison(node),
+            CHAIN_CMP => self.chain_comparison(node),
+            IN_TEST => self.in_test(node),
+            PLUS | MINUS | MUL | DIV | FLOOR_DIV | MODULO => {
+                self.arithmetic(node)
+            },
+            BITWISE_OR | BITWISE_XOR | BITWISE_AND |
+            LEFT_SHIFT | RIGHT_SHIFT => self.bitwise(node),
+            _ => Err(self.error(node, format!("cannot evaluate '{}' yet",
+                                              node.string()))),
+        }
+    }
+
+    fn variable(&mut self, node: &Node) -> EvalResult
+    {
+        return match self.env.get(node.string()) {
+            Some(value) => Ok(value.clone()),
+            None => Err(self.error(node, format!("undefined variable '{}'",
+                                                 node.string()))),
+        }
+    }
+
+    /*
+     * Writes the value of the one child as Display shows it,
+     * the statement itself is nil.
+     */
+    fn debug_statement(&mut self, node: &Node) -> EvalResult
+    {
+        let value = self.eval(&node.children[0])?;
+        if let Err(error) = writeln!(self.out, "{}", value) {
+            return Err(self.error(node, format!("cannot write output: {}",
+                                                error)));
+        }
+        return Ok(NilValue);
+    }
+
+    /*
+     * Only builtins can be called so far, they take their
+     * arguments by position.
+     */
+    fn call(&mut self, node: &Node) -> EvalResult
+    {
+        let callee = &node.children[0];
+        let builtin = match callee.get_type() {
+            IDENT => Builtin::lookup(callee.string()),
+            _ => None,
+        };
+        let builtin = match builtin {
+            Some(builtin) => builtin,
+            None => return Err(self.error(callee, "can only call a \
+                                                   builtin".to_string())),
+        };
+        let mut args: Vec<Value> = Vec::new();
+        for arg in &node.children[1..] {
+            if arg.get_type() == KWARG {
+                return Err(self.error(arg, format!("{}() takes no keyword \
+                                                    arguments",
+                                                   builtin.name())));
+            }
+            args.push(self.eval(arg)?);
+        }
+        return builtin.call(args, &mut self.out)
+                      .map_err(|message| self.error(node, message));
+    }
+
+    /*
+     * Variables first assigned within a block are local to
+     * it, the frame is popped on the way out even when a
+     * statement failed.
+     */
+    fn block(&mut self, node: &Node) -> EvalResult
+    {
+        self.env.push_scope();
+        let value = self.statements(node);
+        self.env.pop_scope();
+
+        return value;
+    }
+
+    fn statements(&mut self, node: &Node) -> EvalResult
+    {
+        let mut value = NilValue;
+
+        for statement in &node.children {
+            value = self.eval(statement)?;
+        }
+        return Ok(value);
+    }
+
+    fn assignment(&mut self, node: &Node) -> EvalResult
+    {
+        let target = &node.children[0];
+        if target.get_type() != IDENT {
+            return Err(self.error(target, "can only assign to a \
+                                           variable".to_string()));
+        }
+        let value = self.eval(&node.children[1])?;
+        let name = target.string();
+        if !self.env.set(name, value.clone()) {
+            self.env.define(name, value.clone());
+        }
+
+        return Ok(value);
+    }
+
+    /*
+     * 'x op= y' is 'x = x op y', with x evaluated once. The
+     * variable has to exist already.
+     */
+    fn compound_assignment(&mut self, node: &Node) -> EvalResult
+    {
+        let target = &node.children[0];
+        if target.get_type() != IDENT {
+            return Err(self.error(target, "can only assign to a \
+                                           variable".to_string()));
+        }
+        let left = self.variable(target)?;
+        let right = self.eval(&node.children[1])?;
+
+        let result = match node.get_type() {
+            PLUS_ASSIGN   => left.add(&right),
+            MINUS_ASSIGN  => left.sub(&right),
+            MUL_ASSIGN    => left.mul(&right),
+            DIV_ASSIGN    => left.div(&right),
+            MODULO_ASSIGN => left.modulo(&right),
+            BITWISE_OR_ASSIGN  => left.bit_or(&right),
+            BITWISE_XOR_ASSIGN => left.bit_xor(&right),
+            BITWISE_AND_ASSIGN => left.bit_and(&right),
+            LEFT_SHIFT_ASSIGN  => left.shl(&right),
+            _                  => left.shr(&right),
+        };
+        let value = result.map_err(|message| self.error(node, message))?;
+        self.env.set(target.string(), value.clone());
+
+        return Ok(value);
+    }
+
+    /*
+     * Every value is evaluated before the first target is
+     * assigned, there have to be as many of them as targets.
+     */
+    fn multi_assignment(&mut self, node: &Node) -> EvalResult
+    {
+        let targets = &node.children[0].children;
+        let mut values: Vec<Value> = Vec::new();
+        for value in &node.children[1].children {
+            values.push(self.eval(value)?);
+        }
+        if values.len() != targets.len() {
+            return Err(self.error(node, format!("cannot assign {} values to \
+                                                 {} targets", values.len(),
+                                                targets.len())));
+        }
+        for (target, value) in targets.iter().zip(values.into_iter()) {
+            if target.get_type() != IDENT {
+                return Err(self.error(target, "can only assign to a \
+                                               variable".to_string()));
+            }
+            let name = target.string();
+            if !self.env.set(name, value.clone()) {
+                self.env.define(name, value);
+            }
+        }
+        return Ok(NilValue);
+    }
+
+    /*
+     * The children are the condition and block, then an ELIF
+     * node holding condition and block pairs and last an
+     * optional else block.
+     */
+    fn if_statement(&mut self, node: &Node) -> EvalResult
+    {
+        if truthy(&self.eval(&node.children[0])?) {
+            return self.eval(&node.children[1]);
+        }
+        let elif_root = &node.children[2];
+        let mut i = 0;
+        while i < elif_root.children.len() {
+            if truthy(&self.eval(&elif_root.children[i])?) {
+                return self.eval(&elif_root.children[i + 1]);
+            }
+            i += 2;
+        }
+        if node.children.len() > 3 {
+            return self.eval(&node.children[3]);
+        }
+        return Ok(NilValue);
+    }
+
+    fn control_statement(&mut self, node: &Node) -> EvalResult
+    {
+        let until = node.get_type() == UNTIL;
+
+        while truthy(&self.eval(&node.children[0])?) != until {
+            self.eval(&node.children[1])?;
+        }
+        return self.loop_else(node);
+    }
+
+    /*
+     * Only ranges can be iterated until there are runtime
+     * collections, 'for i in 1..3' runs with i as 1, 2 and 3.
+     * The loop variable lives in a frame of its own around
+     * the body. A step, either 'for i in 1..9 step 4' or
+     * 'for i in 1..9..4', counts 1, 5 and 9.
+     */
+    fn for_statement(&mut self, node: &Node) -> EvalResult
+    {
+        let name = node.children[0].string();
+        let (low, high, mut step) = self.range(&node.children[1])?;
+
+        if let Some(step_node) = node.child(STEP) {
+            if node.children[1].children.len() > 2 {
+                return Err(self.error(step_node, "range already has a \
+                                                  step".to_string()));
+            }
+            step = self.step(step_node, &step_node.children[0])?;
+        }
+        self.env.push_scope();
+        let mut i = low;
+        while i <= high {
+            self.env.define(name, IntegerValue(i));
+            if let Err(error) = self.eval(&node.children[2]) {
+                self.env.pop_scope();
+                return Err(error);
+            }
+            i = match i.checked_add(step) {
+                Some(i) => i,
+                None => break,
+            };
+        }
+        self.env.pop_scope();
+
+        return self.loop_else(node);
+    }
+
+    // Runs the else block of a loop which ran to its end.
+    fn loop_else(&mut self, node: &Node) -> EvalResult
+    {
+        if let Some(else_node) = node.child(ELSE) {
+            self.eval(&else_node.children[0])?;
+        }
+        return Ok(NilValue);
+    }
+
+    // Evaluates a RANGE node to its bounds and step.
+    fn range(&mut self, node: &Node)
+        -> Result<(i64, i64, i64), RuntimeError>
+    {
+        if node.get_type() != RANGE {
+            return Err(self.error(node, "expected a range".to_string()));
+        }
+        let low = self.eval(&node.children[0])?;
+        let high = self.eval(&node.children[1])?;
+        let mut step = 1;
+        if node.children.len() > 2 {
+            step = self.step(node, &node.children[2])?;
+        }
+        return match (low, high) {
+            (IntegerValue(low), IntegerValue(high)) => Ok((low, high, step)),
+            _ => Err(self.error(node, "range bounds must be integers"
+                                      .to_string())),
+        }
+    }
+
+    fn step(&mut self, node: &Node, step: &Node)
+        -> Result<i64, RuntimeError>
+    {
+        return match self.eval(step)? {
+            IntegerValue(i) if i > 0 => Ok(i),
+            _ => Err(self.error(node, "step must be a positive integer"
+                                      .to_string())),
+        }
+    }
+
+    /*
+     * Whether value is one of the integers a range counts
+     * through, so 5 is in 1..9..2 but 4 is not. The distance
+     * from low can exceed an i64, it is taken as an i128.
+     */
+    fn in_range(&mut self, value: &Value, range: &Node)
+        -> Result<bool, RuntimeError>
+    {
+        let (low, high, step) = self.range(range)?;
+        return Ok(match *value {
+            IntegerValue(i) => {
+                low <= i && i <= high &&
+                (i as i128 - low as i128) % step as i128 == 0
+            },
+            _ => false,
+        });
+    }
+
+    /*
+     * The first branch with a matching value runs, the default
+     * branch (a bare BLOCK) runs when none of them matched.
+     */
+    fn switch_statement(&mut self, node: &Node) -> EvalResult
+    {
+        let value = self.eval(&node.children[0])?;
+        let mut default: Option<&Node> = None;
+
+        for branch in &node.children[1..] {
+            if branch.get_type() != SWITCH_BRANCH {
+                default = Some(branch);
+                continue;
+            }
+            for case in &branch.children[0].children {
+                let matched = match case.get_type() {
+                    RANGE => self.in_range(&value, case)?,
+                    _ => values_equal(&value, &self.eval(case)?),
+                };
+                if matched {
+                    return self.eval(&branch.children[1]);
+                }
+            }
+        }
+        return match default {
+            Some(block) => self.eval(block),
+            None => Ok(NilValue),
+        }
+    }
+
+    /*
+     * Short circuits, the result is the operand which decided
+     * the outcome rather than a plain bool. The right operand
+     * is only evaluated when the left one did not decide, so
+     * 'false && f()' never calls f.
+     */
+    fn logical(&mut self, node: &Node) -> EvalResult
+    {
+        let left = self.eval(&node.children[0])?;
+        if truthy(&left) == (node.get_type() == LOGICAL_OR) {
+            return Ok(left);
+        }
+        return self.eval(&node.children[1]);
+    }
+
+    fn unary(&mut self, node: &Node) -> EvalResult
+    {
+        let value = self.eval(&node.children[0])?;
+        return match (node.get_type(), value) {
+            (NEGATE, IntegerValue(i)) => match i.checked_neg() {
+                Some(i) => Ok(IntegerValue(i)),
+                None => Err(self.error(node, "integer overflow"
+                                             .to_string())),
+            },
+            (NEGATE, FloatValue(f)) => Ok(FloatValue(-f)),
+            (UNARY_PLUS, IntegerValue(i)) => Ok(IntegerValue(i)),
+            (UNARY_PLUS, FloatValue(f)) => Ok(FloatValue(f)),
+            (COMPL, value) => value.compl().map_err(|message| {
+                self.error(node, message)
+            }),
+            (_, value) => Err(self.error(node, format!(
+                "bad operand type for '{}': {}", node.string(),
+                value.type_name()))),
+        }
+    }
+
+    /*
+     * 'x in (1..10)' tests whether x lies within the range and
+     * 'a in b' on strings whether a is a substring of b.
+     */
+    fn in_test(&mut self, node: &Node) -> EvalResult
+    {
+        let left = self.eval(&node.children[0])?;
+        if node.children[1].get_type() == RANGE {
+            return Ok(BoolValue(self.in_range(&left, &node.children[1])?));
+        }
+        let right = self.eval(&node.children[1])?;
+        return match (&left, &right) {
+            (&StringValue(ref a), &StringValue(ref b)) => {
+                Ok(BoolValue(b.contains(a.as_str())))
+            },
+            _ => Err(self.operand_error(node, &left, &right)),
+        }
+    }
+
+    fn comparison(&mut self, node: &Node) -> EvalResult
+    {
+        let left = self.eval(&node.children[0])?;
+        let right = self.eval(&node.children[1])?;
+
+        return self.compare(node, &left, &right);
+    }
+
+    /*
+     * Every operand is evaluated once, left to right, and
+     * evaluation stops at the first comparison which fails.
+     */
+    fn chain_comparison(&mut self, node: &Node) -> EvalResult
+    {
+        let mut left = self.eval(&node.children[0])?;
+
+        for op in &node.children[1..] {
+            let right = self.eval(&op.children[0])?;
+            if !truthy(&self.compare(op, &left, &right)?) {
+                return Ok(BoolValue(false));
+            }
+            left = right;
+        }
+        return Ok(BoolValue(true));
+    }
+
+    // Compares by the type of node, one of the comparisons.
+    fn compare(&self, node: &Node, left: &Value, right: &Value)
+        -> EvalResult
+    {
+        let ordering = match (left, right) {
+            (&StringValue(ref a), &StringValue(ref b)) => a.partial_cmp(b),
+            (&IntegerValue(a), &IntegerValue(b)) => a.partial_cmp(&b),
+            _ => match (to_float(left), to_float(right)) {
+                (Some(a), Some(b)) => a.partial_cmp(&b),
+                _ => return Err(self.operand_error(node, left, right)),
+            },
+        };
+        let ordering = match ordering {
+            Some(ordering) => ordering,
+            None => return Ok(BoolValue(false)),
+        };
+        return Ok(BoolValue(match node.get_type() {
+            LT => ordering == Ordering::Less,
+            LE => ordering != Ordering::Greater,
+            GT => ordering == Ordering::Greater,
+            _  => ordering != Ordering::Less,
+        }));
+    }
+
+    /*
+     * Integers stay integers and are checked for overflow,
+     * as soon as one operand is a float both are promoted.
+     */
+    fn arithmetic(&mut self, node: &Node) -> EvalResult
+    {
+        let left = self.eval(&node.children[0])?;
+        let right = self.eval(&node.children[1])?;
+
+        let result = match node.get_type() {
+            PLUS  => left.add(&right),
+            MINUS => left.sub(&right),
+            MUL   => left.mul(&right),
+            DIV   => left.div(&right),
+            FLOOR_DIV => left.floor_div(&right),
+            _     => left.modulo(&right),
+        };
+        return result.map_err(|message| self.error(node, message));
+    }
+
+    fn bitwise(&mut self, node: &Node) -> EvalResult
+    {
+        let left = self.eval(&node.children[0])?;
+        let right = self.eval(&node.children[1])?;
+
+        let result = match node.get_type() {
+            BITWISE_OR  => left.bit_or(&right),
+            BITWISE_XOR => left.bit_xor(&right),
+            BITWISE_AND => left.bit_and(&right),
+            LEFT_SHIFT  => left.shl(&right),
+            _           => left.shr(&right),
+        };
+        return result.map_err(|message| self.error(node, message));
+    }
+
+    fn operand_error(&self, node: &Node, left: &Value, right: &Value)
+        -> RuntimeError
+    {
+        return self.error(node, format!("bad operand types for '{}': \
+                                         {} and {}", node.string(),
+                                        left.type_name(),
+                                        right.type_name()));
+    }
+}
+
+/*
+ * Only false and nil are false, everything else, 0 and
+ * the empty string included, is true.
+ */
+fn truthy(value: &Value) -> bool
+{
+    return match *value {
+        BoolValue(b) => b,
+        NilValue => false,
+        _ => true,
+    }
+}
+
+fn to_float(value: &Value) -> Option<f64>
+{
+    return match *value {
+        IntegerValue(i) => Some(i as f64),
+        FloatValue(f) => Some(f),
+        _ => None,
+    }
+}
+
+/*
+ * Numbers compare by value whatever their kind, so
+ * 1 == 1.0, other values only equal their own kind. Two
+ * integers compare as integers, past 2^53 not every one
+ * of them has a float of its own.
+ */
+fn values_equal(left: &Value, right: &Value) -> bool
+{
+    if let (&IntegerValue(a), &IntegerValue(b)) = (left, right) {
+        return a == b;
+    }
+    return match (to_float(left), to_float(right)) {
+        (Some(a), Some(b)) => a == b,
+        _ => left == right,
+    }
+}
@@ -4,6 +4,10 @@ use token::*;
 use token::TokenType::*;
 use intermediate::*;
 use module::*;
+use std::fmt;
+use std::mem;
+
+pub mod parser_test;
 
 /*
  * This macro generates nodes using imaginary
@@ -13,10 +17,11 @@ use module::*;
  */
 macro_rules! gen_imag_node {
     ($text:expr, $token_type:expr, $line_num:expr,
-     $line_pos:expr) => (
+     $line_pos:expr, $start:expr) => (
         Node::new(Token::new_imag($text.to_string(),
                                   $token_type,
-                                  $line_num, $line_pos));
+                                  $line_num, $line_pos,
+                                  $start));
     );
 }
 
@@ -27,57 +32,358 @@ macro_rules! gen_imag_node {
  */
 macro_rules! generic_block {
     ($self:expr) => (
-        let mut node = Node::new($self.current.clone());
-        $self.next_token();
+        let mut node = Node::new($self.take_token());
 
-        node.add_child($self.expr());
-        
-        return node;
+        node.add_child($self.expr()?);
+
+        return Ok(node);
     );
 }
 
-pub struct Parser<'a> {
-    scanner: &'a mut Scanner<'a>,
+/*
+ * A syntax error. The parser does not stop on the first
+ * error, it records it and synchronizes to the start of
+ * the next statement, so a single pass reports every
+ * error it can find.
+ */
+#[derive(Clone, Debug, PartialEq)]
+pub struct ParseError {
+    pub filename: String,
+    pub line_num: i32,
+    pub line_pos: i32,
+    pub message: String,
+    // What the parser was looking for, like "expected ')'".
+    pub expected: Option<String>,
+    /*
+     * Where parsing went wrong: the last token consumed, or
+     * the bracket which was never closed. An editor can mark
+     * from here up to the error's own position.
+     */
+    pub from: Option<Span>,
+}
+
+/*
+ * The position and byte range of a token in the source.
+ */
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Span {
+    pub line_num: i32,
+    pub line_pos: i32,
+    pub start: usize,
+    pub end: usize,
+}
+
+impl<'a> From<&'a Token> for Span
+{
+    fn from(token: &'a Token) -> Span
+    {
+        return Span {
+            line_num: token.line_num,
+            line_pos: token.line_pos,
+            start: token.start,
+            end: token.end,
+        };
+    }
+}
+
+impl fmt::Display for ParseError
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+    {
+        return write!(f, "{}:{}:{}: {}", self.filename, self.line_num,
+                      self.line_pos, self.message);
+    }
+}
+
+impl From<ScanError> for ParseError
+{
+    fn from(error: ScanError) -> ParseError
+    {
+        return ParseError {
+            filename: error.filename,
+            line_num: error.line_num,
+            line_pos: error.line_pos,
+            message: error.message,
+            expected: None,
+            from: None,
+        };
+    }
+}
+
+impl ParseError
+{
+    /*
+     * The error followed by the offending source line and
+     * a caret under the column, see scanner::excerpt.
+     */
+    pub fn render(&self, source: &str) -> String
+    {
+        return self.render_with_tabs(source, DEFAULT_TAB_WIDTH);
+    }
+
+    // As render, for a scanner given another tab width.
+    pub fn render_with_tabs(&self, source: &str, tab_width: usize) -> String
+    {
+        return format!("{}\n{}", self, excerpt(source, self.line_num,
+                                               self.line_pos, tab_width));
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Severity {
+    Warning,
+    // Likely intended, but worth pointing out.
+    Note,
+}
+
+impl fmt::Display for Severity
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+    {
+        return match *self {
+            Severity::Warning => write!(f, "warning"),
+            Severity::Note => write!(f, "note"),
+        }
+    }
+}
+
+/*
+ * A problem which does not stop the program from being
+ * parsed, such as code which can never run. Diagnostics
+ * are kept apart from the errors and never fail a parse.
+ */
+#[derive(Clone, Debug, PartialEq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub line_num: i32,
+    pub line_pos: i32,
+    pub message: String,
+}
+
+impl fmt::Display for Diagnostic
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+    {
+        return write!(f, "{}:{}: {}: {}", self.line_num, self.line_pos,
+                      self.severity, self.message);
+    }
+}
+
+pub type ParseResult = Result<Box<Node>, ParseError>;
+
+pub struct ParsedStatement {
+    pub node: Box<Node>,
+    pub recovered: bool,
+}
+
+/*
+ * Scans and parses a whole program. Errors from both
+ * stages are returned together, ordered by position.
+ */
+pub fn parse(source: &str, filename: &str)
+    -> Result<Box<Node>, Vec<ParseError>>
+{
+    let module = Module::new(filename.to_string());
+    let mut scanner = Scanner::new(source, &module);
+    let mut parser = Parser::new(&mut scanner, &module);
+
+    return parser.program();
+}
+
+/*
+ * Scans and parses a single expression, see
+ * Parser::parse_expr.
+ */
+pub fn parse_expr(source: &str, filename: &str)
+    -> Result<Box<Node>, ParseError>
+{
+    let module = Module::new(filename.to_string());
+    let mut scanner = Scanner::new(source, &module);
+    let mut parser = Parser::new(&mut scanner, &module);
+
+    return parser.parse_expr();
+}
+
+/*
+ * Left and right binding power of the binary operators
+ * parse_binary handles, None for any other token. Every
+ * level binds tighter than the one above it, and as the
+ * right power is one more than the left the operators
+ * associate to the left.
+ */
+fn binding_power(token_type: TokenType) -> Option<(u8, u8)>
+{
+    return match token_type {
+        LOGICAL_OR               => Some((1, 2)),
+        LOGICAL_AND              => Some((3, 4)),
+        EQL | NOT_EQL            => Some((5, 6)),
+        IN                       => Some((7, 8)),
+        LT | LE | GT | GE        => Some((9, 10)),
+        BITWISE_OR               => Some((11, 12)),
+        BITWISE_XOR              => Some((13, 14)),
+        BITWISE_AND              => Some((15, 16)),
+        LEFT_SHIFT | RIGHT_SHIFT => Some((17, 18)),
+        PLUS | MINUS             => Some((19, 20)),
+        MUL | DIV | FLOOR_DIV | MODULO => Some((21, 22)),
+        _ => None,
+    }
+}
+
+/*
+ * The error for assigning to something which is not a
+ * variable, subscript or member, like 'cannot assign to
+ * call' or 'cannot assign to operator '+''.
+ */
+fn invalid_target(token_type: TokenType) -> String
+{
+    let operator = match token_type {
+        NEGATE | UNARY_PLUS | IN_TEST => true,
+        _ => token_type.is_operator(),
+    };
+    if operator {
+        return format!("cannot assign to operator {}", token_type);
+    }
+    return format!("cannot assign to {}", token_type);
+}
+
+/*
+ * How deeply expressions and blocks may nest before the
+ * parser gives up on them, see Parser::set_max_depth. Low enough for a
+ * debug build on the 2 MB stack of a spawned thread.
+ */
+pub const DEFAULT_MAX_DEPTH: usize = 64;
+
+/*
+ * Tokens are pulled from a TokenSource, which is the
+ * scanner unless the tokens were made some other way.
+ */
+pub struct Parser<'a, T: 'a + TokenSource> {
+    tokens: &'a mut T,
     module: &'a Module,
     current: Token,
     next: Token,
     in_subroutine: bool,
+    // Set while parsing a for loop's iterable, see in_expr.
+    no_in: bool,
+    /*
+     * Set by subscript for the range right after '[', the
+     * only one which may leave out its bounds.
+     */
+    slice_bounds: bool,
+    // Set while parsing a hash key, see atom.
+    no_arrow: bool,
+    // Nesting of the expression being parsed, see enter.
+    depth: usize,
+    max_depth: usize,
+    errors: Vec<ParseError>,
+    // Names of the modules imported so far, each once.
+    imports: Vec<String>,
+    // Problems which do not stop the program, see warnings.
+    warnings: Vec<Diagnostic>,
+    // The last token consumed other than a newline.
+    last: Option<Span>,
 }
 
-impl<'a> Parser<'a>
+impl<'a, T: TokenSource> Parser<'a, T>
 {
-    pub fn new(scanner: &'a mut Scanner<'a>, module: &'a Module)
-        -> Parser<'a>
+    pub fn new(tokens: &'a mut T, module: &'a Module) -> Parser<'a, T>
     {
         return Parser {
-            current: scanner.next_token(),
-            next: scanner.next_token(),
-            scanner: scanner,
+            current: tokens.next_token(),
+            next: tokens.next_token(),
+            tokens: tokens,
             module: module,
             in_subroutine: false,
+            no_in: false,
+            slice_bounds: false,
+            no_arrow: false,
+            depth: 0,
+            max_depth: DEFAULT_MAX_DEPTH,
+            errors: Vec::new(),
+            imports: Vec::new(),
+            warnings: Vec::new(),
+            last: None,
         };
     }
 
-    fn error(&self, message: &'static str) -> !
+    /*
+     * Every level of nesting in an expression, be it a
+     * unary operator, a bracketed atom, a ternary branch or
+     * the right hand side of an assignment, descends through
+     * the whole ladder of expression methods. That takes up
+     * to 20 KB of stack a level in a debug build and some
+     * 2 KB in a release build. A nested block counts as a
+     * level too. Past max_depth levels the expression or
+     * block is reported instead of risking a stack overflow,
+     * raise it only along with the stack size.
+     */
+    pub fn set_max_depth(&mut self, max_depth: usize)
+    {
+        self.max_depth = max_depth;
+    }
+
+    /*
+     * The modules the program depends on, in the order they
+     * were first imported.
+     */
+    pub fn imports(&self) -> &[String]
+    {
+        return &self.imports;
+    }
+
+    /*
+     * Suspicious but valid constructs, such as a match arm
+     * which can never be reached, in the order found. Each
+     * one is a Warning or, for what is likely intended, a
+     * Note.
+     */
+    pub fn warnings(&self) -> &[Diagnostic]
+    {
+        return &self.warnings;
+    }
+
+    fn diagnostic(&mut self, severity: Severity, line_num: i32,
+                  line_pos: i32, message: String)
+    {
+        self.warnings.push(Diagnostic {
+            severity: severity,
+            line_num: line_num,
+            line_pos: line_pos,
+            message: message,
+        });
+    }
+
+    /*
+     * Goes one level deeper into what, an expression or a
+     * block, the caller takes the level off depth again
+     * once it has the result of the nested part.
+     */
+    fn enter(&mut self, what: &str) -> Result<(), ParseError>
+    {
+        if self.depth == self.max_depth {
+            let message = format!("{} too deeply nested", what);
+            return Err(self.error(message.as_str()));
+        }
+        self.depth += 1;
+
+        return Ok(());
+    }
+
+    fn error(&self, message: &str) -> ParseError
     {
         let mut buf = String::new();
 
-        buf.push_str(format!("{}:{}:{}: ", self.module.filename,
-                             self.current.line_num,
-                             self.current.line_pos).as_str());
         if self.current.token_type == NEWLINE {
             buf.push_str("unexpected newline, ");
         }
         else if self.current.token_type == EOF {
             buf.push_str("unexpected end-of-file, ");
         }
-        else if is_between!(self.current.token_type,
-                            STRING, IDENT) {
+        else if self.current.token_type.is_literal() {
             buf.push_str(format!("unexpected literal near '{}', ",
                                  self.current.string()).as_str());
         }
-        else if is_between!(self.current.token_type,
-                            DEF, IMPORT) {
+        else if self.current.token_type.is_keyword() {
             buf.push_str(format!("unexpected keyword near '{}', ",
                                  self.current.string()).as_str());
         }
@@ -87,13 +393,67 @@ impl<'a> Parser<'a>
         }
         buf.push_str(message);
 
-        panic!(buf);
+        return ParseError {
+            filename: self.module.filename.clone(),
+            line_num: self.current.line_num,
+            line_pos: self.current.line_pos,
+            message: buf,
+            expected: None,
+            from: self.last,
+        };
+    }
+
+    /*
+     * An error for a token other than the one asked for,
+     * which keeps message as the expected description.
+     */
+    fn expected(&self, message: &str) -> ParseError
+    {
+        let mut error = self.error(message);
+        error.expected = Some(message.to_string());
+
+        return error;
+    }
+
+    /*
+     * Records an error and skips ahead to where the next
+     * statement is likely to start: past a newline or ';',
+     * or up to a '}' which is left for the enclosing block
+     * to match.
+     */
+    fn recover(&mut self, error: ParseError)
+    {
+        self.errors.push(error);
+        loop {
+            match self.peek_current() {
+                NEWLINE | SEMICOLON => {
+                    self.next_and_skip_newlines();
+                    return;
+                },
+                RBRACE | EOF => return,
+                _ => self.next_token(),
+            }
+        }
     }
 
     fn next_token(&mut self)
     {
-        self.current = self.next.clone();
-        self.next = self.scanner.next_token();
+        self.take_token();
+    }
+
+    /*
+     * Advances like next_token, handing over the token that
+     * was current instead of dropping it. Nodes are built
+     * from the taken token so nothing has to be cloned.
+     */
+    fn take_token(&mut self) -> Token
+    {
+        let next = mem::replace(&mut self.next, self.tokens.next_token());
+
+        if self.current.token_type != NEWLINE {
+            self.last = Some(Span::from(&self.current));
+        }
+        return mem::replace(&mut self.current, next);
     }
 
     fn peek_current(&self) -> TokenType
@@ -106,13 +466,29 @@ impl<'a> Parser<'a>
         return self.next.token_type;
     }
 
+    /*
+     * Type of the nth token from the current one, where
+     * peek(0) is the current token and peek(1) the next.
+     * Anything further is peeked from the token source.
+     */
+    fn peek(&mut self, n: usize) -> TokenType
+    {
+        return match n {
+            0 => self.peek_current(),
+            1 => self.peek_next(),
+            _ => self.tokens.peek_token(n - 2).token_type,
+        }
+    }
+
     fn __match(&mut self, token_type: TokenType,
-               message: &'static str)
+               message: &'static str) -> Result<(), ParseError>
     {
         if self.peek_current() != token_type {
-            self.error(message);
+            return Err(self.expected(message));
         }
         self.next_token();
+
+        return Ok(());
     }
 
     fn skip_newlines(&mut self)
@@ -122,6 +498,37 @@ impl<'a> Parser<'a>
         }
     }
 
+    /*
+     * Matches the bracket closing opener. Running into the end
+     * of the file instead is reported at the opener, where the
+     * missing bracket most likely belongs, rather than at the
+     * end of the file.
+     */
+    fn close(&mut self, opener: &Token, message: &'static str)
+        -> Result<(), ParseError>
+    {
+        if self.peek_current() == EOF {
+            return Err(ParseError {
+                filename: self.module.filename.clone(),
+                line_num: opener.line_num,
+                line_pos: opener.line_pos,
+                message: format!("unclosed {} opened at line {}",
+                                 opener.token_type, opener.line_num),
+                expected: Some(message.to_string()),
+                from: Some(Span::from(opener)),
+            });
+        }
+        let closer = match opener.token_type {
+            LPAREN => RPAREN,
+            LBRACK => RBRACK,
+            _ => RBRACE,
+        };
+        return self.__match(closer, message).map_err(|mut error| {
+            error.from = Some(Span::from(opener));
+            error
+        });
+    }
+
     fn next_and_skip_newlines(&mut self)
     {
         self.next_token();
@@ -131,26 +538,43 @@ impl<'a> Parser<'a>
     fn match_and_skip_newlines(&mut self,
                                token_type: TokenType,
                                message: &'static str)
+        -> Result<(), ParseError>
     {
-        self.__match(token_type, message);
+        self.__match(token_type, message)?;
         self.skip_newlines();
+
+        return Ok(());
     }
 
     fn match_line(&mut self, message: &'static str)
+        -> Result<(), ParseError>
     {
-        self.__match(NEWLINE, message);
+        self.__match(NEWLINE, message)?;
         self.skip_newlines();
+
+        return Ok(());
+    }
+
+    /*
+     * Soft keywords, like 'step', are not reserved by the
+     * scanner and reach the parser as plain identifiers. They
+     * only act as keywords where the grammar asks for them,
+     * everywhere else they remain usable as names.
+     */
+    fn is_soft_keyword(&self, word: &str) -> bool
+    {
+        return self.peek_current() == IDENT && self.current.string() == word;
     }
 
     fn is_factor(&self) -> bool
     {
         let token_type = self.peek_current();
         
-        return token_type == MINUS || token_type == BANG ||
-               token_type == COMPL;
+        return token_type == MINUS || token_type == PLUS ||
+               token_type == BANG || token_type == COMPL;
     }
     
-    fn statement_trailer(&mut self)
+    fn statement_trailer(&mut self) -> Result<(), ParseError>
     {
         let token_type = self.peek_current();
 
@@ -162,60 +586,222 @@ impl<'a> Parser<'a>
             self.skip_newlines();
         }
         else {
-            self.__match(EOF, "expected end-of-file");
+            self.__match(EOF, "expected end-of-file")?;
         }
+        return Ok(());
     }
 
-    fn block_trailer(&mut self)
+    fn block_trailer(&mut self) -> Result<(), ParseError>
     {
+        /*
+         * The last statement of a block may be followed
+         * directly by the closing brace, as in '{ 1; 2 }'.
+         */
+        if self.peek_current() == RBRACE {
+            return Ok(());
+        }
         if self.peek_current() == SEMICOLON {
             self.next_token();
             self.skip_newlines();
         }
         else {
-            self.match_line("expected newline");
+            self.match_line("expected newline")?;
         }
+        return Ok(());
     }
 
-    pub fn program(&mut self) -> Box<Node>
+    pub fn program(&mut self) -> Result<Box<Node>, Vec<ParseError>>
     {
         let mut program = gen_imag_node!("BLOCK", BLOCK,
                                           self.current.line_num,
-                                          self.current.line_pos);
+                                          self.current.line_pos,
+                                          self.current.start);
+        let (statements, errors) = self.statements();
+        if errors.len() != 0 {
+            return Err(errors);
+        }
+        for statement in statements {
+            program.add_child(statement.node);
+        }
+        return Ok(program);
+    }
+
+    /*
+     * Parses the whole program like program(), but hands
+     * back every top level statement even when errors were
+     * found. A statement which failed to parse is replaced
+     * by an ERROR node, and any statement which needed error
+     * recovery somewhere within it is tagged as recovered,
+     * so tooling can tell which parts of the tree to trust.
+     */
+    pub fn statements(&mut self)
+        -> (Vec<ParsedStatement>, Vec<ParseError>)
+    {
+        let mut statements: Vec<ParsedStatement> = Vec::new();
+
         self.skip_newlines();
         while self.peek_current() != EOF {
-            if self.peek_current() == DEF && self.peek_next() != LPAREN {
-                program.add_child(self.def_statement(false));
+            self.take_scanner_errors();
+            let error_count = self.errors.len();
+            let mut node = gen_imag_node!("ERROR", ERROR,
+                                           self.current.line_num,
+                                           self.current.line_pos,
+                                           self.current.start);
+            match self.program_statement() {
+                Ok(statement) => node = statement,
+                Err(error) => {
+                    self.recover(error);
+                    /*
+                     * There is no block to close at the top
+                     * level, so a stray '}' is skipped.
+                     */
+                    if self.peek_current() == RBRACE {
+                        self.next_and_skip_newlines();
+                    }
+                },
             }
-            else {
-                program.add_child(self.statement());
+            self.take_scanner_errors();
+            statements.push(ParsedStatement {
+                node: node,
+                recovered: self.errors.len() != error_count,
+            });
+        }
+        self.take_scanner_errors();
+
+        let mut errors = mem::replace(&mut self.errors, Vec::new());
+        errors.sort_by_key(|error| (error.line_num, error.line_pos));
+
+        return (statements, errors);
+    }
+
+    /*
+     * Parses one expression which has to make up the rest
+     * of the input, trailing newlines aside. There is no
+     * error recovery, the first error found is returned,
+     * a scanner error before a syntax error.
+     */
+    pub fn parse_expr(&mut self) -> Result<Box<Node>, ParseError>
+    {
+        self.skip_newlines();
+        let result = self.expr().and_then(|node| {
+            self.skip_newlines();
+            if self.peek_current() != EOF {
+                return Err(self.error("expected end of expression"));
             }
-            self.statement_trailer();
+            return Ok(node);
+        });
+        if let Some(error) = self.tokens.take_errors().into_iter().next() {
+            return Err(ParseError::from(error));
+        }
+        return result;
+    }
+
+    fn take_scanner_errors(&mut self)
+    {
+        for error in self.tokens.take_errors() {
+            self.errors.push(ParseError::from(error));
         }
-        return program;
     }
 
-    fn statement(&mut self) -> Box<Node>
+    fn program_statement(&mut self) -> ParseResult
+    {
+        let node = self.statement()?;
+        self.statement_trailer()?;
+
+        return Ok(node);
+    }
+
+    fn block_statement(&mut self) -> ParseResult
+    {
+        let node = self.statement()?;
+        self.block_trailer()?;
+
+        return Ok(node);
+    }
+
+    fn statement(&mut self) -> ParseResult
     {
+        /*
+         * 'def name(' declares a subroutine, while 'def('
+         * starts a subroutine literal within an expression.
+         * Declarations may appear in any block, so they nest.
+         */
+        if self.peek_current() == DEF && self.peek_next() != LPAREN {
+            return self.def_statement(false);
+        }
         return match self.peek_current() {
             IF => self.if_statement(),
             WHILE | UNTIL => self.control_statement(),
             FOR    => self.for_statement(),
+            WITH   => self.with_statement(),
             SWITCH => self.switch_statement(),
+            MATCH  => self.match_statement(),
+            CONST  => self.const_statement(),
             IMPORT => self.import_statement(),
             DEBUG  => self.debug_statement(),
+            ASSERT => self.assert_statement(),
             RETURN => self.return_statement(),
+            YIELD  => self.yield_statement(),
+            ELIF | ELSE => Err(self.error("expected 'if' before it")),
+            IDENT if self.peek(1) == COLON => self.annotated_assignment(),
             _      => self.expr_statement(),
         }
     }
 
+    /*
+     * 'name: Type = value', an assignment whose target carries
+     * a TYPE_ANNOT child. Annotations are only read at the
+     * start of a statement, elsewhere 'a: b' already means
+     * something, as in 'case a: b = 1'.
+     */
+    fn annotated_assignment(&mut self) -> ParseResult
+    {
+        let mut target = Node::new(self.take_token());
+        if let Some(annotation) = self.type_annotation()? {
+            target.add_child(annotation);
+        }
+        if self.peek_current() != ASSIGN {
+            return Err(self.error("expected '=' after type annotation"));
+        }
+        let mut node = Node::new(self.take_token());
+        self.skip_newlines();
+
+        node.add_child(target);
+        node.add_child(self.assignment_expr()?);
+
+        return Ok(node);
+    }
+
+    /*
+     * An optional ': Type' giving a TYPE_ANNOT node holding
+     * the type name. Nothing checks the types as of yet, the
+     * annotations are only kept in the tree.
+     */
+    fn type_annotation(&mut self) -> Result<Option<Box<Node>>, ParseError>
+    {
+        if self.peek_current() != COLON {
+            return Ok(None);
+        }
+        let mut node = gen_imag_node!("TYPE_ANNOT", TYPE_ANNOT,
+                                       self.current.line_num,
+                                       self.current.line_pos,
+                                       self.current.start);
+        self.next_token();
+        if self.peek_current() != IDENT {
+            return Err(self.error("expected type name"));
+        }
+        node.add_child(Node::new(self.take_token()));
+
+        return Ok(Some(node));
+    }
+
     /*
      * The parameter is_literal is passed for code-reusage,
      * the only difference between a named subroutine and a
      * literal is the identifier which is scanned/not scanned
      * based on the value passed.
      */
-    fn def_statement(&mut self, is_literal: bool) -> Box<Node>
+    fn def_statement(&mut self, is_literal: bool) -> ParseResult
     {
         let mut node: Box<Node>;
         self.next_token();
@@ -223,465 +809,1041 @@ impl<'a> Parser<'a>
         if !is_literal {
             node = gen_imag_node!("SUB_DECL", SUB_DECL,
                                    self.current.line_num,
-                                   self.current.line_pos);
+                                   self.current.line_pos,
+                                   self.current.start);
             if self.peek_current() != IDENT {
-                self.error("expected identifier");
+                return Err(self.error("expected identifier"));
             }
-            node.add_child(Node::new(self.current.clone()));
-            self.next_token();
+            node.add_child(Node::new(self.take_token()));
         }
         else {
             node = gen_imag_node!("SUB_LITERAL", SUB_LITERAL,
                                    self.current.line_num,
-                                   self.current.line_pos);
+                                   self.current.line_pos,
+                                   self.current.start);
         }
+        let opener = self.current.clone();
         self.match_and_skip_newlines(LPAREN,
-                                     "expected '(' to open parameter list");
+                                     "expected '(' to open parameter list")?;
 
         let mut params = gen_imag_node!("SUB_PARAMS", SUB_PARAMS,
                                          self.current.line_num,
-                                         self.current.line_pos);
-        for n in self.parameter_list() {
+                                         self.current.line_pos,
+                                         self.current.start);
+        for n in self.parameter_list()? {
             params.add_child(n);
         }
         self.skip_newlines();
-        self.__match(RPAREN, "expected ')' to close parameter list");
+        self.close(&opener, "expected ')' to close parameter list")?;
         
         node.add_child(params);
 
+        let in_subroutine = self.in_subroutine;
         self.in_subroutine = true;
-        node.add_child(self.block());
-        self.in_subroutine = false;
+        let block = self.block();
+        self.in_subroutine = in_subroutine;
 
-        return node;
+        node.add_child(block?);
+
+        return Ok(node);
     }
 
-    fn parameter_list(&mut self) -> Vec<Box<Node>>
+    fn parameter_list(&mut self)
+        -> Result<Vec<Box<Node>>, ParseError>
     {
         let mut sequence: Vec<Box<Node>> = Vec::new();
+        let mut has_default = false;
 
         if self.peek_current() == RPAREN {
-            return sequence;
+            return Ok(sequence);
         }
         loop {
+            /*
+             * A rest parameter, '*name', collects the remaining
+             * arguments and so can only be the last one.
+             */
+            if self.peek_current() == MUL {
+                let mut rest = gen_imag_node!("REST_PARAM", REST_PARAM,
+                                               self.current.line_num,
+                                               self.current.line_pos,
+                                               self.current.start);
+                self.next_token();
+                if self.peek_current() != IDENT {
+                    return Err(self.error("expected identifier after '*'"));
+                }
+                rest.add_child(Node::new(self.take_token()));
+                if let Some(annotation) = self.type_annotation()? {
+                    rest.add_child(annotation);
+                }
+                sequence.push(rest);
+                if self.peek_current() == COMMA {
+                    return Err(self.error("rest parameter must be the \
+                                           last parameter"));
+                }
+                break;
+            }
             if self.peek_current() != IDENT {
-                self.error("expected identifier as argument");
+                return Err(self.error("expected identifier as argument"));
             }
-            sequence.push(Node::new(self.current.clone()));
-            self.next_token();
+            /*
+             * A type annotation and a default value hang as the
+             * children of their parameter, in that order. Once
+             * a default is given the parameters that follow
+             * must have one aswell.
+             */
+            let assign = if self.peek(1) == COLON { 3 } else { 1 };
+            if has_default && self.peek(assign) != ASSIGN {
+                return Err(self.error("non-default parameter follows \
+                                       default parameter"));
+            }
+            let mut param = Node::new(self.take_token());
+            if let Some(annotation) = self.type_annotation()? {
+                param.add_child(annotation);
+            }
+            if self.peek_current() == ASSIGN {
+                self.next_token();
+                param.add_child(self.expr()?);
+                has_default = true;
+            }
+            sequence.push(param);
             if self.peek_current() != COMMA {
                 break;
             }
             self.next_and_skip_newlines();
         }
-        return sequence;
+        return Ok(sequence);
     }
 
-    fn if_statement(&mut self) -> Box<Node>
+    /*
+     * if <expr> { ... } elif <expr> { ... } else { ... }
+     *
+     * The else has to come last, an elif following it is an
+     * error rather than the start of another statement. As
+     * every branch is a braced block an else always belongs
+     * to the innermost if it follows.
+     */
+    fn if_statement(&mut self) -> ParseResult
     {
-        let mut node = Node::new(self.current.clone());
-        self.next_token();
+        let mut node = Node::new(self.take_token());
 
-        node.add_child(self.expr());
-        node.add_child(self.block());
+        node.add_child(self.expr()?);
+        node.add_child(self.block()?);
 
         let mut elif_root = gen_imag_node!("ELIF", ELIF,
                                             self.current.line_num,
-                                            self.current.line_pos);
+                                            self.current.line_pos,
+                                            self.current.start);
         while self.peek_current() == ELIF {
             self.next_token();
 
-            elif_root.add_child(self.expr());
-            elif_root.add_child(self.block());
+            elif_root.add_child(self.expr()?);
+            elif_root.add_child(self.block()?);
         }
         node.add_child(elif_root);
         if self.peek_current() == ELSE {
             self.next_token();
-            node.add_child(self.block());
+            node.add_child(self.block()?);
+            if self.peek_current() == ELIF {
+                return Err(self.error("'elif' cannot follow 'else'"));
+            }
         }
-        return node;
+        return Ok(node);
     }
 
-    fn control_statement(&mut self) -> Box<Node>
+    fn control_statement(&mut self) -> ParseResult
     {
-        let mut node = Node::new(self.current.clone());
-        self.next_token();
+        let mut node = Node::new(self.take_token());
 
-        node.add_child(self.expr());
-        node.add_child(self.block());
+        node.add_child(self.expr()?);
+        node.add_child(self.block()?);
+        self.loop_else(&mut node)?;
+
+        return Ok(node);
+    }
 
-        return node;
+    /*
+     * 'while c { ... } else { ... }', the else block runs
+     * once the loop has finished. It is added to the loop
+     * as an ELSE node holding the block, following the
+     * loop's other children.
+     */
+    fn loop_else(&mut self, node: &mut Box<Node>) -> Result<(), ParseError>
+    {
+        if self.peek_current() == ELSE {
+            let mut else_node = Node::new(self.take_token());
+            else_node.add_child(self.block()?);
+            node.add_child(else_node);
+        }
+        return Ok(());
     }
 
-    fn for_statement(&mut self) -> Box<Node>
+    fn for_statement(&mut self) -> ParseResult
     {
-        let mut node = Node::new(self.current.clone());
-        self.next_token();
+        let mut node = Node::new(self.take_token());
 
         if self.peek_current() != IDENT {
-            self.error("expected identifier");
+            return Err(self.error("expected identifier"));
         }
-        node.add_child(Node::new(self.current.clone()));
-        self.next_token();
+        node.add_child(Node::new(self.take_token()));
+
+        self.__match(IN, "expected keyword 'in' before expression")?;
+        let no_in = self.no_in;
+        self.no_in = true;
+        let iterable = self.expr();
+        self.no_in = no_in;
+        node.add_child(iterable?);
 
-        self.__match(IN, "expected keyword 'in' before expression");
-        node.add_child(self.expr());
-        node.add_child(self.block());
+        /*
+         * for <ident> in <expr> step <expr> { ... }, the step
+         * follows the block so the block stays third.
+         */
+        let mut step: Option<Box<Node>> = None;
+        if self.is_soft_keyword("step") {
+            let mut step_node = gen_imag_node!("STEP", STEP,
+                                                self.current.line_num,
+                                                self.current.line_pos,
+                                                self.current.start);
+            self.next_token();
+            step_node.add_child(self.expr()?);
+            step = Some(step_node);
+        }
+        node.add_child(self.block()?);
+        if let Some(step_node) = step {
+            node.add_child(step_node);
+        }
+        self.loop_else(&mut node)?;
 
-        return node;
+        return Ok(node);
     }
 
-    fn switch_statement(&mut self) -> Box<Node>
+    /*
+     * with <expr> as <ident>, <expr> as <ident> { ... }
+     *
+     * The children are the resource and name pairs, followed
+     * by the block. Each resource is meant to be bound to its
+     * name for the block alone and cleaned up as the block is
+     * left, however it is left, the last bound first. Only
+     * the parsing is in place so far.
+     */
+    fn with_statement(&mut self) -> ParseResult
     {
-        let mut node = Node::new(self.current.clone());
-        self.next_token();
+        let mut node = Node::new(self.take_token());
 
-        node.add_child(self.expr());
+        loop {
+            node.add_child(self.expr()?);
+            if !self.is_soft_keyword("as") {
+                return Err(self.error("expected 'as' after resource"));
+            }
+            self.next_token();
+            if self.peek_current() != IDENT {
+                return Err(self.error("expected identifier"));
+            }
+            node.add_child(Node::new(self.take_token()));
+            if self.peek_current() != COMMA {
+                break;
+            }
+            self.next_and_skip_newlines();
+        }
+        node.add_child(self.block()?);
+
+        return Ok(node);
+    }
+
+    /*
+     * switch <expr> {
+     *     case <expr>, <expr>: <statements>
+     *     default: <statements>
+     * }
+     *
+     * A case may list several comma separated values, the
+     * branch is taken if the switch expression matches any
+     * of them. A value written as a range, 'case 1..10:',
+     * matches when the switch expression lies within it
+     * rather than by equality.
+     */
+    fn switch_statement(&mut self) -> ParseResult
+    {
+        let mut node = Node::new(self.take_token());
+
+        node.add_child(self.expr()?);
 
         self.skip_newlines();
+        let opener = self.current.clone();
         self.match_and_skip_newlines(LBRACE, "expected '{' to \
-                                     open switch block");
+                                     open switch block")?;
 
         let mut token_type = self.peek_current();
         while token_type != RBRACE && token_type != EOF {
-            node.add_child(self.branch());
+            node.add_child(self.branch()?);
 
             token_type = self.peek_current(); 
         }
-        self.__match(RBRACE, "expected '}' to close switch block");
+        self.close(&opener, "expected '}' to close switch block")?;
 
-        return node;
+        return Ok(node);
     }
 
-    fn branch(&mut self) -> Box<Node>
+    fn branch(&mut self) -> ParseResult
     {
         self.skip_newlines();
 
         if self.peek_current() == DEFAULT {
             self.next_token();
+            self.__match(COLON, "expected ':' after 'default'")?;
             return self.branch_block();
         }
-        self.__match(CASE, "expected 'case'");
+        self.__match(CASE, "expected 'case'")?;
         let mut branch_node = gen_imag_node!("SWITCH_BRANCH",
                                               SWITCH_BRANCH,
                                               self.current.line_num,
-                                              self.current.line_pos);
+                                              self.current.line_pos,
+                                              self.current.start);
         let mut constants_node = gen_imag_node!("SWITCH_EXPRS",
                                                  SWITCH_EXPRS,
                                                  self.current.line_num,
-                                                 self.current.line_pos);
-        self.constant_list(&mut constants_node);
-        
+                                                 self.current.line_pos,
+                                                 self.current.start);
+        self.constant_list(&mut constants_node)?;
+        self.__match(COLON, "expected ':' after case values")?;
+
         branch_node.add_child(constants_node);
-        branch_node.add_child(self.branch_block());
+        branch_node.add_child(self.branch_block()?);
 
-        return branch_node;
+        return Ok(branch_node);
     }
 
     fn constant_list(&mut self, node: &mut Box<Node>)
+        -> Result<(), ParseError>
     {
         loop {
-            node.add_child(self.expr());
+            node.add_child(self.expr()?);
             if self.peek_current() != COMMA {
                 break;
             }
             self.next_and_skip_newlines();
         }
+        return Ok(());
     }
 
-    fn branch_block(&mut self) -> Box<Node>
+    fn branch_block(&mut self) -> ParseResult
     {
-        self.match_line("expected newline before branch block");
+        self.skip_newlines();
 
         let mut node = gen_imag_node!("BLOCK", BLOCK,
                                        self.current.line_num,
-                                       self.current.line_pos);
+                                       self.current.line_pos,
+                                       self.current.start);
         let mut token_type = self.peek_current();
         while token_type != CASE && token_type != DEFAULT &&
               token_type != RBRACE && token_type != EOF
         {
-            node.add_child(self.statement());
-            self.block_trailer();
-
+            match self.block_statement() {
+                Ok(statement) => node.add_child(statement),
+                Err(error) => self.recover(error),
+            }
             token_type = self.peek_current();
         }
-        return node;
+        return Ok(node);
     }
 
-    fn import_statement(&mut self) -> Box<Node>
+    /*
+     * match x {
+     *     [a, b] => debug a + b
+     *     {'k' => v} => { debug v }
+     *     _ => debug x
+     * }
+     *
+     * Each arm becomes a MATCH_ARM holding its pattern and a
+     * BLOCK. The first arm whose pattern fits the value is
+     * taken, binding the identifiers in the pattern. An arm
+     * following one which matches everything, or repeating
+     * an earlier pattern, can never be taken and is recorded
+     * as a warning.
+     */
+    fn match_statement(&mut self) -> ParseResult
     {
-        generic_block!(self);
+        let mut node = Node::new(self.take_token());
+
+        node.add_child(self.expr()?);
+
+        self.skip_newlines();
+        let opener = self.current.clone();
+        self.match_and_skip_newlines(LBRACE, "expected '{' to \
+                                     open match block")?;
+
+        let mut token_type = self.peek_current();
+        while token_type != RBRACE && token_type != EOF {
+            let arm = self.match_arm()?;
+            self.check_reachable(&node.children[1..], &arm);
+            node.add_child(arm);
+
+            token_type = self.peek_current();
+            if token_type == COMMA || token_type == SEMICOLON {
+                self.next_and_skip_newlines();
+                token_type = self.peek_current();
+            }
+            else if token_type != RBRACE {
+                self.match_line("expected newline after match arm")?;
+                token_type = self.peek_current();
+            }
+        }
+        self.close(&opener, "expected '}' to close match block")?;
+
+        return Ok(node);
     }
 
-    fn debug_statement(&mut self) -> Box<Node>
+    fn match_arm(&mut self) -> ParseResult
     {
-        /*
-         * The debug statement is just a statement that
-         * outputs information about an ares object. This
-         * will help to verify values during development. This
-         * will be replaced by some builtin subroutines in later
-         * stages.
-         */
-        generic_block!(self);
+        let mut node = gen_imag_node!("MATCH_ARM", MATCH_ARM,
+                                       self.current.line_num,
+                                       self.current.line_pos,
+                                       self.current.start);
+        node.add_child(self.pattern()?);
+        self.match_and_skip_newlines(ASSIGN_ARROW, "expected '=>' after \
+                                     pattern")?;
+        if self.peek_current() == LBRACE {
+            node.add_child(self.block()?);
+        }
+        else {
+            let mut block = gen_imag_node!("BLOCK", BLOCK,
+                                            self.current.line_num,
+                                            self.current.line_pos,
+                                            self.current.start);
+            block.add_child(self.statement()?);
+            node.add_child(block);
+        }
+        return Ok(node);
     }
 
-    fn return_statement(&mut self) -> Box<Node>
+    /*
+     * A pattern is written like the literal it matches, with
+     * identifiers binding the parts they stand in for and
+     * '_' matching anything without binding it. Arrays and
+     * hashes give ARRAY_DECL and HASH_DECL nodes as their
+     * literals do, a hash pattern keying on constants only.
+     */
+    fn pattern(&mut self) -> ParseResult
     {
-        if !self.in_subroutine {
-            self.error("'return' outside subroutine");
+        match self.peek_current() {
+            IDENT if self.current.string() == "_" => {
+                self.current.token_type = WILDCARD;
+                return Ok(Node::new(self.take_token()));
+            },
+            LBRACK => return self.array_pattern(),
+            LBRACE => return self.hash_pattern(),
+            IDENT => return Ok(Node::new(self.take_token())),
+            _ => return self.constant_pattern(),
         }
-        let mut node = Node::new(self.current.clone());
-        self.next_token();
+    }
 
-        let token_type = self.peek_current();
+    // A literal, or a negated number, matched by equality.
+    fn constant_pattern(&mut self) -> ParseResult
+    {
+        if self.peek_current() == MINUS {
+            match self.peek_next() {
+                INTEGER | FLOAT => {
+                    self.current.token_type = NEGATE;
+                    let mut node = Node::new(self.take_token());
+                    node.add_child(Node::new(self.take_token()));
+                    return Ok(node);
+                },
+                _ => (),
+            }
+        }
+        return match self.peek_current() {
+            STRING | INTEGER | FLOAT | TRUE | FALSE | NIL => {
+                Ok(Node::new(self.take_token()))
+            },
+            _ => Err(self.error("expected pattern")),
+        }
+    }
 
-        if token_type != NEWLINE && token_type != SEMICOLON &&
-           token_type != EOF {
-            node.add_child(self.expr());
+    fn array_pattern(&mut self) -> ParseResult
+    {
+        let mut node = gen_imag_node!("ARRAY_DECL", ARRAY_DECL,
+                                       self.current.line_num,
+                                       self.current.line_pos,
+                                       self.current.start);
+        let opener = self.current.clone();
+        self.next_and_skip_newlines();
+        while self.peek_current() != RBRACK {
+            node.add_child(self.pattern()?);
+            self.skip_newlines();
+            if self.peek_current() != COMMA {
+                break;
+            }
+            self.next_and_skip_newlines();
         }
-        return node;
+        self.close(&opener, "expected ']' to close array pattern")?;
+
+        return Ok(node);
     }
 
-    fn expr_statement(&mut self) -> Box<Node>
+    fn hash_pattern(&mut self) -> ParseResult
     {
-        let node = self.expr();
+        let mut node = gen_imag_node!("HASH_DECL", HASH_DECL,
+                                       self.current.line_num,
+                                       self.current.line_pos,
+                                       self.current.start);
+        let opener = self.current.clone();
+        self.next_and_skip_newlines();
+        while self.peek_current() != RBRACE {
+            let mut elem = gen_imag_node!("HASH_ELEM", HASH_ELEM,
+                                           self.current.line_num,
+                                           self.current.line_pos,
+                                           self.current.start);
+            elem.add_child(self.constant_pattern()?);
+            self.match_and_skip_newlines(ASSIGN_ARROW, "expected '=>'")?;
+            elem.add_child(self.pattern()?);
+            node.add_child(elem);
+            self.skip_newlines();
+            if self.peek_current() != COMMA {
+                break;
+            }
+            self.next_and_skip_newlines();
+        }
+        self.close(&opener, "expected '}' to close hash pattern")?;
 
-        return node;
+        return Ok(node);
     }
 
-    fn block(&mut self) -> Box<Node>
+    fn check_reachable(&mut self, arms: &[Box<Node>], arm: &Node)
     {
-        self.skip_newlines();
-        self.__match(LBRACE, "expected '{' to open block");
+        let pattern = &arm.children[0];
+        for earlier in arms {
+            let other = &earlier.children[0];
+            let message = match other.get_type() {
+                WILDCARD | IDENT => {
+                    format!("unreachable match arm, the pattern '{}' on \
+                             line {} matches everything", other.string(),
+                            other.token.line_num)
+                },
+                _ if other.structurally_eq(pattern) => {
+                    format!("unreachable match arm, the same pattern is \
+                             matched on line {}", other.token.line_num)
+                },
+                _ => continue,
+            };
+            self.diagnostic(Severity::Warning, pattern.token.line_num,
+                            pattern.token.line_pos, message);
+            return;
+        }
+    }
 
-        let mut node = gen_imag_node!("BLOCK", BLOCK,
+    /*
+     * const name = value, a binding which can not be
+     * assigned to again. That is enforced by resolve, to the
+     * parser it is an assignment to a plain name.
+     */
+    fn const_statement(&mut self) -> ParseResult
+    {
+        let mut node = gen_imag_node!("CONST_DECL", CONST_DECL,
                                        self.current.line_num,
-                                       self.current.line_pos);
+                                       self.current.line_pos,
+                                       self.current.start);
+        self.next_token();
+        if self.peek_current() != IDENT {
+            return Err(self.error("expected identifier"));
+        }
+        node.add_child(Node::new(self.take_token()));
+        self.__match(ASSIGN, "expected '=' after constant name")?;
         self.skip_newlines();
-        while self.peek_current() != RBRACE &&
-              self.peek_current() != EOF {
-            node.add_child(self.statement());
+        node.add_child(self.expr()?);
 
-            self.block_trailer();
-        }
-        self.__match(RBRACE, "expected '}' to close block");
+        return Ok(node);
+    }
+
+    /*
+     * import name, or import 'name' for a name which is not
+     * an identifier. Either way the name becomes a MODULE_REF
+     * node, whose value is the name as a string.
+     */
+    fn import_statement(&mut self) -> ParseResult
+    {
+        let mut node = Node::new(self.take_token());
 
-        return node;
+        if self.peek_current() != IDENT && self.peek_current() != STRING {
+            return Err(self.error("expected module name"));
+        }
+        let mut module = self.take_token();
+        let name = module.string().to_string();
+        module.token_type = MODULE_REF;
+        module.value = Value::StringValue(name.clone());
+        node.add_child(Node::new(module));
+
+        if !self.imports.contains(&name) {
+            self.imports.push(name);
+        }
+        return Ok(node);
     }
 
-    fn expr(&mut self) -> Box<Node>
+    fn debug_statement(&mut self) -> ParseResult
     {
-        return self.assignment_expr();
+        /*
+         * The debug statement is just a statement that
+         * outputs information about an ares object. This
+         * will help to verify values during development. The
+         * DEBUG node has the expression as its only child,
+         * the interpreter writes its value out.
+         */
+        generic_block!(self);
     }
-    
-    fn assignment_expr(&mut self) -> Box<Node>
+
+    /*
+     * assert <expr> or assert <expr>, <expr>, the ASSERT node
+     * holds the condition and the optional message. A falsy
+     * condition is meant to fail the program with the message,
+     * the interpreter does not run asserts yet.
+     */
+    fn assert_statement(&mut self) -> ParseResult
     {
-        let mut left = self.range_expr();
-        if self.peek_current() == ASSIGN {
-            match left.get_type() {
-                SUBSCRIPT | IDENT => (),
-                _ => self.error(""),
-            }
-            let op_node = Node::new(self.current.clone());
-            left = left.get_root(op_node);
+        let mut node = Node::new(self.take_token());
 
+        node.add_child(self.expr()?);
+        if self.peek_current() == COMMA {
             self.next_and_skip_newlines();
-            left.add_child(self.range_expr());
+            node.add_child(self.expr()?);
         }
-        return left;
+        return Ok(node);
     }
 
-    fn range_expr(&mut self) -> Box<Node>
+    fn return_statement(&mut self) -> ParseResult
     {
-        let mut left = self.or_expr();
-        while self.peek_current() == DOTDOT {
-            let op_node = Node::new(self.current.clone());
-            left = left.get_root(op_node);
+        if !self.in_subroutine {
+            return Err(self.error("'return' outside subroutine"));
+        }
+        let mut node = Node::new(self.take_token());
 
-            self.next_and_skip_newlines();
-            left.add_child(self.or_expr());
+        let token_type = self.peek_current();
+
+        if token_type != NEWLINE && token_type != SEMICOLON &&
+           token_type != EOF {
+            node.add_child(self.expr()?);
         }
-        return left;
+        return Ok(node);
     }
 
-    fn or_expr(&mut self) -> Box<Node>
+    /*
+     * 'yield <expr>', which hands a value to whoever is
+     * iterating the subroutine. A bare yield yields nil.
+     */
+    fn yield_statement(&mut self) -> ParseResult
     {
-        let mut left = self.and_expr();
-        while self.peek_current() == LOGICAL_OR {
-            let op_node = Node::new(self.current.clone());
-            left = left.get_root(op_node);
+        if !self.in_subroutine {
+            return Err(self.error("'yield' outside subroutine"));
+        }
+        let mut node = Node::new(self.take_token());
 
-            self.next_and_skip_newlines();
-            left.add_child(self.and_expr());
+        let token_type = self.peek_current();
+
+        if token_type != NEWLINE && token_type != SEMICOLON &&
+           token_type != EOF {
+            node.add_child(self.expr()?);
         }
-        return left;
+        return Ok(node);
     }
 
-    fn and_expr(&mut self) -> Box<Node>
+    fn expr_statement(&mut self) -> ParseResult
     {
-        let mut left = self.eql_expr();
-        while self.peek_current() == LOGICAL_AND {
-            let op_node = Node::new(self.current.clone());
-            left = left.get_root(op_node);
+        let grouped = self.peek_current() == LPAREN;
+        let node = self.expr()?;
 
-            self.next_and_skip_newlines();
-            left.add_child(self.eql_expr());
+        if self.peek_current() == COMMA && !node.get_type().is_assignment() {
+            return self.multi_assignment(node, grouped);
         }
-        return left;
+        /*
+         * A literal on its own does nothing, unless it ends a
+         * block and so gives the block its value, as in
+         * 'x = do { 1 }'.
+         */
+        match node.get_type() {
+            STRING | INTEGER | FLOAT | TRUE | FALSE | NIL | REGEX => {
+                if !self.ends_block() {
+                    self.diagnostic(Severity::Warning, node.token.line_num,
+                                    node.token.line_pos,
+                                    "statement has no effect".to_string());
+                }
+            },
+            _ => (),
+        }
+        return Ok(node);
     }
 
-    fn eql_expr(&mut self) -> Box<Node>
+    // Whether only newlines and ';' are left before a '}'.
+    fn ends_block(&mut self) -> bool
     {
-        let mut left = self.comp_expr();
-        while self.peek_current() == EQL ||
-              self.peek_current() == NOT_EQL {
-            let op_node = Node::new(self.current.clone());
-            left = left.get_root(op_node);
-
-            self.next_and_skip_newlines();
-            left.add_child(self.comp_expr());
+        let mut n = 0;
+        loop {
+            match self.peek(n) {
+                NEWLINE | SEMICOLON => n += 1,
+                token_type => return token_type == RBRACE,
+            }
         }
-        return left;
     }
 
-    fn comp_expr(&mut self) -> Box<Node>
+    /*
+     * 'a, b = 1, 2', which is only read as a statement since
+     * elsewhere the commas separate arguments or elements.
+     * The values are all evaluated before any is assigned,
+     * so 'a, b = b, a' swaps. The parser does not hold the
+     * number of targets to that of the values, running it
+     * does.
+     */
+    fn multi_assignment(&mut self, first: Box<Node>, grouped: bool)
+        -> ParseResult
     {
-        let mut left = self.bit_or_expr();
-        while is_between!(self.peek_current(), LT, GE) {
-            let op_node = Node::new(self.current.clone());
-            left = left.get_root(op_node);
+        let mut targets = gen_imag_node!("ASSIGN_TARGETS", ASSIGN_TARGETS,
+                                          first.token.line_num,
+                                          first.token.line_pos,
+                                          first.token.start);
+        self.check_target(&first, grouped)?;
+        targets.add_child(first);
+        while self.peek_current() == COMMA {
+            self.next_token();
+            let grouped = self.peek_current() == LPAREN;
+            let target = self.ternary_expr()?;
+            self.check_target(&target, grouped)?;
+            targets.add_child(target);
+        }
+        if self.peek_current() != ASSIGN {
+            return Err(self.error("expected '=' after assignment targets"));
+        }
+        let mut node = gen_imag_node!("MULTI_ASSIGN", MULTI_ASSIGN,
+                                       self.current.line_num,
+                                       self.current.line_pos,
+                                       self.current.start);
+        self.next_token();
+        self.skip_newlines();
 
-            self.next_and_skip_newlines();
-            left.add_child(self.bit_or_expr());
+        let mut values = gen_imag_node!("ASSIGN_VALUES", ASSIGN_VALUES,
+                                         self.current.line_num,
+                                         self.current.line_pos,
+                                         self.current.start);
+        loop {
+            values.add_child(self.expr()?);
+            if self.peek_current() != COMMA {
+                break;
+            }
+            self.next_token();
         }
-        return left;
+        node.add_child(targets);
+        node.add_child(values);
+
+        return Ok(node);
     }
 
-    fn bit_or_expr(&mut self) -> Box<Node>
+    /*
+     * Only a variable, subscript or member can be assigned
+     * to. grouped tells whether target started with a '('.
+     */
+    fn check_target(&self, target: &Node, grouped: bool)
+        -> Result<(), ParseError>
     {
-        let mut left = self.xor_expr();
-        while self.peek_current() == BITWISE_OR {
-            let op_node = Node::new(self.current.clone());
-            left = left.get_root(op_node);
-
-            self.next_and_skip_newlines();
-            left.add_child(self.xor_expr());
+        return match target.get_type() {
+            // '(a) = 1', the grouping is all that is left of it.
+            IDENT if grouped => {
+                Err(self.error("cannot assign to parenthesized expression"))
+            },
+            SUBSCRIPT | MEMBER | IDENT => Ok(()),
+            token_type => {
+                let message = invalid_target(token_type);
+                Err(self.error(message.as_str()))
+            },
         }
-        return left;
     }
 
-    fn xor_expr(&mut self) -> Box<Node>
+    fn block(&mut self) -> ParseResult
     {
-        let mut left = self.bit_and_expr();
-        while self.peek_current() == BITWISE_XOR {
-            let op_node = Node::new(self.current.clone());
-            left = left.get_root(op_node);
+        self.skip_newlines();
+        let opener = self.current.clone();
+        self.__match(LBRACE, "expected '{' to open block")?;
+        self.enter("block")?;
 
-            self.next_and_skip_newlines();
-            left.add_child(self.bit_and_expr());
+        let mut node = gen_imag_node!("BLOCK", BLOCK,
+                                       self.current.line_num,
+                                       self.current.line_pos,
+                                       self.current.start);
+        self.skip_newlines();
+        let mut returned = false;
+        while self.peek_current() != RBRACE &&
+              self.peek_current() != EOF {
+            let (line_num, line_pos) = (self.current.line_num,
+                                        self.current.line_pos);
+            match self.block_statement() {
+                Ok(statement) => {
+                    if returned {
+                        self.diagnostic(Severity::Warning, line_num, line_pos,
+                                        "unreachable code after \
+                                         return".to_string());
+                    }
+                    returned = !returned && statement.get_type() == RETURN;
+                    node.add_child(statement);
+                },
+                Err(error) => self.recover(error),
+            }
         }
-        return left;
+        self.depth -= 1;
+        if node.children.len() == 0 {
+            self.diagnostic(Severity::Note, opener.line_num,
+                            opener.line_pos, "empty block".to_string());
+        }
+        self.close(&opener, "expected '}' to close block")?;
+
+        return Ok(node);
     }
 
-    fn bit_and_expr(&mut self) -> Box<Node>
+    fn expr(&mut self) -> ParseResult
+    {
+        return self.assignment_expr();
+    }
+    
+    /*
+     * Assignment is right associative, 'a = b = 1' assigns
+     * 1 to b and then the result to a, so the right hand
+     * side recurses back into assignment_expr.
+     *
+     * A compound assignment keeps its operator token, 'x <<= 2'
+     * is (<<= x 2) and the interpreter applies the operator.
+     */
+    fn assignment_expr(&mut self) -> ParseResult
     {
-        let mut left = self.shift_expr();
-        while self.peek_current() == BITWISE_AND {
-            let op_node = Node::new(self.current.clone());
+        let grouped = self.peek_current() == LPAREN;
+        let mut left = self.ternary_expr()?;
+        if self.peek_current().is_assignment() {
+            self.check_target(&left, grouped)?;
+            let op_node = Node::new(self.take_token());
             left = left.get_root(op_node);
 
-            self.next_and_skip_newlines();
-            left.add_child(self.shift_expr());
+            self.skip_newlines();
+            self.enter("expression")?;
+            let right = self.assignment_expr();
+            self.depth -= 1;
+            left.add_child(right?);
         }
-        return left;
+        return Ok(left);
     }
 
-    fn shift_expr(&mut self) -> Box<Node>
+    /*
+     * cond ? a : b, the else branch recurses so nested
+     * ternaries associate to the right, ie. 'a ? b : c ? d : e'
+     * is read as 'a ? b : (c ? d : e)'.
+     */
+    fn ternary_expr(&mut self) -> ParseResult
     {
-        let mut left = self.arith_expr();
-        while self.peek_current() == LEFT_SHIFT ||
-              self.peek_current() == RIGHT_SHIFT {
-            let op_node = Node::new(self.current.clone());
-            left = left.get_root(op_node);
-
-            self.next_and_skip_newlines();
-            left.add_child(self.arith_expr());
+        let left = self.range_expr()?;
+        if self.peek_current() != QUESTION {
+            return Ok(left);
         }
-        return left;
+        let mut node = gen_imag_node!("TERNARY", TERNARY,
+                                       self.current.line_num,
+                                       self.current.line_pos,
+                                       self.current.start);
+        node = left.get_root(node);
+        self.next_and_skip_newlines();
+
+        self.enter("expression")?;
+        let result = self.ternary_branches(node);
+        self.depth -= 1;
+
+        return result;
     }
 
-    fn arith_expr(&mut self) -> Box<Node>
+    fn ternary_branches(&mut self, mut node: Box<Node>) -> ParseResult
     {
-        let mut left = self.term_expr();
-        while self.peek_current() == PLUS ||
-              self.peek_current() == MINUS {
-            let op_node = Node::new(self.current.clone());
-            left = left.get_root(op_node);
+        node.add_child(self.ternary_expr()?);
+        self.skip_newlines();
+        self.match_and_skip_newlines(COLON, "expected ':' in \
+                                     conditional expression")?;
+        node.add_child(self.ternary_expr()?);
+
+        return Ok(node);
+    }
 
+    /*
+     * start..end or start..end..step, both build a RANGE node
+     * with the step as an optional third child. Ranges do not
+     * chain, a further '..' is an error.
+     *
+     * Subscript parses its index with slice_bounds set, the
+     * first range_expr reached is the one directly within the
+     * brackets and it alone may leave out the start, the end
+     * or both, 'a[..n]', 'a[n..]' or 'a[..]'. Any range nested
+     * deeper, as in 'a[x ? 1 : 2..]', must be complete.
+     */
+    fn range_expr(&mut self) -> ParseResult
+    {
+        let slice_bounds = mem::replace(&mut self.slice_bounds, false);
+        let left = if slice_bounds && self.peek_current() == DOTDOT {
+            self.open_bound()
+        }
+        else {
+            self.parse_binary(0)?
+        };
+        if self.peek_current() != DOTDOT {
+            return Ok(left);
+        }
+        let mut node = gen_imag_node!("RANGE", RANGE,
+                                       self.current.line_num,
+                                       self.current.line_pos,
+                                       self.current.start);
+        node = left.get_root(node);
+
+        self.next_and_skip_newlines();
+        if slice_bounds && self.peek_current() == RBRACK {
+            node.add_child(self.open_bound());
+            return Ok(node);
+        }
+        node.add_child(self.parse_binary(0)?);
+        if self.peek_current() == DOTDOT {
             self.next_and_skip_newlines();
-            left.add_child(self.term_expr());
+            node.add_child(self.parse_binary(0)?);
+        }
+        if self.peek_current() == DOTDOT {
+            return Err(self.error("a range takes at most a step"));
         }
-        return left;
+        return Ok(node);
     }
 
-    fn term_expr(&mut self) -> Box<Node>
+    fn open_bound(&self) -> Box<Node>
     {
-        let mut left = self.factor_expr();
-        while is_between!(self.peek_current(), MUL,
-                          MODULO) {
-            let op_node = Node::new(self.current.clone());
-            left = left.get_root(op_node);
+        return gen_imag_node!("OPEN_BOUND", OPEN_BOUND,
+                              self.current.line_num,
+                              self.current.line_pos,
+                              self.current.start);
+    }
 
-            self.next_and_skip_newlines();
-            left.add_child(self.factor_expr());
+    /*
+     * The binary operators from '||' up to '*', '/' and '%'
+     * are parsed by precedence climbing, driven by the
+     * binding powers in binding_power. Each loop takes the
+     * operators binding at least as tightly as min_bp and
+     * parses their right operand with the operator's right
+     * binding power, so a new operator only needs an entry
+     * in the table.
+     *
+     * Two operators need a little more. 'in' gives an IN_TEST
+     * node and is left alone within a for loop's iterable, so
+     * 'for x in y in z {}' is an error rather than a
+     * membership test unless parenthesized. A run of
+     * comparisons chains, 'a < b <= c' meaning
+     * 'a < b && b <= c' with b evaluated once, and gives a
+     * CHAIN_CMP node holding a followed by the operator nodes,
+     * each of which holds its right operand,
+     * (CHAIN_CMP a (< b) (<= c)).
+     */
+    fn parse_binary(&mut self, min_bp: u8) -> ParseResult
+    {
+        let mut left = self.factor_expr()?;
+        let mut chained = false;
+
+        loop {
+            let op = self.peek_current();
+            let (left_bp, right_bp) = match binding_power(op) {
+                Some(bp) => bp,
+                None => break,
+            };
+            if left_bp < min_bp || (op == IN && self.no_in) {
+                break;
+            }
+            let mut op_node = match op {
+                IN => {
+                    let node = gen_imag_node!("IN_TEST", IN_TEST,
+                                               self.current.line_num,
+                                               self.current.line_pos,
+                                               self.current.start);
+                    self.next_token();
+                    node
+                },
+                _ => Node::new(self.take_token()),
+            };
+            self.skip_newlines();
+            let right = self.parse_binary(right_bp)?;
+
+            let comparison = is_between!(op, LT, GE);
+            if comparison && chained {
+                if left.get_type() != CHAIN_CMP {
+                    let mut chain = gen_imag_node!("CHAIN_CMP", CHAIN_CMP,
+                                                    left.token.line_num,
+                                                    left.token.line_pos,
+                                                    left.token.start);
+                    chain.add_child(left.children.remove(0));
+                    chain.add_child(left);
+                    left = chain;
+                }
+                op_node.add_child(right);
+                left.add_child(op_node);
+            }
+            else {
+                left = left.get_root(op_node);
+                left.add_child(right);
+            }
+            chained = comparison;
         }
-        return left;
+        return Ok(left);
     }
 
-    fn factor_expr(&mut self) -> Box<Node>
+    fn factor_expr(&mut self) -> ParseResult
     {
         if self.is_factor() {
             /*
              * is_factor uses a '-' (minus) tokentype to verify
              * if it is a factor unit, amongst other types. If this
              * is the case, change the type into imaginary
-             * TokenType::NEGATE.
+             * TokenType::NEGATE, likewise a '+' into UNARY_PLUS.
              */
-            if self.peek_current() == MINUS {
-                self.current.token_type = NEGATE;
+            match self.peek_current() {
+                MINUS => self.current.token_type = NEGATE,
+                PLUS  => self.current.token_type = UNARY_PLUS,
+                _ => (),
             }
-            let mut left = Node::new(self.current.clone());
-            self.next_and_skip_newlines();
-            if self.is_factor() {
+            let mut left = Node::new(self.take_token());
+            self.skip_newlines();
+            self.enter("expression")?;
+            let operand = if self.is_factor() {
                 // Recurse factor units
-                left.add_child(self.factor_expr());
+                self.factor_expr()
             }
             else {
-                left.add_child(self.trailer_expr());
-            }
-            return left;
+                self.trailer_expr()
+            };
+            self.depth -= 1;
+            left.add_child(operand?);
+
+            return Ok(left);
         }
         return self.trailer_expr();
     }
 
-    fn trailer_expr(&mut self) -> Box<Node>
+    fn trailer_expr(&mut self) -> ParseResult
+    {
+        self.enter("expression")?;
+        let result = self.trailer_unit();
+        self.depth -= 1;
+
+        return result;
+    }
+
+    fn trailer_unit(&mut self) -> ParseResult
     {
-        let mut left = self.atom();
+        let mut left = self.atom()?;
         loop {
             if self.peek_current() == LBRACK {
-                left = self.subscript(left);
+                left = self.subscript(left)?;
             }
             else if self.peek_current() == LPAREN {
-                left = self.call_literal(left);
+                left = self.call_literal(left)?;
+            }
+            else if self.peek_current() == DOT {
+                left = self.member(left)?;
             }
             else {
                 break;
             }
         }
-        return left;
+        return Ok(left);
     }
     
-    fn atom(&mut self) -> Box<Node>
+    fn atom(&mut self) -> ParseResult
     {
         /*
          * Since we are using homogenous nodes which uses
@@ -695,122 +1857,500 @@ impl<'a> Parser<'a>
 
         match self.peek_current() {
             STRING | INTEGER | FLOAT | TRUE | FALSE | NIL |
-            IDENT  => {
-                node = Node::new(self.current.clone());
-                self.next_token();
+            REGEX  | IDENT  => {
+                node = Node::new(self.take_token());
             },
-            LBRACK => node = self.array_literal(),
-            LBRACE => node = self.hash_literal(),
-            LPAREN => node = self.grouping(),
-            DEF    => node = self.def_statement(true),
-            _      => self.error("expected expression"),
+            LBRACK => node = self.array_literal()?,
+            LBRACE => {
+                if self.is_hash_literal() {
+                    node = self.hash_literal()?;
+                }
+                else {
+                    node = self.block()?;
+                }
+            },
+            LPAREN => {
+                /*
+                 * In a hash key '(a) => 1' is a key and its value
+                 * rather than an arrow literal, it has to be
+                 * bracketed as '((a) => 1)' to make it a key.
+                 */
+                if !self.no_arrow && self.is_arrow_literal() {
+                    node = self.arrow_literal()?;
+                }
+                else {
+                    node = self.grouping()?;
+                }
+            },
+            DEF    => node = self.def_statement(true)?,
+            DO     => node = self.do_expr()?,
+            _      => return Err(self.error("expected expression")),
         }
-        return node;
+        return Ok(node);
     }
     
-    fn grouping(&mut self) -> Box<Node>
+    /*
+     * A '{' in expression position starts a hash literal when
+     * it is empty, '{}', or when its first element is followed
+     * by '=>', as in '{ 1 => 2 }'. Anything else, such as
+     * '{ x = 1 }', is a block. Since a bare statement is parsed
+     * as an expression, '{}' on its own line is an empty hash,
+     * not an empty block.
+     *
+     * A bracketed group later in the element and followed by
+     * '=>' is an arrow literal, so in '{ y = (a) => a }' the
+     * '=>' belongs to the arrow and the '{' opens a block. At
+     * the start of the element, '{ (a) => 1 }', it is the key.
+     */
+    fn is_hash_literal(&mut self) -> bool
+    {
+        let mut n = 1;
+        let mut depth = 0;
+        // The token before the current one at depth 0.
+        let mut last = LBRACE;
+        let mut arrow_params = false;
+
+        while self.peek(n) == NEWLINE {
+            n += 1;
+        }
+        if self.peek(n) == RBRACE {
+            return true;
+        }
+        loop {
+            let token_type = self.peek(n);
+            match token_type {
+                LPAREN | LBRACK | LBRACE => {
+                    if depth == 0 {
+                        arrow_params = token_type == LPAREN &&
+                                       last != LBRACE &&
+                                       !last.is_literal() &&
+                                       last != RPAREN && last != RBRACK &&
+                                       last != RBRACE;
+                    }
+                    depth += 1;
+                },
+                RPAREN | RBRACK | RBRACE => {
+                    if depth == 0 {
+                        return false;
+                    }
+                    depth -= 1;
+                },
+                ASSIGN_ARROW => {
+                    if depth == 0 && !(last == RPAREN && arrow_params) {
+                        return true;
+                    }
+                },
+                NEWLINE | SEMICOLON | COMMA => {
+                    if depth == 0 {
+                        return false;
+                    }
+                },
+                EOF => return false,
+                _ => (),
+            }
+            if depth == 0 {
+                last = token_type;
+            }
+            n += 1;
+        }
+    }
+
+    /*
+     * A do block is an expression which runs its statements
+     * in a scope of its own and yields the value of the
+     * last one, eg. 'x = do { y = 2; y * 2 }'.
+     */
+    fn do_expr(&mut self) -> ParseResult
     {
+        let mut node = gen_imag_node!("DO_EXPR", DO_EXPR,
+                                       self.current.line_num,
+                                       self.current.line_pos,
+                                       self.current.start);
         self.next_token();
-        let node = self.expr();
-        self.__match(RPAREN, "expected ')'");
+        node.add_child(self.block()?);
 
-        return node;
+        return Ok(node);
     }
 
-    fn subscript(&mut self, left: Box<Node>) -> Box<Node>
+    /*
+     * Whether the '(' under the cursor opens the parameter
+     * list of an arrow literal, that is whether its matching
+     * ')' is directly followed by '=>'.
+     */
+    fn is_arrow_literal(&mut self) -> bool
     {
-        let mut node = gen_imag_node!("SUBSCRIPT", SUBSCRIPT,
+        let mut n = 1;
+        let mut depth = 0;
+
+        loop {
+            match self.peek(n) {
+                LPAREN | LBRACK | LBRACE => depth += 1,
+                RPAREN | RBRACK | RBRACE => {
+                    if depth == 0 {
+                        return self.peek(n) == RPAREN &&
+                               self.peek(n + 1) == ASSIGN_ARROW;
+                    }
+                    depth -= 1;
+                },
+                EOF => return false,
+                _ => (),
+            }
+            n += 1;
+        }
+    }
+
+    /*
+     * (params) => expr or (params) => { ... }, shorthand for
+     * a subroutine literal. An expression body is returned,
+     * so '(a) => a + 1' gives the same tree as
+     * 'def(a) { return a + 1 }'. A body in braces is read
+     * like any other '{', '(a) => {}' returns an empty hash.
+     */
+    fn arrow_literal(&mut self) -> ParseResult
+    {
+        let opener = self.current.clone();
+        let mut node = gen_imag_node!("SUB_LITERAL", SUB_LITERAL,
                                        self.current.line_num,
-                                       self.current.line_pos);
-        node = left.get_root(node);
-        
+                                       self.current.line_pos,
+                                       self.current.start);
+        self.next_and_skip_newlines();
+
+        let mut params = gen_imag_node!("SUB_PARAMS", SUB_PARAMS,
+                                         self.current.line_num,
+                                         self.current.line_pos,
+                                         self.current.start);
+        for n in self.parameter_list()? {
+            params.add_child(n);
+        }
+        self.skip_newlines();
+        self.close(&opener, "expected ')' to close parameter list")?;
+        self.match_and_skip_newlines(ASSIGN_ARROW, "expected '=>'")?;
+
+        node.add_child(params);
+
+        let in_subroutine = self.in_subroutine;
+        self.in_subroutine = true;
+        let body = self.arrow_body();
+        self.in_subroutine = in_subroutine;
+
+        node.add_child(body?);
+
+        return Ok(node);
+    }
+
+    fn arrow_body(&mut self) -> ParseResult
+    {
+        if self.peek_current() == LBRACE && !self.is_hash_literal() {
+            return self.block();
+        }
+        let mut node = gen_imag_node!("BLOCK", BLOCK,
+                                       self.current.line_num,
+                                       self.current.line_pos,
+                                       self.current.start);
+        let mut ret = gen_imag_node!("return", RETURN,
+                                      self.current.line_num,
+                                      self.current.line_pos,
+                                      self.current.start);
+        ret.add_child(self.expr()?);
+        node.add_child(ret);
+
+        return Ok(node);
+    }
+
+    fn grouping(&mut self) -> ParseResult
+    {
+        let opener = self.current.clone();
         self.next_and_skip_newlines();
-        node.add_child(self.expr());
+        let (no_in, no_arrow) = (self.no_in, self.no_arrow);
+        self.no_in = false;
+        self.no_arrow = false;
+        let node = self.expr();
+        self.no_in = no_in;
+        self.no_arrow = no_arrow;
+        let node = node?;
         self.skip_newlines();
+        self.close(&opener, "expected ')'")?;
 
-        self.__match(RBRACK, "expected ']' to close subscript");
+        return Ok(node);
+    }
 
-        return node;
+    /*
+     * a[index] gives a SUBSCRIPT node, a[range] a SLICE, see
+     * range_expr for the ranges a slice accepts.
+     */
+    fn subscript(&mut self, left: Box<Node>) -> ParseResult
+    {
+        let (line_num, line_pos, start) = (self.current.line_num,
+                                           self.current.line_pos,
+                                           self.current.start);
+        let opener = self.current.clone();
+        self.next_and_skip_newlines();
+        self.slice_bounds = true;
+        let index = self.expr()?;
+
+        let mut node = match index.get_type() {
+            RANGE => gen_imag_node!("SLICE", SLICE, line_num, line_pos,
+                                    start),
+            _ => gen_imag_node!("SUBSCRIPT", SUBSCRIPT, line_num,
+                                line_pos, start),
+        };
+        node = left.get_root(node);
+        node.add_child(index);
+        self.skip_newlines();
+
+        self.close(&opener, "expected ']' to close subscript")?;
+
+        return Ok(node);
+    }
+
+    fn member(&mut self, left: Box<Node>) -> ParseResult
+    {
+        let mut node = gen_imag_node!("MEMBER", MEMBER,
+                                       self.current.line_num,
+                                       self.current.line_pos,
+                                       self.current.start);
+        node = left.get_root(node);
+        self.next_token();
+
+        if self.peek_current() != IDENT {
+            return Err(self.error("expected identifier after '.'"));
+        }
+        node.add_child(Node::new(self.take_token()));
+
+        return Ok(node);
     }
 
-    fn call_literal(&mut self, left: Box<Node>) -> Box<Node>
+    fn call_literal(&mut self, left: Box<Node>) -> ParseResult
     {
         let mut node = gen_imag_node!("CALL", CALL,
                                        self.current.line_num,
-                                       self.current.line_pos);
+                                       self.current.line_pos,
+                                       self.current.start);
         node = left.get_root(node);
+        let opener = self.current.clone();
         self.next_and_skip_newlines();
 
-        for n in self.expression_list(RPAREN) {
+        for n in self.argument_list()? {
             node.add_child(n);
         }
         self.skip_newlines();
-        self.__match(RPAREN, "expected ')' to close the function call");
+        self.close(&opener, "expected ')' to close the function call")?;
 
-        return node;
+        return Ok(node);
     }
 
-    fn array_literal(&mut self) -> Box<Node>
+    fn array_literal(&mut self) -> ParseResult
     {
         let mut node = gen_imag_node!("ARRAY_DECL",
                                        ARRAY_DECL,
                                        self.current.line_num,
-                                       self.current.line_pos);
+                                       self.current.line_pos,
+                                       self.current.start);
+        let opener = self.current.clone();
         self.next_and_skip_newlines();
-        for n in self.expression_list(RBRACK) {
-            node.add_child(n);
+        if self.peek_current() != RBRACK {
+            let first = self.expr()?;
+            self.skip_newlines();
+            if self.peek_current() == FOR {
+                let mut comp = gen_imag_node!("ARRAY_COMP", ARRAY_COMP,
+                                               node.token.line_num,
+                                               node.token.line_pos,
+                                               node.token.start);
+                comp.add_child(first);
+                self.comprehension(&mut comp)?;
+                self.skip_newlines();
+                self.close(&opener, "expected ']' to close array \
+                                     comprehension")?;
+                return Ok(comp);
+            }
+            node.add_child(first);
+            if self.peek_current() == COMMA {
+                self.next_and_skip_newlines();
+                for n in self.expression_list(RBRACK)? {
+                    node.add_child(n);
+                }
+            }
         }
         self.skip_newlines();
-        self.__match(RBRACK, "expected ']' to close array literal");
+        self.close(&opener, "expected ']' to close array literal")?;
 
-        return node;
+        return Ok(node);
     }
 
-    fn hash_literal(&mut self) -> Box<Node>
+    /*
+     * The clause of a comprehension following its element,
+     * 'for a, b in iterable if filter', the filter being
+     * optional. The loop variables are gathered under a
+     * COMP_VARS node, followed by the iterable and an 'if'
+     * node holding the filter, if any.
+     */
+    fn comprehension(&mut self, node: &mut Box<Node>)
+        -> Result<(), ParseError>
+    {
+        let mut vars = gen_imag_node!("COMP_VARS", COMP_VARS,
+                                       self.current.line_num,
+                                       self.current.line_pos,
+                                       self.current.start);
+        self.next_token();
+        loop {
+            if self.peek_current() != IDENT {
+                return Err(self.error("expected identifier"));
+            }
+            vars.add_child(Node::new(self.take_token()));
+            if self.peek_current() != COMMA {
+                break;
+            }
+            self.next_token();
+        }
+        node.add_child(vars);
+
+        self.__match(IN, "expected keyword 'in' before expression")?;
+        let no_in = self.no_in;
+        self.no_in = true;
+        let iterable = self.expr();
+        self.no_in = no_in;
+        node.add_child(iterable?);
+
+        if self.peek_current() == IF {
+            let mut filter = Node::new(self.take_token());
+            filter.add_child(self.expr()?);
+            node.add_child(filter);
+        }
+        return Ok(());
+    }
+
+    fn hash_literal(&mut self) -> ParseResult
     {
         let mut node = gen_imag_node!("HASH_DECL", HASH_DECL,
                                        self.current.line_num,
-                                       self.current.line_pos);
+                                       self.current.line_pos,
+                                       self.current.start);
+        let opener = self.current.clone();
         self.next_and_skip_newlines();
         if self.peek_current() == RBRACE {
             self.next_token();
 
-            return node;
+            return Ok(node);
         }
         loop {
             let mut elem = gen_imag_node!("HASH_ELEM", HASH_ELEM,
                                            self.current.line_num,
-                                           self.current.line_pos);
-            elem.add_child(self.expr());
-            self.__match(ASSIGN_ARROW, "expected '=>'");
-            elem.add_child(self.expr());
+                                           self.current.line_pos,
+                                           self.current.start);
+            let no_arrow = mem::replace(&mut self.no_arrow, true);
+            let key = self.expr();
+            self.no_arrow = no_arrow;
+            elem.add_child(key?);
+            self.skip_newlines();
+            self.match_and_skip_newlines(ASSIGN_ARROW, "expected '=>'")?;
+            elem.add_child(self.expr()?);
+            self.skip_newlines();
+
+            if node.children.len() == 0 && self.peek_current() == FOR {
+                let mut comp = gen_imag_node!("HASH_COMP", HASH_COMP,
+                                               node.token.line_num,
+                                               node.token.line_pos,
+                                               node.token.start);
+                for child in elem.children {
+                    comp.add_child(child);
+                }
+                self.comprehension(&mut comp)?;
+                self.skip_newlines();
+                self.close(&opener, "expected '}' to close hash \
+                                     comprehension")?;
+                return Ok(comp);
+            }
 
             node.add_child(elem);
             if self.peek_current() != COMMA {
                 break;
             }
             self.next_and_skip_newlines();
+            /*
+             * Allow a trailing comma before the closing brace.
+             */
+            if self.peek_current() == RBRACE {
+                break;
+            }
         }
         self.skip_newlines();
-        self.__match(RBRACE, "expected '}' to close hash literal");
+        self.close(&opener, "expected '}' to close hash literal")?;
+
+        return Ok(node);
+    }
+
+    /*
+     * Arguments of a call, positional ones first and then
+     * keyword ones, 'name = value', which become KWARG nodes.
+     * An assignment can still be passed positionally by
+     * wrapping it in parentheses, 'f((a = 1))'.
+     */
+    fn argument_list(&mut self) -> Result<Vec<Box<Node>>, ParseError>
+    {
+        let mut sequence: Vec<Box<Node>> = Vec::new();
+        let mut keywords = false;
 
-        return node;
+        if self.peek_current() == RPAREN {
+            return Ok(sequence);
+        }
+        loop {
+            if self.peek_current() == IDENT && self.peek_next() == ASSIGN {
+                let mut node = gen_imag_node!("KWARG", KWARG,
+                                               self.current.line_num,
+                                               self.current.line_pos,
+                                               self.current.start);
+                node.add_child(Node::new(self.take_token()));
+                self.next_and_skip_newlines();
+                node.add_child(self.expr()?);
+                sequence.push(node);
+                keywords = true;
+            }
+            else if keywords {
+                return Err(self.error("positional argument follows \
+                                       keyword argument"));
+            }
+            else {
+                sequence.push(self.expr()?);
+            }
+            self.skip_newlines();
+            if self.peek_current() != COMMA {
+                break;
+            }
+            self.next_and_skip_newlines();
+            if self.peek_current() == RPAREN {
+                break;
+            }
+        }
+        return Ok(sequence);
     }
 
-    fn expression_list(&mut self, end: TokenType) -> Vec<Box<Node>>
+    /*
+     * Newlines are insignificant between an open bracket and its
+     * matching close, so every element of a list may be followed
+     * by newlines both before its comma and before the closer.
+     */
+    fn expression_list(&mut self, end: TokenType)
+        -> Result<Vec<Box<Node>>, ParseError>
     {
         let mut sequence: Vec<Box<Node>> = Vec::new();
 
         if self.peek_current() == end {
-            return sequence;
+            return Ok(sequence);
         }
         loop {
-            sequence.push(self.expr());
+            sequence.push(self.expr()?);
+            self.skip_newlines();
             if self.peek_current() != COMMA {
                 break;
             }
             self.next_and_skip_newlines();
+            /*
+             * Allow a trailing comma before the closing delimiter.
+             */
+            if self.peek_current() == end {
+                break;
+            }
         }
-        return sequence;
+        return Ok(sequence);
     }
 }
\ No newline at end of file
@@ -0,0 +1,1483 @@
+/*
+ * Test that the parser builds the expected trees,
+ * compared through their string tree form.
+ */
+use scanner::scanner::*;
+use parser::*;
+use module::Module;
+
+macro_rules! create_tests {
+    ($($input:expr, $tree:expr),+) => (
+        {
+            let tests = [
+            $(
+                TreeMatcher::new($input, $tree),
+            )+
+            ];
+            tests
+        }
+    );
+}
+
+pub struct TreeMatcher {
+    input: &'static str,
+    expected_tree: &'static str,
+}
+
+impl TreeMatcher
+{
+    fn new(input: &'static str, expected_tree: &'static str)
+        -> TreeMatcher
+    {
+        return TreeMatcher {
+            input: input,
+            expected_tree: expected_tree,
+        };
+    }
+
+    pub fn match_switch()
+    {
+        let tests = create_tests!(
+            "switch x {\ncase 1, 2:\ndebug x\ndefault:\ndebug 0\n}",
+            "(BLOCK (switch x (SWITCH_BRANCH (SWITCH_EXPRS 1 2) \
+             (BLOCK (debug x))) (BLOCK (debug 0))))",
+            "switch x { case 1: debug x; }",
+            "(BLOCK (switch x (SWITCH_BRANCH (SWITCH_EXPRS 1) \
+             (BLOCK (debug x)))))",
+            "switch x { case 1..10, 20: debug x; }",
+            "(BLOCK (switch x (SWITCH_BRANCH (SWITCH_EXPRS (RANGE 1 10) 20) \
+             (BLOCK (debug x)))))");
+        println!("Starting match_switch() test..");
+        TreeMatcher::__match(&tests);
+        println!("Ending match_switch() test..");
+    }
+
+    pub fn match_patterns()
+    {
+        let tests = create_tests!(
+            "match x {\n[a, b] => debug a\n{'k' => v} => { debug v }\n\
+             _ => debug x\n}",
+            "(BLOCK (match x (MATCH_ARM (ARRAY_DECL a b) (BLOCK (debug a))) \
+             (MATCH_ARM (HASH_DECL (HASH_ELEM k v)) (BLOCK (debug v))) \
+             (MATCH_ARM _ (BLOCK (debug x)))))",
+            "match x { 1 => debug 1, -2.5 => debug 2, nil => debug 3 }",
+            "(BLOCK (match x (MATCH_ARM 1 (BLOCK (debug 1))) \
+             (MATCH_ARM (- 2.5) (BLOCK (debug 2))) \
+             (MATCH_ARM nil (BLOCK (debug 3)))))",
+            "match x { [[a], {1 => _}, 'b'] => debug a }",
+            "(BLOCK (match x (MATCH_ARM (ARRAY_DECL (ARRAY_DECL a) \
+             (HASH_DECL (HASH_ELEM 1 _)) b) (BLOCK (debug a)))))",
+            "match x { [] => debug 0; {} => debug 1 }",
+            "(BLOCK (match x (MATCH_ARM ARRAY_DECL (BLOCK (debug 0))) \
+             (MATCH_ARM HASH_DECL (BLOCK (debug 1)))))");
+        println!("Starting match_patterns() test..");
+        TreeMatcher::__match(&tests);
+
+        let errors = [("match x { a + 1 => debug a }",
+                       "expected '=>' after pattern"),
+                      ("match x { (a) => debug a }", "expected pattern"),
+                      ("match x { {k => 1} => debug 1 }", "expected pattern"),
+                      ("match x { 1 => debug 1 2 => debug 2 }",
+                       "expected newline after match arm")];
+        for (i, &(input, message)) in errors.iter().enumerate() {
+            match parse(input, "treematcher") {
+                Ok(_) => println!("{}. expected errors, parse succeeded", i),
+                Err(errors) => {
+                    if !errors[0].message.ends_with(message) {
+                        println!("{}. error({}) != expected error({})", i,
+                                 errors[0].message, message);
+                    }
+                },
+            }
+        }
+
+        let warnings = [("match x {\n_ => debug 1\n1 => debug 2\n}",
+                         vec![(3, 1, "unreachable match arm, the pattern '_' \
+                                      on line 2 matches everything")]),
+                        ("match x {\n[1, a] => debug 1\n[2, a] => debug 2\n\
+                          [1, a] => debug 3\ny => debug 4\n}",
+                         vec![(4, 1, "unreachable match arm, the same \
+                                      pattern is matched on line 2")]),
+                        ("match x {\n[a] => debug 1\n[b] => debug 2\n}",
+                         vec![])];
+        for (i, &(input, ref expected)) in warnings.iter().enumerate() {
+            let found: Vec<(i32, i32, String)> =
+                TreeMatcher::warnings(input).into_iter()
+                    .filter(|d| d.severity == Severity::Warning)
+                    .map(|d| (d.line_num, d.line_pos, d.message))
+                    .collect();
+            let expected: Vec<(i32, i32, String)> = expected.iter()
+                .map(|&(line, pos, message)| (line, pos, message.to_string()))
+                .collect();
+            if found != expected {
+                println!("{}. warnings({:?}) != expected warnings({:?})",
+                         i, found, expected);
+            }
+        }
+        println!("Ending match_patterns() test..");
+    }
+
+    pub fn match_diagnostics()
+    {
+        use parser::Severity::*;
+
+        let tests = [
+            ("42", vec![(Warning, 1, 1, "statement has no effect")]),
+            ("f()
+x = 1
+'a'; nil", vec![
+                (Warning, 3, 1, "statement has no effect"),
+                (Warning, 3, 6, "statement has no effect")]),
+            // The last statement of a block is its value.
+            ("x = do {
+1; 2
+}
+if x {
+'a'
+} else { 'b' }",
+             vec![(Warning, 2, 1, "statement has no effect")]),
+            ("def f() {
+return 1
+g()
+h()
+}",
+             vec![(Warning, 3, 1, "unreachable code after return")]),
+            ("def f() {
+if a {
+return 1
+}
+return 2
+}", vec![]),
+            ("while a {}", vec![(Note, 1, 9, "empty block")]),
+            ("x = {}", vec![]),
+        ];
+        println!("Starting match_diagnostics() test..");
+        for (i, &(input, ref expected)) in tests.iter().enumerate() {
+            let found = TreeMatcher::warnings(input);
+            let found: Vec<(Severity, i32, i32, &str)> = found.iter()
+                .map(|d| (d.severity, d.line_num, d.line_pos,
+                          d.message.as_str()))
+                .collect();
+            if found != *expected {
+                println!("{}. diagnostics({:?}) != expected diagnostics({:?})",
+                         i, found, expected);
+            }
+        }
+        println!("Ending match_diagnostics() test..");
+    }
+
+    // The warnings of a program which parses without errors.
+    fn warnings(input: &str) -> Vec<Diagnostic>
+    {
+        let module = Module::new("treematcher".to_string());
+        let mut scanner = Scanner::new(input, &module);
+        let mut parser = Parser::new(&mut scanner, &module);
+        if let Err(errors) = parser.program() {
+            println!("unexpected errors({:?})", errors);
+        }
+        return parser.warnings().to_vec();
+    }
+
+    pub fn match_loop_else()
+    {
+        let tests = create_tests!(
+            "while x {\ndebug 1\n} else {\ndebug 2\n}",
+            "(BLOCK (while x (BLOCK (debug 1)) (else (BLOCK (debug 2)))))",
+            "until x {} else {}",
+            "(BLOCK (until x BLOCK (else BLOCK)))",
+            "for i in 1..3 {\ndebug i\n} else {\ndebug 0\n}",
+            "(BLOCK (for i (RANGE 1 3) (BLOCK (debug i)) \
+             (else (BLOCK (debug 0)))))",
+            "for i in 1..3 step 2 {} else { debug 0 }",
+            "(BLOCK (for i (RANGE 1 3) BLOCK (STEP 2) (else (BLOCK (debug 0)))))",
+            "if a {\nwhile b {}\n} else {}",
+            "(BLOCK (if a (BLOCK (while b BLOCK)) ELIF BLOCK))");
+        println!("Starting match_loop_else() test..");
+        TreeMatcher::__match(&tests);
+
+        let counts = [("while x {}", 2), ("while x {} else {}", 3),
+                      ("for i in 1..2 {}", 3), ("for i in 1..2 {} else {}", 4),
+                      ("for i in 1..2 step 1 {} else {}", 5)];
+        for (i, &(input, expected)) in counts.iter().enumerate() {
+            match parse(input, "treematcher") {
+                Ok(program) => {
+                    let count = program.children[0].children.len();
+                    if count != expected {
+                        println!("{}. children({}) != expected children({})",
+                                 i, count, expected);
+                    }
+                },
+                Err(errors) => println!("{}. unexpected errors({:?})", i,
+                                        errors),
+            }
+        }
+        println!("Ending match_loop_else() test..");
+    }
+
+    pub fn match_empty_program()
+    {
+        let tests = ["", "   ", "\t \t", "# comment", "# comment\n",
+                     "\n\n", "\r\n", "=== long ===",
+                     "=== long\nlines\n===\n", "\\\n", "\n=== a ===\n# b"];
+        println!("Starting match_empty_program() test..");
+        for (i, input) in tests.iter().enumerate() {
+            match parse(input, "treematcher") {
+                Ok(mut program) => {
+                    let tree = program.to_string_tree();
+                    if tree != "BLOCK" {
+                        println!("{}. tree({}) != expected tree(BLOCK)", i,
+                                 tree);
+                    }
+                },
+                Err(errors) => println!("{}. unexpected errors({:?})", i,
+                                        errors),
+            }
+        }
+        println!("Ending match_empty_program() test..");
+    }
+
+    pub fn match_do_expr()
+    {
+        let tests = create_tests!(
+            "x = do { 1; 2; 3 }",
+            "(BLOCK (= x (DO_EXPR (BLOCK 1 2 3))))",
+            "debug do {\ny = 2\ny * 2\n} + 1",
+            "(BLOCK (debug (+ (DO_EXPR (BLOCK (= y 2) (* y 2))) 1)))");
+        println!("Starting match_do_expr() test..");
+        TreeMatcher::__match(&tests);
+        println!("Ending match_do_expr() test..");
+    }
+
+    pub fn match_associativity()
+    {
+        let tests = create_tests!(
+            "1 - 2 - 3", "(BLOCK (- (- 1 2) 3))",
+            "1 / 2 * 3", "(BLOCK (* (/ 1 2) 3))",
+            "1 << 2 >> 3", "(BLOCK (>> (<< 1 2) 3))",
+            "a || b || c", "(BLOCK (|| (|| a b) c))",
+            "a == b != c", "(BLOCK (!= (== a b) c))",
+            "a = b = 1", "(BLOCK (= a (= b 1)))",
+            "a[0] = b = c = 1", "(BLOCK (= (SUBSCRIPT a 0) (= b (= c 1))))");
+        println!("Starting match_associativity() test..");
+        TreeMatcher::__match(&tests);
+        println!("Ending match_associativity() test..");
+    }
+
+    pub fn match_member()
+    {
+        let tests = create_tests!(
+            "foo.bar", "(BLOCK (MEMBER foo bar))",
+            "a.b.c", "(BLOCK (MEMBER (MEMBER a b) c))",
+            "a.b().c[0]",
+            "(BLOCK (SUBSCRIPT (MEMBER (CALL (MEMBER a b)) c) 0))");
+        println!("Starting match_member() test..");
+        TreeMatcher::__match(&tests);
+        println!("Ending match_member() test..");
+    }
+
+    pub fn match_ternary()
+    {
+        let tests = create_tests!(
+            "x = a ? 1 : 2", "(BLOCK (= x (TERNARY a 1 2)))",
+            "a ? b : c ? d : e", "(BLOCK (TERNARY a b (TERNARY c d e)))",
+            "a ? b ? c : d : e", "(BLOCK (TERNARY a (TERNARY b c d) e))",
+            "a || b ? 1 + 2 : 3", "(BLOCK (TERNARY (|| a b) (+ 1 2) 3))");
+        println!("Starting match_ternary() test..");
+        TreeMatcher::__match(&tests);
+        println!("Ending match_ternary() test..");
+    }
+
+    /*
+     * Tokens made by hand rather than scanned, laid out on
+     * one line with a space between them.
+     */
+    fn tokens(spec: &[(&str, TokenType)]) -> Vec<Token>
+    {
+        let mut tokens: Vec<Token> = Vec::new();
+        let mut start = 0;
+        for &(text, token_type) in spec {
+            let mut token = Token::new_imag(text.to_string(), token_type,
+                                            1, start as i32 + 1, start);
+            token.end = start + text.len();
+            tokens.push(token);
+            start += text.len() + 1;
+        }
+        return tokens;
+    }
+
+    pub fn match_token_source()
+    {
+        println!("Starting match_token_source() test..");
+        let module = Module::new("treematcher".to_string());
+        let tokens = TreeMatcher::tokens(&[("x", IDENT), ("=", ASSIGN),
+                                           ("1", INTEGER), ("+", PLUS),
+                                           ("2", INTEGER)]);
+        let mut source = VecTokenSource::new(&tokens);
+        let mut parser = Parser::new(&mut source, &module);
+        match parser.program() {
+            Ok(mut program) => {
+                let tree = program.to_string_tree();
+                if tree != "(BLOCK (= x (+ 1 2)))" {
+                    println!("tree({}) != expected tree((BLOCK (= x \
+                              (+ 1 2))))", tree);
+                }
+            },
+            Err(errors) => println!("unexpected errors({:?})", errors),
+        }
+
+        let tokens = TreeMatcher::tokens(&[("f", IDENT), ("(", LPAREN),
+                                           ("1", INTEGER), ("+", PLUS)]);
+        let mut source = VecTokenSource::new(&tokens);
+        let mut parser = Parser::new(&mut source, &module);
+        match parser.program() {
+            Ok(_) => println!("expected errors, parse succeeded"),
+            Err(errors) => {
+                let expected = "treematcher:1:8: unexpected end-of-file, \
+                                expected expression";
+                if errors.len() != 1 || errors[0].to_string() != expected {
+                    println!("errors({:?}) != expected error({})", errors,
+                             expected);
+                }
+            },
+        }
+        println!("Ending match_token_source() test..");
+    }
+
+    pub fn match_assignment_targets()
+    {
+        let tests = create_tests!(
+            "a = 1", "(BLOCK (= a 1))",
+            "a[0] = 1", "(BLOCK (= (SUBSCRIPT a 0) 1))",
+            "a.b = 1", "(BLOCK (= (MEMBER a b) 1))",
+            "(a)[0] = 1", "(BLOCK (= (SUBSCRIPT a 0) 1))",
+            "f().b = 1", "(BLOCK (= (MEMBER (CALL f) b) 1))");
+        println!("Starting match_assignment_targets() test..");
+        TreeMatcher::__match(&tests);
+
+        let tests = [("1 = 2", "cannot assign to integer literal"),
+                     ("f() = 2", "cannot assign to call"),
+                     ("(a) = 2", "cannot assign to parenthesized expression"),
+                     ("a + b = 2", "cannot assign to operator '+'"),
+                     ("-a = 2", "cannot assign to operator '-'"),
+                     ("[a] = 2", "cannot assign to array literal"),
+                     ("nil = 2", "cannot assign to 'nil'")];
+        for (i, &(input, message)) in tests.iter().enumerate() {
+            match parse(input, "treematcher") {
+                Ok(_) => println!("{}. expected errors, parse succeeded", i),
+                Err(errors) => {
+                    let expected = format!("unexpected symbol near '=', {}",
+                                           message);
+                    if errors[0].message != expected {
+                        println!("{}. error({}) != expected error({})", i,
+                                 errors[0].message, expected);
+                    }
+                },
+            }
+        }
+        println!("Ending match_assignment_targets() test..");
+    }
+
+    pub fn match_compound_assign()
+    {
+        let tests = create_tests!(
+            "x |= 1", "(BLOCK (|= x 1))",
+            "x &= 2", "(BLOCK (&= x 2))",
+            "x ^= 3", "(BLOCK (^= x 3))",
+            "x <<= 1", "(BLOCK (<<= x 1))",
+            "x >>= 1", "(BLOCK (>>= x 1))",
+            "x += y -= 2", "(BLOCK (+= x (-= y 2)))",
+            "a[0] |= b << 1", "(BLOCK (|= (SUBSCRIPT a 0) (<< b 1)))",
+            "a.b %=\n2", "(BLOCK (%= (MEMBER a b) 2))");
+        println!("Starting match_compound_assign() test..");
+        TreeMatcher::__match(&tests);
+
+        let tests = [("1 |= 2", "cannot assign to integer literal"),
+                     ("f() &= 2", "cannot assign to call"),
+                     ("a | b ^= 3", "cannot assign to operator '|'"),
+                     ("(x) <<= 1", "cannot assign to parenthesized \
+                                    expression")];
+        for (i, &(input, message)) in tests.iter().enumerate() {
+            match parse(input, "treematcher") {
+                Ok(_) => println!("{}. expected errors, parse succeeded", i),
+                Err(errors) => {
+                    if !errors[0].message.ends_with(message) {
+                        println!("{}. error({}) != expected error({})", i,
+                                 errors[0].message, message);
+                    }
+                },
+            }
+        }
+        println!("Ending match_compound_assign() test..");
+    }
+
+    pub fn match_multi_assign()
+    {
+        let tests = create_tests!(
+            "a, b = 1, 2",
+            "(BLOCK (MULTI_ASSIGN (ASSIGN_TARGETS a b) (ASSIGN_VALUES 1 2)))",
+            "a, b = b, a",
+            "(BLOCK (MULTI_ASSIGN (ASSIGN_TARGETS a b) (ASSIGN_VALUES b a)))",
+            "a, b, c = c, a, b",
+            "(BLOCK (MULTI_ASSIGN (ASSIGN_TARGETS a b c) \
+             (ASSIGN_VALUES c a b)))",
+            "a[0], b.c =\nf(x, y), [1, 2]",
+            "(BLOCK (MULTI_ASSIGN (ASSIGN_TARGETS (SUBSCRIPT a 0) (MEMBER b c)) \
+             (ASSIGN_VALUES (CALL f x y) (ARRAY_DECL 1 2))))",
+            "a, b = 1, 2, 3",
+            "(BLOCK (MULTI_ASSIGN (ASSIGN_TARGETS a b) (ASSIGN_VALUES 1 2 3)))",
+            "f(a, b)", "(BLOCK (CALL f a b))");
+        println!("Starting match_multi_assign() test..");
+        TreeMatcher::__match(&tests);
+
+        let tests = [("a, b", "unexpected end-of-file, expected '=' after \
+                                assignment targets"),
+                     ("a, f() = 1, 2", "unexpected symbol near '=', cannot \
+                                        assign to call"),
+                     ("1, a = 1, 2", "unexpected symbol near ',', cannot \
+                                      assign to integer literal")];
+        for (i, &(input, message)) in tests.iter().enumerate() {
+            match parse(input, "treematcher") {
+                Ok(_) => println!("{}. expected errors, parse succeeded", i),
+                Err(errors) => {
+                    if errors[0].message != message {
+                        println!("{}. error({}) != expected error({})", i,
+                                 errors[0].message, message);
+                    }
+                },
+            }
+        }
+        println!("Ending match_multi_assign() test..");
+    }
+
+    pub fn match_if_chains()
+    {
+        let tests = create_tests!(
+            "if a {\n} elif b {\n} elif c {\n} else {\n}",
+            "(BLOCK (if a BLOCK (ELIF b BLOCK c BLOCK) BLOCK))",
+            "if a {} else {}", "(BLOCK (if a BLOCK ELIF BLOCK))",
+            "if a { if b { x } else { y } }",
+            "(BLOCK (if a (BLOCK (if b (BLOCK x) ELIF (BLOCK y))) ELIF))",
+            "if a { if b { x } } else { y }",
+            "(BLOCK (if a (BLOCK (if b (BLOCK x) ELIF)) ELIF (BLOCK y)))");
+        println!("Starting match_if_chains() test..");
+        TreeMatcher::__match(&tests);
+
+        let tests = [("if a {} else {} elif b {}",
+                      "unexpected keyword near 'elif', 'elif' cannot \
+                       follow 'else'"),
+                     ("if a {} else", "unexpected end-of-file, expected \
+                                       '{' to open block"),
+                     ("if a {}\nelse {}", "unexpected keyword near 'else', \
+                                           expected 'if' before it"),
+                     ("elif a {}", "unexpected keyword near 'elif', \
+                                    expected 'if' before it")];
+        for (i, &(input, message)) in tests.iter().enumerate() {
+            match parse(input, "treematcher") {
+                Ok(_) => println!("{}. expected errors, parse succeeded", i),
+                Err(errors) => {
+                    if errors[0].message != message {
+                        println!("{}. error({}) != expected error({})", i,
+                                 errors[0].message, message);
+                    }
+                },
+            }
+        }
+        println!("Ending match_if_chains() test..");
+    }
+
+    pub fn match_return_and_yield()
+    {
+        let tests = create_tests!(
+            "def f() {\nreturn\n}", "(BLOCK (SUB_DECL f SUB_PARAMS (BLOCK return)))",
+            "def f(a) {\nreturn a + 1\n}",
+            "(BLOCK (SUB_DECL f (SUB_PARAMS a) (BLOCK (return (+ a 1)))))",
+            "def f() {\nyield\n}", "(BLOCK (SUB_DECL f SUB_PARAMS (BLOCK yield)))",
+            "def f(a) {\nyield a; yield a + 1\n}",
+            "(BLOCK (SUB_DECL f (SUB_PARAMS a) (BLOCK (yield a) (yield (+ a 1)))))",
+            "g = () => {\nyield 1\n}",
+            "(BLOCK (= g (SUB_LITERAL SUB_PARAMS (BLOCK (yield 1)))))");
+        println!("Starting match_return_and_yield() test..");
+        TreeMatcher::__match(&tests);
+
+        let tests = [("return 1", "'return' outside subroutine"),
+                     ("yield 1", "'yield' outside subroutine"),
+                     ("if x {\nyield x\n}", "'yield' outside subroutine"),
+                     ("yield = 1", "'yield' outside subroutine")];
+        for (i, &(input, message)) in tests.iter().enumerate() {
+            match parse(input, "treematcher") {
+                Ok(_) => println!("{}. expected errors, parse succeeded", i),
+                Err(errors) => {
+                    if !errors[0].message.ends_with(message) {
+                        println!("{}. error({}) != expected error({})", i,
+                                 errors[0].message, message);
+                    }
+                },
+            }
+        }
+        println!("Ending match_return_and_yield() test..");
+    }
+
+    pub fn match_assert()
+    {
+        let tests = create_tests!(
+            "assert x", "(BLOCK (assert x))",
+            "assert a == 1, 'a is not 1'", "(BLOCK (assert (== a 1) a is not 1))",
+            "assert f(x),\n  'f failed: ' + x",
+            "(BLOCK (assert (CALL f x) (+ f failed:  x)))",
+            "def f() {\nassert 1 < 2\n}",
+            "(BLOCK (SUB_DECL f SUB_PARAMS (BLOCK (assert (< 1 2)))))");
+        println!("Starting match_assert() test..");
+        TreeMatcher::__match(&tests);
+
+        let tests = [("assert", "unexpected end-of-file, expected expression"),
+                     ("assert\nx", "unexpected newline, expected expression"),
+                     ("assert x,", "unexpected end-of-file, expected \
+                                    expression"),
+                     ("assert = 1", "unexpected symbol near '=', expected \
+                                     expression")];
+        for (i, &(input, message)) in tests.iter().enumerate() {
+            match parse(input, "treematcher") {
+                Ok(_) => println!("{}. expected errors, parse succeeded", i),
+                Err(errors) => {
+                    if errors[0].message != message {
+                        println!("{}. error({}) != expected error({})", i,
+                                 errors[0].message, message);
+                    }
+                },
+            }
+        }
+        println!("Ending match_assert() test..");
+    }
+
+    pub fn match_with()
+    {
+        let tests = create_tests!(
+            "with open(p) as f {\nread(f)\n}",
+            "(BLOCK (with (CALL open p) f (BLOCK (CALL read f))))",
+            "with a as x, b.lock() as y {}",
+            "(BLOCK (with a x (CALL (MEMBER b lock)) y BLOCK))",
+            "with a as x,\n  f(x) as y {\n}",
+            "(BLOCK (with a x (CALL f x) y BLOCK))",
+            "as = 1\nwith as as as {}", "(BLOCK (= as 1) (with as as BLOCK))");
+        println!("Starting match_with() test..");
+        TreeMatcher::__match(&tests);
+
+        let tests = [("with a {}", "expected 'as' after resource"),
+                     ("with a as 1 {}", "expected identifier"),
+                     ("with a as x, {}", "expected 'as' after resource"),
+                     ("with a as x", "expected '{' to open block"),
+                     ("with = 1", "unexpected symbol near '=', expected \
+                                   expression")];
+        for (i, &(input, message)) in tests.iter().enumerate() {
+            match parse(input, "treematcher") {
+                Ok(_) => println!("{}. expected errors, parse succeeded", i),
+                Err(errors) => {
+                    if !errors[0].message.ends_with(message) {
+                        println!("{}. error({}) != expected error({})", i,
+                                 errors[0].message, message);
+                    }
+                },
+            }
+        }
+        println!("Ending match_with() test..");
+    }
+
+    pub fn match_const()
+    {
+        let tests = create_tests!(
+            "const PI = 3.14", "(BLOCK (CONST_DECL PI 3.14))",
+            "const N =\n1 + 2", "(BLOCK (CONST_DECL N (+ 1 2)))");
+        println!("Starting match_const() test..");
+        TreeMatcher::__match(&tests);
+
+        let tests = [("const = 1", "expected identifier"),
+                     ("const 1 = 1", "expected identifier"),
+                     ("const N 1", "expected '=' after constant name"),
+                     ("const = 1", "unexpected symbol near '='"),
+                     ("x = const", "unexpected keyword near 'const'")];
+        for (i, &(input, message)) in tests.iter().enumerate() {
+            match parse(input, "treematcher") {
+                Ok(_) => println!("{}. expected errors, parse succeeded", i),
+                Err(errors) => {
+                    if !errors[0].message.contains(message) {
+                        println!("{}. error({}) != expected error({})", i,
+                                 errors[0].message, message);
+                    }
+                },
+            }
+        }
+        println!("Ending match_const() test..");
+    }
+
+    pub fn match_imports()
+    {
+        let tests = create_tests!(
+            "import foo", "(BLOCK (import foo))",
+            "import 'lib/foo'", "(BLOCK (import lib/foo))");
+        println!("Starting match_imports() test..");
+        TreeMatcher::__match(&tests);
+
+        let module = Module::new("treematcher".to_string());
+        let mut scanner = Scanner::new("import foo\nimport 'bar'\n\
+                                        import foo", &module);
+        let mut parser = Parser::new(&mut scanner, &module);
+        match parser.program() {
+            Ok(program) => {
+                let module = &program.children[1].children[0];
+                if module.get_type() != MODULE_REF {
+                    println!("type({:?}) != expected type(MODULE_REF)",
+                             module.get_type());
+                }
+            },
+            Err(errors) => println!("unexpected errors({:?})", errors),
+        }
+        if parser.imports() != ["foo", "bar"] {
+            println!("imports({:?}) != expected imports([foo, bar])",
+                     parser.imports());
+        }
+        match parse("import 1", "treematcher") {
+            Ok(_) => println!("expected errors, parse succeeded"),
+            Err(errors) => {
+                if !errors[0].message.ends_with("expected module name") {
+                    println!("error({}) != expected error(expected module \
+                              name)", errors[0].message);
+                }
+            },
+        }
+        println!("Ending match_imports() test..");
+    }
+
+    pub fn match_recovered_statements()
+    {
+        println!("Starting match_recovered_statements() test..");
+        let input = "x = 1\ny = )\nif x {\n1 +\n}\nz = 2";
+        let expected = [(ASSIGN, false), (ERROR, true), (IF, true),
+                         (ASSIGN, false)];
+        let module = Module::new("treematcher".to_string());
+        let mut scanner = Scanner::new(input, &module);
+        let mut parser = Parser::new(&mut scanner, &module);
+
+        let (statements, errors) = parser.statements();
+        if errors.len() != 2 {
+            println!("errors({}) != expected errors(2)", errors.len());
+        }
+        if statements.len() != expected.len() {
+            println!("statements({}) != expected statements({})",
+                     statements.len(), expected.len());
+        }
+        for (i, statement) in statements.iter().enumerate() {
+            if i >= expected.len() {
+                break;
+            }
+            let (token_type, recovered) = expected[i];
+            if statement.node.get_type() != token_type ||
+               statement.recovered != recovered {
+                println!("{}. statement({:?}, {}) != expected \
+                          statement({:?}, {})", i,
+                         statement.node.get_type(), statement.recovered,
+                         token_type, recovered);
+            }
+        }
+        println!("Ending match_recovered_statements() test..");
+    }
+
+    pub fn match_adjacent_minus()
+    {
+        let tests = create_tests!(
+            "a--b", "(BLOCK (- a (- b)))",
+            "a- -b", "(BLOCK (- a (- b)))",
+            "a - - b", "(BLOCK (- a (- b)))",
+            "a---b", "(BLOCK (- a (- (- b))))");
+        println!("Starting match_adjacent_minus() test..");
+        TreeMatcher::__match(&tests);
+
+        /*
+         * The string tree shows both as '-', make sure the
+         * inner one really is a negation.
+         */
+        match parse("a--b", "treematcher") {
+            Ok(program) => {
+                let sub = &program.children[0];
+                if sub.get_type() != MINUS ||
+                   sub.children[1].get_type() != NEGATE {
+                    println!("a--b: types({:?}, {:?}) != expected \
+                              types(MINUS, NEGATE)", sub.get_type(),
+                             sub.children[1].get_type());
+                }
+            },
+            Err(errors) => println!("unexpected errors({})", errors.len()),
+        }
+        println!("Ending match_adjacent_minus() test..");
+    }
+
+    pub fn match_unary_plus()
+    {
+        let tests = create_tests!(
+            "+5", "(BLOCK (+ 5))",
+            "+-5", "(BLOCK (+ (- 5)))",
+            "-+5", "(BLOCK (- (+ 5)))",
+            "a + +b", "(BLOCK (+ a (+ b)))",
+            "a++b", "(BLOCK (+ a (+ b)))",
+            "+a * b", "(BLOCK (* (+ a) b))");
+        println!("Starting match_unary_plus() test..");
+        TreeMatcher::__match(&tests);
+
+        match parse("a + +b", "treematcher") {
+            Ok(program) => {
+                let add = &program.children[0];
+                if add.get_type() != PLUS ||
+                   add.children[1].get_type() != UNARY_PLUS {
+                    println!("a + +b: types({:?}, {:?}) != expected \
+                              types(PLUS, UNARY_PLUS)", add.get_type(),
+                             add.children[1].get_type());
+                }
+            },
+            Err(errors) => println!("unexpected errors({})", errors.len()),
+        }
+        println!("Ending match_unary_plus() test..");
+    }
+
+    pub fn match_hash_or_block()
+    {
+        let tests = create_tests!(
+            "{}", "(BLOCK HASH_DECL)",
+            "{ 1 => 2 }", "(BLOCK (HASH_DECL (HASH_ELEM 1 2)))",
+            "x = {\n(a) => [1, 2]\n}",
+            "(BLOCK (= x (HASH_DECL (HASH_ELEM a (ARRAY_DECL 1 2)))))",
+            "{ x = 1 }", "(BLOCK (BLOCK (= x 1)))",
+            "{ f(1, {2 => 3}) }",
+            "(BLOCK (BLOCK (CALL f 1 (HASH_DECL (HASH_ELEM 2 3)))))",
+            "{ y = (a) => a }",
+            "(BLOCK (BLOCK (= y (SUB_LITERAL (SUB_PARAMS a) \
+             (BLOCK (return a))))))",
+            "{ f(a) => 1 }", "(BLOCK (HASH_DECL (HASH_ELEM (CALL f a) 1)))");
+        println!("Starting match_hash_or_block() test..");
+        TreeMatcher::__match(&tests);
+        println!("Ending match_hash_or_block() test..");
+    }
+
+    pub fn match_trailing_commas()
+    {
+        let tests = create_tests!(
+            "x = [1, 2,]", "(BLOCK (= x (ARRAY_DECL 1 2)))",
+            "f(a,)", "(BLOCK (CALL f a))",
+            "x = {1 => 2,}", "(BLOCK (= x (HASH_DECL (HASH_ELEM 1 2))))",
+            "x = [\n1,\n2,\n]", "(BLOCK (= x (ARRAY_DECL 1 2)))");
+        println!("Starting match_trailing_commas() test..");
+        TreeMatcher::__match(&tests);
+        println!("Ending match_trailing_commas() test..");
+    }
+
+    pub fn match_multiline_brackets()
+    {
+        let tests = create_tests!(
+            "x = [1\n, 2\n]", "(BLOCK (= x (ARRAY_DECL 1 2)))",
+            "x = [\n1\n,\n2\n,\n]", "(BLOCK (= x (ARRAY_DECL 1 2)))",
+            "x = [\ny\nfor y in z\n]", "(BLOCK (= x (ARRAY_COMP y (COMP_VARS y) z)))",
+            "f(\na\n,\nb = 1\n)", "(BLOCK (CALL f a (KWARG b 1)))",
+            "f(a\n, b)", "(BLOCK (CALL f a b))",
+            "x = {1 => 2\n, 3\n=>\n4\n}",
+            "(BLOCK (= x (HASH_DECL (HASH_ELEM 1 2) (HASH_ELEM 3 4))))",
+            "x = (\n1 + 2\n)", "(BLOCK (= x (+ 1 2)))");
+        println!("Starting match_multiline_brackets() test..");
+        TreeMatcher::__match(&tests);
+        println!("Ending match_multiline_brackets() test..");
+    }
+
+    pub fn match_default_params()
+    {
+        let tests = create_tests!(
+            "def f(a, b = 10) {}", "(BLOCK (SUB_DECL f (SUB_PARAMS a (b 10)) BLOCK))",
+            "g = def(a = 1, b = a + 1) {}",
+            "(BLOCK (= g (SUB_LITERAL (SUB_PARAMS (a 1) (b (+ a 1))) BLOCK)))");
+        println!("Starting match_default_params() test..");
+        TreeMatcher::__match(&tests);
+        match parse("def f(a = 1, b) {}", "treematcher") {
+            Ok(_) => println!("expected error, parse succeeded"),
+            Err(errors) => {
+                if errors.len() != 1 || errors[0].line_pos != 14 ||
+                   !errors[0].message.ends_with("non-default parameter \
+                                                 follows default parameter") {
+                    println!("errors({:?}) != expected error(non-default \
+                              parameter follows default parameter)", errors);
+                }
+            },
+        }
+        println!("Ending match_default_params() test..");
+    }
+
+    pub fn match_rest_params()
+    {
+        let tests = create_tests!(
+            "def f(*xs) {}", "(BLOCK (SUB_DECL f (SUB_PARAMS (REST_PARAM xs)) BLOCK))",
+            "def f(a, *xs) {}",
+            "(BLOCK (SUB_DECL f (SUB_PARAMS a (REST_PARAM xs)) BLOCK))",
+            "def f(a = 1, *xs) {}",
+            "(BLOCK (SUB_DECL f (SUB_PARAMS (a 1) (REST_PARAM xs)) BLOCK))");
+        println!("Starting match_rest_params() test..");
+        TreeMatcher::__match(&tests);
+        match parse("def f(*xs, a) {}", "treematcher") {
+            Ok(_) => println!("expected error, parse succeeded"),
+            Err(errors) => {
+                if errors.len() != 1 || !errors[0].message.ends_with(
+                   "rest parameter must be the last parameter") {
+                    println!("errors({:?}) != expected error(rest \
+                              parameter must be the last parameter)", errors);
+                }
+            },
+        }
+        println!("Ending match_rest_params() test..");
+    }
+
+    pub fn match_soft_keywords()
+    {
+        let tests = create_tests!(
+            "for i in 1..10 step 2 {}", "(BLOCK (for i (RANGE 1 10) BLOCK (STEP 2)))",
+            "for i in 1..10 {}", "(BLOCK (for i (RANGE 1 10) BLOCK))",
+            "step = 2\nstep * step", "(BLOCK (= step 2) (* step step))",
+            "for step in 1..step step step {}",
+            "(BLOCK (for step (RANGE 1 step) BLOCK (STEP step)))");
+        println!("Starting match_soft_keywords() test..");
+        TreeMatcher::__match(&tests);
+        println!("Ending match_soft_keywords() test..");
+    }
+
+    pub fn match_word_operators()
+    {
+        let tests = [("not a and b or c", "!a && b || c"),
+                     ("x = not (a or b) and not c", "x = !(a || b) && !c")];
+        println!("Starting match_word_operators() test..");
+        for (i, &(words, symbols)) in tests.iter().enumerate() {
+            let module = Module::new("treematcher".to_string());
+            let mut scanner = Scanner::new(words, &module);
+            scanner.set_word_operators(true);
+            let mut parser = Parser::new(&mut scanner, &module);
+
+            let words_tree = match parser.program() {
+                Ok(mut program) => program.to_string_tree(),
+                Err(errors) => {
+                    println!("{}. unexpected errors({:?})", i, errors);
+                    continue;
+                },
+            };
+            let symbols_tree = parse(symbols, "treematcher").unwrap()
+                               .to_string_tree();
+            if words_tree != symbols_tree {
+                println!("{}. tree({}) != expected tree({})", i,
+                         words_tree, symbols_tree);
+            }
+        }
+        // Off by default, the words are still plain names.
+        let tests = create_tests!("and = not + or", "(BLOCK (= and (+ not or)))");
+        TreeMatcher::__match(&tests);
+        println!("Ending match_word_operators() test..");
+    }
+
+    pub fn match_in_test()
+    {
+        let tests = create_tests!(
+            "for x in y {}", "(BLOCK (for x y BLOCK))",
+            "z = x in y", "(BLOCK (= z (IN_TEST x y)))",
+            "z = x in y == a < b in c",
+            "(BLOCK (= z (== (IN_TEST x y) (IN_TEST (< a b) c))))",
+            "for x in (a in b) {}", "(BLOCK (for x (IN_TEST a b) BLOCK))",
+            "for x in y {\nz = x in y\n}",
+            "(BLOCK (for x y (BLOCK (= z (IN_TEST x y)))))");
+        println!("Starting match_in_test() test..");
+        TreeMatcher::__match(&tests);
+        if parse("for x in a in b {}", "treematcher").is_ok() {
+            println!("'in' was taken as membership in a for header");
+        }
+        println!("Ending match_in_test() test..");
+    }
+
+    pub fn match_range()
+    {
+        let tests = create_tests!(
+            "r = 1..5", "(BLOCK (= r (RANGE 1 5)))",
+            "r = 1..5..2", "(BLOCK (= r (RANGE 1 5 2)))",
+            "r = a + 1..b * 2..-c", "(BLOCK (= r (RANGE (+ a 1) (* b 2) (- c))))",
+            "r = (1..5)..2", "(BLOCK (= r (RANGE (RANGE 1 5) 2)))");
+        println!("Starting match_range() test..");
+        TreeMatcher::__match(&tests);
+        match parse("r = 1..5..2..3", "treematcher") {
+            Ok(_) => println!("expected error, parse succeeded"),
+            Err(errors) => {
+                if errors.len() != 1 || !errors[0].message.ends_with(
+                   "a range takes at most a step") {
+                    println!("errors({:?}) != expected error(a range takes \
+                              at most a step)", errors);
+                }
+            },
+        }
+        println!("Ending match_range() test..");
+    }
+
+    pub fn match_render()
+    {
+        let tests = [("x = 1\ny = )", "treematcher:2:5: unexpected symbol \
+                       near ')', expected expression\ny = )\n    ^"),
+                     ("if x {\r\n\tf(1 2)\r\n}", "treematcher:2:13: \
+                       unexpected literal near '2', expected ')' to close \
+                       the function call\n\tf(1 2)\n\t    ^"),
+                     ("y = 1 +", "treematcher:1:8: unexpected end-of-file, \
+                       expected expression\ny = 1 +\n       ^"),
+                     ("'a' $", "treematcher:1:5: unrecognized character \
+                       '$'\n'a' $\n    ^")];
+        println!("Starting match_render() test..");
+        for (i, &(input, expected)) in tests.iter().enumerate() {
+            match parse(input, "treematcher") {
+                Ok(_) => println!("{}. expected errors, parse succeeded", i),
+                Err(errors) => {
+                    let rendered = errors[0].render(input);
+                    if rendered != expected {
+                        println!("{}. rendered({:?}) != expected \
+                                  rendered({:?})", i, rendered, expected);
+                    }
+                },
+            }
+        }
+        println!("Ending match_render() test..");
+    }
+
+    pub fn match_error_recovery()
+    {
+        println!("Starting match_error_recovery() test..");
+        let input = "x = )\ny = 1 $\nif y {\n1 +* 2\n}\nz = ]";
+        let expected = [(1, "unexpected symbol near ')', \
+                             expected expression"),
+                        (2, "unrecognized character '$'"),
+                        (4, "unexpected symbol near '*', \
+                             expected expression"),
+                        (6, "unexpected symbol near ']', \
+                             expected expression")];
+        match parse(input, "treematcher") {
+            Ok(_) => println!("expected errors, parse succeeded"),
+            Err(errors) => {
+                if errors.len() != expected.len() {
+                    println!("errors({}) != expected errors({})",
+                             errors.len(), expected.len());
+                }
+                for (i, error) in errors.iter().enumerate() {
+                    if i >= expected.len() {
+                        break;
+                    }
+                    let (line_num, message) = expected[i];
+                    if error.line_num != line_num ||
+                       error.message != message {
+                        println!("{}. error({}) != expected error({}: {})",
+                                 i, error, line_num, message);
+                    }
+                }
+            },
+        }
+        println!("Ending match_error_recovery() test..");
+    }
+
+    pub fn match_keyword_args()
+    {
+        let tests = create_tests!(
+            "f(1, x = 2)", "(BLOCK (CALL f 1 (KWARG x 2)))",
+            "f(x = 1, y = a + b,)",
+            "(BLOCK (CALL f (KWARG x 1) (KWARG y (+ a b))))",
+            "f(\nx =\n1\n)", "(BLOCK (CALL f (KWARG x 1)))",
+            "f((x = 1))", "(BLOCK (CALL f (= x 1)))",
+            "f(a[0] = 1)", "(BLOCK (CALL f (= (SUBSCRIPT a 0) 1)))",
+            "f(x == 1)", "(BLOCK (CALL f (== x 1)))"
+        );
+        println!("Starting match_keyword_args() test..");
+        TreeMatcher::__match(&tests);
+
+        let tests = ["f(x = 1, 2)", "f(1, x = 1, a + b)"];
+        for (i, input) in tests.iter().enumerate() {
+            match parse(input, "treematcher") {
+                Ok(mut program) => println!("{}. expected errors, got tree({})",
+                                            i, program.to_string_tree()),
+                Err(errors) => {
+                    if !errors[0].message.ends_with("positional argument \
+                                                     follows keyword \
+                                                     argument") {
+                        println!("{}. unexpected errors({:?})", i, errors);
+                    }
+                },
+            }
+        }
+        println!("Ending match_keyword_args() test..");
+    }
+
+    pub fn match_slices()
+    {
+        let tests = create_tests!(
+            "a[0]", "(BLOCK (SUBSCRIPT a 0))",
+            "a[-1]", "(BLOCK (SUBSCRIPT a (- 1)))",
+            "a[1..3]", "(BLOCK (SLICE a (RANGE 1 3)))",
+            "a[..n]", "(BLOCK (SLICE a (RANGE OPEN_BOUND n)))",
+            "a[n..]", "(BLOCK (SLICE a (RANGE n OPEN_BOUND)))",
+            "a[..]", "(BLOCK (SLICE a (RANGE OPEN_BOUND OPEN_BOUND)))",
+            "a[1..10..2]", "(BLOCK (SLICE a (RANGE 1 10 2)))",
+            "a[b[1..]..]",
+            "(BLOCK (SLICE a (RANGE (SLICE b (RANGE 1 OPEN_BOUND)) \
+                                    OPEN_BOUND)))"
+        );
+        println!("Starting match_slices() test..");
+        TreeMatcher::__match(&tests);
+
+        let tests = ["x = 1..", "[..2]", "a[f(1..)]", "a[(..1)]",
+                     "a[x ? 1 : 2..]", "a[1..]..", "a[....]"];
+        for (i, input) in tests.iter().enumerate() {
+            if let Ok(mut program) = parse(input, "treematcher") {
+                println!("{}. expected errors, got tree({})", i,
+                         program.to_string_tree());
+            }
+        }
+        println!("Ending match_slices() test..");
+    }
+
+    pub fn match_arrow_literals()
+    {
+        let tests = [("f = (a, b) => a + b",
+                      "f = def(a, b) {\nreturn a + b\n}"),
+                     ("(x) => {\ndebug x\n}", "def(x) {\ndebug x\n}"),
+                     ("g(() => 1, 2)", "g(def() {\nreturn 1\n}, 2)"),
+                     ("h = (a, b = 1, *c) =>\n a ? b : c",
+                      "h = def(a, b = 1, *c) {\nreturn a ? b : c\n}"),
+                     ("(a) => (b) => a", "def(a) {\nreturn def(b) {\n\
+                                         return a\n}\n}"),
+                     ("(a) => {}", "def(a) {\nreturn {}\n}"),
+                     ("(a) => {1 => a}", "def(a) {\nreturn {1 => a}\n}"),
+                     ("x = {(a) => 1, 2 => (b) => b}",
+                      "x = {(a) => 1, 2 => def(b) {\nreturn b\n}}"),
+                     ("(a) + 1", "(a) + 1")];
+        println!("Starting match_arrow_literals() test..");
+        for (i, &(arrow, def)) in tests.iter().enumerate() {
+            match (parse(arrow, "treematcher"), parse(def, "treematcher")) {
+                (Ok(mut tree), Ok(mut expected)) => {
+                    if tree.to_string_tree() != expected.to_string_tree() {
+                        println!("{}. tree({}) != expected tree({})", i,
+                                 tree.to_string_tree(),
+                                 expected.to_string_tree());
+                    }
+                },
+                (tree, expected) => println!("{}. unexpected errors({:?}, \
+                                             {:?})", i, tree.err(),
+                                             expected.err()),
+            }
+        }
+        let tests = ["(a + 1) => 2", "(a) => return", "(a) =>"];
+        for (i, input) in tests.iter().enumerate() {
+            if let Ok(mut program) = parse(input, "treematcher") {
+                println!("{}. expected errors, got tree({})", i,
+                         program.to_string_tree());
+            }
+        }
+        println!("Ending match_arrow_literals() test..");
+    }
+
+    pub fn match_comprehensions()
+    {
+        let tests = create_tests!(
+            "[x * 2 for x in arr]",
+            "(BLOCK (ARRAY_COMP (* x 2) (COMP_VARS x) arr))",
+            "[x for x in a if x > 0]",
+            "(BLOCK (ARRAY_COMP x (COMP_VARS x) a (if (> x 0))))",
+            "{k => v for k, v in pairs}",
+            "(BLOCK (HASH_COMP k v (COMP_VARS k v) pairs))",
+            "{k => [v] for k, v in f(1) if k in ks\n}",
+            "(BLOCK (HASH_COMP k (ARRAY_DECL v) (COMP_VARS k v) \
+             (CALL f 1) (if (IN_TEST k ks))))",
+            "[[y for y in x] for x in 1..3]",
+            "(BLOCK (ARRAY_COMP (ARRAY_COMP y (COMP_VARS y) x) (COMP_VARS x) \
+             (RANGE 1 3)))",
+            "[x, y]", "(BLOCK (ARRAY_DECL x y))",
+            "[x,]", "(BLOCK (ARRAY_DECL x))"
+        );
+        println!("Starting match_comprehensions() test..");
+        TreeMatcher::__match(&tests);
+
+        let tests = ["[x, y for x in a]", "[x for 1 in a]", "[x for x a]",
+                     "{1 => 2, k => v for k in a}", "[x for x in a in b]",
+                     "[x for x in a if]"];
+        for (i, input) in tests.iter().enumerate() {
+            if let Ok(mut program) = parse(input, "treematcher") {
+                println!("{}. expected errors, got tree({})", i,
+                         program.to_string_tree());
+            }
+        }
+        println!("Ending match_comprehensions() test..");
+    }
+
+    pub fn match_chained_comparison()
+    {
+        let tests = create_tests!(
+            "a < b", "(BLOCK (< a b))",
+            "a < b < c", "(BLOCK (CHAIN_CMP a (< b) (< c)))",
+            "a < b <= c > d", "(BLOCK (CHAIN_CMP a (< b) (<= c) (> d)))",
+            "a + 1 >= b * 2 < c", "(BLOCK (CHAIN_CMP (+ a 1) (>= (* b 2)) \
+                                   (< c)))",
+            "(a < b) < c", "(BLOCK (< (< a b) c))",
+            "a < b == b < c", "(BLOCK (== (< a b) (< b c)))"
+        );
+        println!("Starting match_chained_comparison() test..");
+        TreeMatcher::__match(&tests);
+        println!("Ending match_chained_comparison() test..");
+    }
+
+    /*
+     * One expression per pair of neighbouring precedence
+     * levels, plus the associativity of each level, so any
+     * change to the binary operator table shows up here.
+     */
+    pub fn match_precedence()
+    {
+        let tests = create_tests!(
+            "a || b && c", "(BLOCK (|| a (&& b c)))",
+            "a && b || c", "(BLOCK (|| (&& a b) c))",
+            "a || b || c", "(BLOCK (|| (|| a b) c))",
+            "a && b == c", "(BLOCK (&& a (== b c)))",
+            "a == b != c", "(BLOCK (!= (== a b) c))",
+            "a == b in c", "(BLOCK (== a (IN_TEST b c)))",
+            "a in b in c", "(BLOCK (IN_TEST (IN_TEST a b) c))",
+            "a in b < c", "(BLOCK (IN_TEST a (< b c)))",
+            "a < b | c", "(BLOCK (< a (| b c)))",
+            "a | b ^ c", "(BLOCK (| a (^ b c)))",
+            "a && b & c", "(BLOCK (&& a (& b c)))",
+            "a || b | c", "(BLOCK (|| a (| b c)))",
+            "a ^ b & c", "(BLOCK (^ a (& b c)))",
+            "a & b << c", "(BLOCK (& a (<< b c)))",
+            "a << b >> c", "(BLOCK (>> (<< a b) c))",
+            "a >> b + c", "(BLOCK (>> a (+ b c)))",
+            "a + b - c", "(BLOCK (- (+ a b) c))",
+            "a - b * c", "(BLOCK (- a (* b c)))",
+            "a * b / c % d", "(BLOCK (% (/ (* a b) c) d))",
+            "a // b * c - d", "(BLOCK (- (* (// a b) c) d))",
+            "-a * !b", "(BLOCK (* (- a) (! b)))",
+            "a | b & c ^ d", "(BLOCK (| a (^ (& b c) d)))",
+            "a + b * c << d < e == f && g || h",
+            "(BLOCK (|| (&& (== (< (<< (+ a (* b c)) d) e) f) g) h))",
+            "a ||\nb +\nc", "(BLOCK (|| a (+ b c)))",
+            "a = b || c ? d..e : f", "(BLOCK (= a (TERNARY (|| b c) \
+                                     (RANGE d e) f)))",
+            "a..b | c", "(BLOCK (RANGE a (| b c)))"
+        );
+        println!("Starting match_precedence() test..");
+        TreeMatcher::__match(&tests);
+        println!("Ending match_precedence() test..");
+    }
+
+    pub fn match_error_spans()
+    {
+        let tests = [("x = f(1 2)", (1, 9), (1, 6), "expected ')' to close \
+                                                  the function call"),
+                     ("y = (1\n\n+ 2)", (3, 1), (1, 5), "expected ')'"),
+                     ("if x 1", (1, 6), (1, 4), "expected '{' to open block")];
+        println!("Starting match_error_spans() test..");
+        for (i, &(input, at, from, expected)) in tests.iter().enumerate() {
+            match parse(input, "treematcher") {
+                Ok(_) => println!("{}. expected errors, parse succeeded", i),
+                Err(errors) => {
+                    let error = &errors[0];
+                    let span = error.from.map(|s| (s.line_num, s.line_pos));
+                    if (error.line_num, error.line_pos) != at ||
+                       span != Some(from) ||
+                       error.expected != Some(expected.to_string()) {
+                        println!("{}. error({:?}) != expected error at \
+                                  {:?} from {:?} ({})", i, error, at,
+                                 from, expected);
+                    }
+                },
+            }
+        }
+        println!("Ending match_error_spans() test..");
+    }
+
+    pub fn match_unclosed()
+    {
+        let tests = [("def f(a) {\n    if a {\n        a\n    }\n",
+                      (1, 10, "'{'")),
+                     ("x = 1\nswitch x {\ncase 1:\n", (2, 10, "'{'")),
+                     ("y = (1 +\n2", (1, 5, "'('")),
+                     ("f(1,\n  [2, 3\n", (2, 3, "'['")),
+                     ("a[0", (1, 2, "'['")),
+                     ("h = {1 => 2\n", (1, 5, "'{'")),
+                     ("g = (a) => (a +\nb", (1, 12, "'('")),
+                     ("{\n(1)\n", (1, 1, "'{'"))];
+        println!("Starting match_unclosed() test..");
+        for (i, &(input, (line_num, line_pos, opener))) in
+            tests.iter().enumerate() {
+            let message = format!("unclosed {} opened at line {}", opener,
+                                  line_num);
+            match parse(input, "treematcher") {
+                Ok(_) => println!("{}. expected errors, parse succeeded", i),
+                Err(errors) => {
+                    let found = errors.iter().any(|error| {
+                        error.line_num == line_num &&
+                        error.line_pos == line_pos &&
+                        error.message == message
+                    });
+                    if !found {
+                        println!("{}. errors({:?}) have no {}:{}: {}", i,
+                                 errors, line_num, line_pos, message);
+                    }
+                },
+            }
+        }
+        println!("Ending match_unclosed() test..");
+    }
+
+    pub fn match_type_annotations()
+    {
+        let tests = create_tests!(
+            "def f(a: Int, b: String) {}",
+            "(BLOCK (SUB_DECL f (SUB_PARAMS (a (TYPE_ANNOT Int)) \
+             (b (TYPE_ANNOT String))) BLOCK))",
+            "def f(a, b: Int = 1, *c: Array) {}",
+            "(BLOCK (SUB_DECL f (SUB_PARAMS a (b (TYPE_ANNOT Int) 1) \
+             (REST_PARAM c (TYPE_ANNOT Array))) BLOCK))",
+            "def f(a, b = 1) {}",
+            "(BLOCK (SUB_DECL f (SUB_PARAMS a (b 1)) BLOCK))",
+            "x: Int = 5", "(BLOCK (= (x (TYPE_ANNOT Int)) 5))",
+            "x: Int =\ny = 5", "(BLOCK (= (x (TYPE_ANNOT Int)) (= y 5)))",
+            "g = (a: Int) => a", "(BLOCK (= g (SUB_LITERAL (SUB_PARAMS \
+             (a (TYPE_ANNOT Int))) (BLOCK (return a)))))",
+            "switch x {\ncase a: b = 1\n}",
+            "(BLOCK (switch x (SWITCH_BRANCH (SWITCH_EXPRS a) \
+             (BLOCK (= b 1)))))",
+            "x = a ? b : c", "(BLOCK (= x (TERNARY a b c)))"
+        );
+        println!("Starting match_type_annotations() test..");
+        TreeMatcher::__match(&tests);
+
+        let tests = [("x: Int", "expected '=' after type annotation"),
+                     ("def f(a: 1) {}", "expected type name"),
+                     ("def f(a: Int = 1, b: Int) {}",
+                      "non-default parameter follows default parameter")];
+        for (i, &(input, message)) in tests.iter().enumerate() {
+            match parse(input, "treematcher") {
+                Ok(_) => println!("{}. expected errors, parse succeeded", i),
+                Err(errors) => {
+                    if !errors[0].message.ends_with(message) {
+                        println!("{}. error({}) != expected error({})", i,
+                                 errors[0].message, message);
+                    }
+                },
+            }
+        }
+        println!("Ending match_type_annotations() test..");
+    }
+
+    pub fn match_parse_expr()
+    {
+        let tests = [("1 + 2", Ok("(+ 1 2)")),
+                     ("\na ? b : c\n\n", Ok("(TERNARY a b c)")),
+                     ("f(x)[0]", Ok("(SUBSCRIPT (CALL f x) 0)")),
+                     ("1 + 2\n3", Err("2:1: unexpected literal near '3', \
+                                       expected end of expression")),
+                     ("1 2", Err("1:3: unexpected literal near '2', \
+                                  expected end of expression")),
+                     ("", Err("1:1: unexpected end-of-file, \
+                               expected expression")),
+                     ("'abc", Err("1:1: unterminated string literal"))];
+        println!("Starting match_parse_expr() test..");
+        for (i, &(input, ref expected)) in tests.iter().enumerate() {
+            let result = match parse_expr(input, "treematcher") {
+                Ok(mut node) => Ok(node.to_string_tree()),
+                Err(error) => Err(format!("{}:{}: {}", error.line_num,
+                                          error.line_pos, error.message)),
+            };
+            let expected = match *expected {
+                Ok(tree) => Ok(tree.to_string()),
+                Err(message) => Err(message.to_string()),
+            };
+            if result != expected {
+                println!("{}. result({:?}) != expected result({:?})", i,
+                         result, expected);
+            }
+        }
+        println!("Ending match_parse_expr() test..");
+    }
+
+    pub fn match_deep_nesting()
+    {
+        let levels = 10000;
+        let tests = [(format!("{}1{}", "(".repeat(levels),
+                              ")".repeat(levels)), 1),
+                     (format!("x = {}y", "!".repeat(levels)), 1),
+                     (format!("{}1{}", "[".repeat(levels),
+                              "]".repeat(levels)), 1),
+                     (format!("{}1{}", "(".repeat(60), ")".repeat(60)), 0),
+                     (format!("{}1{}", "{1 => ".repeat(60), "}".repeat(60)), 0),
+                     (format!("{}1", "-".repeat(60)), 0)];
+        println!("Starting match_deep_nesting() test..");
+        for (i, &(ref input, expected)) in tests.iter().enumerate() {
+            let errors = match parse(input.as_str(), "treematcher") {
+                Ok(_) => Vec::new(),
+                Err(errors) => errors,
+            };
+            if errors.len() != expected {
+                println!("{}. errors({:?}) != expected errors({})", i,
+                         errors, expected);
+            }
+            else if expected != 0 &&
+                    !errors[0].message.ends_with("expression too deeply \
+                                                  nested") {
+                println!("{}. message({}) != expected message", i,
+                         errors[0].message);
+            }
+        }
+        /*
+         * Ternary branches, assignments and blocks nest
+         * without a bracket or unary operator, the error
+         * may be followed by others from recovering. The
+         * innermost if of the nested blocks fails on its
+         * condition, as the blocks have used up every level.
+         */
+        let tests = [format!("x = {}1{}", "1 ? ".repeat(2000),
+                             " : 2".repeat(2000)),
+                     format!("x = {}1", "a = ".repeat(50000)),
+                     format!("{}1{}", "if 1 {\n".repeat(5000),
+                             "\n}".repeat(5000))];
+        for (i, input) in tests.iter().enumerate() {
+            match parse(input.as_str(), "treematcher") {
+                Ok(_) => println!("{}. expected error, parse succeeded", i),
+                Err(errors) => {
+                    if !errors[0].message.ends_with("too deeply nested") {
+                        println!("{}. message({}) != expected message", i,
+                                 errors[0].message);
+                    }
+                },
+            }
+        }
+        let module = Module::new("treematcher".to_string());
+        let input = "def f() {\n    def g() {\n    }\n}";
+        let mut scanner = Scanner::new(input, &module);
+        let mut parser = Parser::new(&mut scanner, &module);
+
+        parser.set_max_depth(1);
+        match parser.program() {
+            Ok(_) => println!("expected error, nested blocks parsed"),
+            Err(errors) => {
+                if !errors[0].message.ends_with("block too deeply nested") {
+                    println!("message({}) != expected message(block too \
+                              deeply nested)", errors[0].message);
+                }
+            },
+        }
+        let tests = [("((1))", true), ("(((1)))", false), ("!!1", true),
+                     ("-!-1", false)];
+        for (i, &(input, expected)) in tests.iter().enumerate() {
+            let module = Module::new("treematcher".to_string());
+            let mut scanner = Scanner::new(input, &module);
+            let mut parser = Parser::new(&mut scanner, &module);
+
+            parser.set_max_depth(3);
+            if parser.program().is_ok() != expected {
+                println!("{}. parse({}) with max depth 3 != expected({})",
+                         i, input, expected);
+            }
+        }
+        println!("Ending match_deep_nesting() test..");
+    }
+
+    /*
+     * Parses a few hundred lines of generated source and
+     * checks that every statement made it into the tree,
+     * benches/parser.rs times the same input.
+     */
+    pub fn match_large_input()
+    {
+        let repeats = 50;
+        let mut input = String::new();
+
+        for i in 0..repeats {
+            input.push_str(format!("def f(a, b = {}) {{\n\
+                                    \x20   if a > b {{\n\
+                                    \x20       return a * (b - 1)\n\
+                                    \x20   }}\n\
+                                    \x20   return [a, b, \"s\"][0]\n\
+                                    }}\n\
+                                    x = f(1, 2) + {}\n", i, i)
+                           .as_str());
+        }
+        println!("Starting match_large_input() test..");
+        match parse(input.as_str(), "treematcher") {
+            Ok(program) => {
+                if program.children.len() != repeats * 2 {
+                    println!("statements({}) != expected statements({})",
+                             program.children.len(), repeats * 2);
+                }
+            },
+            Err(errors) => println!("unexpected errors({:?})", errors),
+        }
+        println!("Ending match_large_input() test..");
+    }
+
+    pub fn match_all()
+    {
+        TreeMatcher::match_switch();
+        TreeMatcher::match_patterns();
+        TreeMatcher::match_loop_else();
+        TreeMatcher::match_empty_program();
+        TreeMatcher::match_diagnostics();
+        TreeMatcher::match_do_expr();
+        TreeMatcher::match_associativity();
+        TreeMatcher::match_member();
+        TreeMatcher::match_ternary();
+        TreeMatcher::match_error_recovery();
+        TreeMatcher::match_adjacent_minus();
+        TreeMatcher::match_unary_plus();
+        TreeMatcher::match_token_source();
+        TreeMatcher::match_assignment_targets();
+        TreeMatcher::match_multi_assign();
+        TreeMatcher::match_compound_assign();
+        TreeMatcher::match_if_chains();
+        TreeMatcher::match_const();
+        TreeMatcher::match_with();
+        TreeMatcher::match_assert();
+        TreeMatcher::match_return_and_yield();
+        TreeMatcher::match_imports();
+        TreeMatcher::match_recovered_statements();
+        TreeMatcher::match_hash_or_block();
+        TreeMatcher::match_trailing_commas();
+        TreeMatcher::match_multiline_brackets();
+        TreeMatcher::match_default_params();
+        TreeMatcher::match_rest_params();
+        TreeMatcher::match_soft_keywords();
+        TreeMatcher::match_word_operators();
+        TreeMatcher::match_in_test();
+        TreeMatcher::match_range();
+        TreeMatcher::match_render();
+        TreeMatcher::match_keyword_args();
+        TreeMatcher::match_slices();
+        TreeMatcher::match_arrow_literals();
+        TreeMatcher::match_comprehensions();
+        TreeMatcher::match_chained_comparison();
+        TreeMatcher::match_precedence();
+        TreeMatcher::match_unclosed();
+        TreeMatcher::match_error_spans();
+        TreeMatcher::match_type_annotations();
+        TreeMatcher::match_parse_expr();
+        TreeMatcher::match_deep_nesting();
+        TreeMatcher::match_large_input();
+    }
+
+    fn __match(tests: &[TreeMatcher])
+    {
+        let mut i = 0;
+        for tt in tests {
+            let tree = match parse(tt.input, "treematcher") {
+                Ok(mut program) => program.to_string_tree(),
+                Err(errors) => {
+                    for error in errors {
+                        println!("{}. {}", i, error);
+                    }
+                    String::new()
+                },
+            };
+            if tree != tt.expected_tree {
+                println!("{}. tree({}) != expected tree({})",
+                         i, tree, tt.expected_tree);
+            }
+            i += 1
+        }
+    }
+}
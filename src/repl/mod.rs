@@ -0,0 +1,194 @@
+/*
+ * An interactive session, every line read is parsed and
+ * run by the same interpreter so variables carry over
+ * from one line to the next. Input which leaves a bracket,
+ * string or long comment open is continued on the lines
+ * that follow before anything is run.
+ *
+ * Lines starting with ':' are commands to the repl itself,
+ * ':quit' ends the session and ':tree' toggles printing the
+ * tree of each program before it is run.
+ */
+use std::cell::RefCell;
+use std::io;
+use std::io::{BufRead, Write};
+use std::rc::Rc;
+use token::TokenType::*;
+use intermediate::*;
+use scanner::scanner::*;
+use parser::*;
+use interpreter::*;
+use module::Module;
+
+pub mod repl_test;
+
+pub struct Repl {
+    pub prompt: String,
+    // Shown while an unfinished statement is being read.
+    pub continuation_prompt: String,
+    pub echo_tree: bool,
+    interpreter: Interpreter,
+    /*
+     * What the program writes, with debug or print, held
+     * until the line has run and then passed on to the
+     * output of run_with.
+     */
+    program_output: Rc<RefCell<Vec<u8>>>,
+}
+
+struct SharedOutput(Rc<RefCell<Vec<u8>>>);
+
+impl Write for SharedOutput
+{
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize>
+    {
+        return self.0.borrow_mut().write(buf);
+    }
+
+    fn flush(&mut self) -> io::Result<()>
+    {
+        return Ok(());
+    }
+}
+
+impl Repl
+{
+    pub fn new() -> Repl
+    {
+        let program_output = Rc::new(RefCell::new(Vec::new()));
+        let out = Box::new(SharedOutput(program_output.clone()));
+
+        return Repl {
+            prompt: "ares> ".to_string(),
+            continuation_prompt: "  ... ".to_string(),
+            echo_tree: false,
+            interpreter: Interpreter::with_output(out),
+            program_output: program_output,
+        };
+    }
+
+    pub fn run(&mut self) -> io::Result<()>
+    {
+        let stdin = io::stdin();
+        let stdout = io::stdout();
+
+        return self.run_with(stdin.lock(), &mut stdout.lock());
+    }
+
+    pub fn run_with<R: BufRead, W: Write>(&mut self, input: R,
+                                          output: &mut W)
+        -> io::Result<()>
+    {
+        let mut source = String::new();
+
+        write!(output, "{}", self.prompt)?;
+        output.flush()?;
+        for line in input.lines() {
+            let line = line?;
+            if source.len() == 0 && line.trim().starts_with(':') {
+                match line.trim() {
+                    ":quit" => return Ok(()),
+                    ":tree" => self.echo_tree = !self.echo_tree,
+                    command => writeln!(output, "unknown command '{}'",
+                                        command)?,
+                }
+                write!(output, "{}", self.prompt)?;
+                output.flush()?;
+                continue;
+            }
+            source.push_str(line.as_str());
+            source.push('\n');
+            if is_incomplete(source.as_str()) {
+                write!(output, "{}", self.continuation_prompt)?;
+                output.flush()?;
+                continue;
+            }
+            self.eval_line(source.as_str(), output)?;
+            source.clear();
+            write!(output, "{}", self.prompt)?;
+            output.flush()?;
+        }
+        // Input ended halfway through, report what is wrong.
+        if source.len() != 0 {
+            self.eval_line(source.as_str(), output)?;
+        }
+        return Ok(());
+    }
+
+    /*
+     * Runs one line of input and writes what the program
+     * wrote followed by the value to echo, which is nothing
+     * unless the last statement was an expression giving
+     * something other than nil. Assigning 'x = 5' is silent
+     * while 'x + 1' echoes 6.
+     */
+    fn eval_line<W: Write>(&mut self, line: &str, output: &mut W)
+        -> io::Result<()>
+    {
+        let mut program = match parse(line, "repl") {
+            Ok(program) => program,
+            Err(errors) => {
+                for error in errors {
+                    writeln!(output, "{}", error.render(line))?;
+                }
+                return Ok(());
+            },
+        };
+        if self.echo_tree {
+            writeln!(output, "{}", program.to_string_tree())?;
+        }
+        let result = self.interpreter.run(&program);
+        output.write_all(&self.program_output.borrow())?;
+        self.program_output.borrow_mut().clear();
+
+        let value = match result {
+            Ok(value) => value,
+            Err(error) => return writeln!(output, "{}", error),
+        };
+        let echo = match program.children.last() {
+            Some(statement) => is_expression(statement),
+            None => false,
+        };
+        if echo && !value.is_nil() {
+            writeln!(output, "{}", value)?;
+        }
+        return Ok(());
+    }
+}
+
+/*
+ * Whether source stops in the middle of something, that is
+ * with more brackets opened than closed or within a string
+ * or long comment.
+ */
+fn is_incomplete(source: &str) -> bool
+{
+    let module = Module::new("repl".to_string());
+    let mut scanner = Scanner::new(source, &module);
+    let mut depth = 0;
+
+    for token in scanner.by_ref() {
+        match token.token_type {
+            LPAREN | LBRACK | LBRACE => depth += 1,
+            RPAREN | RBRACK | RBRACE => depth -= 1,
+            _ => (),
+        }
+    }
+    for error in scanner.take_errors() {
+        if error.message.starts_with("unterminated") {
+            return true;
+        }
+    }
+    return depth > 0;
+}
+
+fn is_expression(node: &Node) -> bool
+{
+    return match node.get_type() {
+        ASSIGN | MULTI_ASSIGN | CONST_DECL | IF | WHILE | UNTIL | FOR |
+        WITH | SWITCH | MATCH | SUB_DECL | IMPORT | DEBUG | ASSERT |
+        RETURN | YIELD => false,
+        token_type if token_type.is_assignment() => false,
+        _ => true,
+    }
+}
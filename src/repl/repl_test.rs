@@ -0,0 +1,86 @@
+/*
+ * Test that the repl only echoes the values of
+ * expression statements.
+ */
+use repl::*;
+
+pub struct ReplMatcher;
+
+impl ReplMatcher
+{
+    pub fn match_echo()
+    {
+        let input = "x = 5\nx + 1\nif x > 1 { 2 }\nnil\nx = x * 2; x\ny\n";
+        let expected = "6\n10\n1:1: undefined variable 'y'\n";
+        println!("Starting match_echo() test..");
+        let mut repl = Repl::new();
+        let mut output: Vec<u8> = Vec::new();
+
+        repl.prompt = "".to_string();
+        if let Err(error) = repl.run_with(input.as_bytes(), &mut output) {
+            println!("unexpected io error({})", error);
+        }
+        let output = String::from_utf8(output).unwrap();
+        if output != expected {
+            println!("output({:?}) != expected output({:?})", output,
+                     expected);
+        }
+        println!("Ending match_echo() test..");
+    }
+
+    pub fn match_multiline()
+    {
+        let input = "x = do {\n1 +\n2\n}\nx\n:tree\nf(1,\n\
+                     2)[0]\n:tree\ns = 'a\nb'\ns\n:quit\nx\n";
+        let expected = "..\n..\n..\n3\n..\n(BLOCK (SUBSCRIPT (CALL f 1 2) 0))\n\
+                        2:3: cannot evaluate 'SUBSCRIPT' yet\n..\na\nb\n";
+        println!("Starting match_multiline() test..");
+        let mut repl = Repl::new();
+        let mut output: Vec<u8> = Vec::new();
+
+        repl.prompt = "".to_string();
+        repl.continuation_prompt = "..\n".to_string();
+        if let Err(error) = repl.run_with(input.as_bytes(), &mut output) {
+            println!("unexpected io error({})", error);
+        }
+        let output = String::from_utf8(output).unwrap();
+        if output != expected {
+            println!("output({:?}) != expected output({:?})", output,
+                     expected);
+        }
+        println!("Ending match_multiline() test..");
+    }
+
+    /*
+     * What the program writes goes to the same output as
+     * the prompts and echoed values, in the order written.
+     */
+    pub fn match_program_output()
+    {
+        let input = "debug 41\nx = 1\nprint('x is', x); x + 1\n\
+                     debug 1; 1 / 0\n";
+        let expected = "> 41\n> > x is 1\n2\n> 1\n\
+                        1:12: division by zero\n> ";
+        println!("Starting match_program_output() test..");
+        let mut repl = Repl::new();
+        let mut output: Vec<u8> = Vec::new();
+
+        repl.prompt = "> ".to_string();
+        if let Err(error) = repl.run_with(input.as_bytes(), &mut output) {
+            println!("unexpected io error({})", error);
+        }
+        let output = String::from_utf8(output).unwrap();
+        if output != expected {
+            println!("output({:?}) != expected output({:?})", output,
+                     expected);
+        }
+        println!("Ending match_program_output() test..");
+    }
+
+    pub fn match_all()
+    {
+        ReplMatcher::match_echo();
+        ReplMatcher::match_program_output();
+        ReplMatcher::match_multiline();
+    }
+}
@@ -0,0 +1,381 @@
+/*
+ * Name resolution ahead of evaluation. The scopes follow
+ * those of the Environment, a block or a loop opens a new
+ * one, and assigning to a name which is not bound anywhere
+ * binds it in the innermost scope. A name can only be read
+ * after the assignment binding it, with the exception of
+ * subroutine declarations which are visible throughout the
+ * block declaring them, so subroutines may call each other
+ * regardless of their order. The builtins are always
+ * defined, unless a variable shadows them.
+ */
+use std::collections::HashMap;
+use token::TokenType::*;
+use intermediate::*;
+use interpreter::builtins::Builtin;
+/*
+ * The parser's diagnostics, every problem found here is
+ * reported as a Warning.
+ */
+pub use parser::{Diagnostic, Severity};
+
+pub mod resolve_test;
+
+struct Binding {
+    is_param: bool,
+    is_const: bool,
+    used: bool,
+    line_num: i32,
+    line_pos: i32,
+}
+
+struct Resolver {
+    scopes: Vec<HashMap<String, Binding>>,
+    diagnostics: Vec<Diagnostic>,
+    // Every module imported anywhere in the program.
+    imports: Vec<String>,
+}
+
+/*
+ * Reports every read of an undefined name and every unused
+ * parameter, parameters named with a leading '_' are
+ * expected to be unused. The root need not be a BLOCK, a
+ * lone expression from parse_expr binds into a scope of
+ * its own.
+ */
+pub fn resolve(program: &Node) -> Vec<Diagnostic>
+{
+    let mut resolver = Resolver {
+        scopes: Vec::new(),
+        diagnostics: Vec::new(),
+        imports: Vec::new(),
+    };
+    resolver.push_scope();
+    resolver.visit(program);
+    resolver.pop_scope();
+
+    return resolver.diagnostics;
+}
+
+impl Resolver
+{
+    fn push_scope(&mut self)
+    {
+        self.scopes.push(HashMap::new());
+    }
+
+    fn pop_scope(&mut self)
+    {
+        let scope = match self.scopes.pop() {
+            Some(scope) => scope,
+            None => return,
+        };
+        let mut unused: Vec<(&String, &Binding)> = scope.iter()
+            .filter(|&(name, b)| b.is_param && !b.used &&
+                                 !name.starts_with('_'))
+            .collect();
+        unused.sort_by_key(|&(_, b)| (b.line_num, b.line_pos));
+        for (name, binding) in unused {
+            self.diagnostics.push(Diagnostic {
+                severity: Severity::Warning,
+                line_num: binding.line_num,
+                line_pos: binding.line_pos,
+                message: format!("unused parameter '{}'", name),
+            });
+        }
+    }
+
+    fn define(&mut self, node: &Node, is_param: bool)
+    {
+        let last = self.scopes.len() - 1;
+        self.scopes[last].insert(node.string().to_string(), Binding {
+            is_param: is_param,
+            is_const: false,
+            used: false,
+            line_num: node.token.line_num,
+            line_pos: node.token.line_pos,
+        });
+    }
+
+    fn is_bound(&self, name: &str) -> bool
+    {
+        return self.scopes.iter().any(|scope| scope.contains_key(name));
+    }
+
+    fn visit(&mut self, node: &Node)
+    {
+        match node.get_type() {
+            IDENT => self.variable(node),
+            BLOCK => self.block(node),
+            IMPORT => self.import_statement(&node.children[0]),
+            ASSIGN => self.assignment(node),
+            token_type if token_type.is_assignment() => {
+                self.compound_assignment(node)
+            },
+            CONST_DECL => self.constant(node),
+            MULTI_ASSIGN => {
+                self.visit(&node.children[1]);
+                for target in &node.children[0].children {
+                    self.bind(target);
+                }
+            },
+            FOR => self.for_statement(node),
+            WITH => self.with_statement(node),
+            SUB_DECL => self.subroutine(&node.children[1],
+                                        &node.children[2]),
+            SUB_LITERAL => self.subroutine(&node.children[0],
+                                           &node.children[1]),
+            ARRAY_COMP | HASH_COMP => self.comprehension(node),
+            MATCH_ARM => self.match_arm(node),
+            // Only the object is a variable, not the member.
+            MEMBER => self.visit(&node.children[0]),
+            KWARG => self.visit(&node.children[1]),
+            TYPE_ANNOT => (),
+            _ => {
+                for child in &node.children {
+                    self.visit(child);
+                }
+            },
+        }
+    }
+
+    fn variable(&mut self, node: &Node)
+    {
+        for scope in self.scopes.iter_mut().rev() {
+            if let Some(binding) = scope.get_mut(node.string()) {
+                binding.used = true;
+                return;
+            }
+        }
+        if Builtin::lookup(node.string()).is_some() {
+            return;
+        }
+        self.diagnostics.push(Diagnostic {
+            severity: Severity::Warning,
+            line_num: node.token.line_num,
+            line_pos: node.token.line_pos,
+            message: format!("undefined variable '{}'", node.string()),
+        });
+    }
+
+    fn block(&mut self, node: &Node)
+    {
+        self.push_scope();
+        for child in &node.children {
+            if child.get_type() == SUB_DECL {
+                self.define(&child.children[0], false);
+            }
+        }
+        for child in &node.children {
+            self.visit(child);
+        }
+        self.pop_scope();
+    }
+
+    /*
+     * An import binds the module name like a variable, so
+     * 'foo.bar' resolves once 'foo' is imported. The
+     * members themselves are not checked, the module is
+     * never loaded.
+     */
+    fn import_statement(&mut self, module: &Node)
+    {
+        let name = module.string().to_string();
+        if self.imports.contains(&name) {
+            self.diagnostics.push(Diagnostic {
+                severity: Severity::Warning,
+                line_num: module.token.line_num,
+                line_pos: module.token.line_pos,
+                message: format!("module '{}' is already imported", name),
+            });
+            return;
+        }
+        self.imports.push(name);
+        if !self.is_bound(module.string()) {
+            self.define(module, false);
+        }
+    }
+
+    /*
+     * The value is resolved first, 'x = x + 1' reads x
+     * before the assignment could bind it.
+     */
+    fn assignment(&mut self, node: &Node)
+    {
+        self.visit(&node.children[1]);
+        self.bind(&node.children[0]);
+    }
+
+    /*
+     * 'x += 1' reads x before writing it, so x has to be
+     * bound already rather than being bound here.
+     */
+    fn compound_assignment(&mut self, node: &Node)
+    {
+        self.visit(&node.children[1]);
+        let target = &node.children[0];
+        if target.get_type() == IDENT && self.is_const(target.string()) {
+            self.const_error(target);
+        }
+        else {
+            self.visit(target);
+        }
+    }
+
+    fn bind(&mut self, target: &Node)
+    {
+        if target.get_type() != IDENT {
+            self.visit(target);
+        }
+        else if self.is_const(target.string()) {
+            self.const_error(target);
+        }
+        else if !self.is_bound(target.string()) {
+            self.define(target, false);
+        }
+    }
+
+    /*
+     * A constant is bound in the innermost scope, like a
+     * parameter it may shadow an outer name. It can not
+     * take the place of a constant though, and nothing
+     * may assign to it afterwards.
+     */
+    fn constant(&mut self, node: &Node)
+    {
+        let name = &node.children[0];
+        self.visit(&node.children[1]);
+        if self.is_const(name.string()) {
+            self.const_error(name);
+            return;
+        }
+        self.define(name, false);
+        let last = self.scopes.len() - 1;
+        if let Some(binding) = self.scopes[last].get_mut(name.string()) {
+            binding.is_const = true;
+        }
+    }
+
+    // Whether name, as it resolves from here, is a constant.
+    fn is_const(&self, name: &str) -> bool
+    {
+        for scope in self.scopes.iter().rev() {
+            if let Some(binding) = scope.get(name) {
+                return binding.is_const;
+            }
+        }
+        return false;
+    }
+
+    fn const_error(&mut self, target: &Node)
+    {
+        self.diagnostics.push(Diagnostic {
+            severity: Severity::Warning,
+            line_num: target.token.line_num,
+            line_pos: target.token.line_pos,
+            message: format!("cannot assign to constant '{}'",
+                             target.string()),
+        });
+    }
+
+    fn for_statement(&mut self, node: &Node)
+    {
+        self.visit(&node.children[1]);
+        if let Some(step) = node.child(STEP) {
+            self.visit(step);
+        }
+        self.push_scope();
+        self.define(&node.children[0], false);
+        self.visit(&node.children[2]);
+        self.pop_scope();
+        if let Some(else_node) = node.child(ELSE) {
+            self.visit(else_node);
+        }
+    }
+
+    /*
+     * The names are bound one at a time, a resource can
+     * make use of those bound before it.
+     */
+    fn with_statement(&mut self, node: &Node)
+    {
+        let last = node.children.len() - 1;
+        self.push_scope();
+        for pair in node.children[..last].chunks(2) {
+            self.visit(&pair[0]);
+            self.define(&pair[1], false);
+        }
+        self.visit(&node.children[last]);
+        self.pop_scope();
+    }
+
+    /*
+     * The parameters are bound left to right, a default
+     * value can read the parameters preceding it.
+     */
+    fn subroutine(&mut self, params: &Node, body: &Node)
+    {
+        self.push_scope();
+        for param in &params.children {
+            if param.get_type() == REST_PARAM {
+                self.define(&param.children[0], true);
+                continue;
+            }
+            for child in &param.children {
+                self.visit(child);
+            }
+            self.define(param, true);
+        }
+        self.visit(body);
+        self.pop_scope();
+    }
+
+    // The names in the pattern are bound for the arm only.
+    fn match_arm(&mut self, node: &Node)
+    {
+        self.push_scope();
+        self.bind_pattern(&node.children[0]);
+        self.visit(&node.children[1]);
+        self.pop_scope();
+    }
+
+    fn bind_pattern(&mut self, pattern: &Node)
+    {
+        match pattern.get_type() {
+            IDENT => self.define(pattern, false),
+            // A hash key is a constant, only its value binds.
+            HASH_ELEM => self.bind_pattern(&pattern.children[1]),
+            _ => {
+                for child in &pattern.children {
+                    self.bind_pattern(child);
+                }
+            },
+        }
+    }
+
+    /*
+     * The iterable is resolved outside the comprehension,
+     * the element and the filter see its loop variables.
+     */
+    fn comprehension(&mut self, node: &Node)
+    {
+        let position = node.children.iter()
+                                    .position(|c| c.get_type() == COMP_VARS);
+        let vars = match position {
+            Some(vars) => vars,
+            None => return,
+        };
+        self.visit(&node.children[vars + 1]);
+
+        self.push_scope();
+        for var in &node.children[vars].children {
+            self.define(var, false);
+        }
+        for (i, child) in node.children.iter().enumerate() {
+            if i != vars && i != vars + 1 {
+                self.visit(child);
+            }
+        }
+        self.pop_scope();
+    }
+}
@@ -0,0 +1,118 @@
+/*
+ * Test that resolve reports undefined names and unused
+ * parameters following the scoping rules of the
+ * environment.
+ */
+use parser::*;
+use resolve::*;
+
+pub struct ResolveMatcher;
+
+impl ResolveMatcher
+{
+    pub fn match_diagnostics()
+    {
+        let tests = [
+            ("x = 1\ny = x + 1", vec![]),
+            ("y = x + 1", vec![(1, 5, "undefined variable 'x'")]),
+            ("x = x + 1", vec![(1, 5, "undefined variable 'x'")]),
+            // A read ahead of the assignment in the same block.
+            ("debug a\na = 1", vec![(1, 7, "undefined variable 'a'")]),
+            ("if true {\nb = 1\n}\ndebug b",
+             vec![(4, 7, "undefined variable 'b'")]),
+            ("b = 0\nif true {\nb = 1\n}\ndebug b", vec![]),
+            // Shadowing an outer name by a parameter or a loop variable.
+            ("x = 1\ndef f(x) {\nreturn x\n}\nfor x in 1..3 {\ndebug x\n}",
+             vec![]),
+            ("def f() {\nreturn g()\n}\ndef g() {\nreturn f()\n}", vec![]),
+            ("def f(a, b) {\nreturn a\n}",
+             vec![(1, 10, "unused parameter 'b'")]),
+            ("def f(a, _b, *c) {\nreturn a\n}",
+             vec![(1, 15, "unused parameter 'c'")]),
+            ("def f(a, b = a + 1) {\nreturn b\n}", vec![]),
+            ("def f(a = b, b = 1) {\nreturn a + b\n}",
+             vec![(1, 11, "undefined variable 'b'")]),
+            ("s = [x * 2 for x in s if x > 0]\ndebug x",
+             vec![(1, 21, "undefined variable 's'"),
+                  (2, 7, "undefined variable 'x'")]),
+            ("o = 1\no.size(n = 1)", vec![]),
+            ("n = len([1, 2])\nprint(str(n))", vec![]),
+            ("a, b = 1, a", vec![(1, 11, "undefined variable 'a'")]),
+            ("import foo\nfoo.bar(1)\nbar.foo", vec![(3, 1,
+             "undefined variable 'bar'")]),
+            ("import foo\nif true {\nimport foo\n}", vec![(3, 8,
+             "module 'foo' is already imported")]),
+            ("a, b = 1, 2\na, b = b, a", vec![]),
+            ("g = (a, b) => a", vec![(1, 9, "unused parameter 'b'")]),
+            ("const PI = 3.14\nx = PI * 2\nx = 1", vec![]),
+            ("const PI = 3.14\nPI = 3", vec![(2, 1,
+             "cannot assign to constant 'PI'")]),
+            ("const N = 1\nif true {\na, N = 1, 2\n}", vec![(3, 4,
+             "cannot assign to constant 'N'")]),
+            ("x = 1\nx <<= 2\ny |= x", vec![(3, 1,
+             "undefined variable 'y'")]),
+            ("const N = 1\nN &= 2", vec![(2, 1,
+             "cannot assign to constant 'N'")]),
+            ("const N = 1\nconst N = 2", vec![(2, 7,
+             "cannot assign to constant 'N'")]),
+            ("const N = 1\ndef f(N) {\nN = 2\nreturn N\n}", vec![]),
+            ("N = 1\nconst N = 2", vec![]),
+            ("a = 1\nwith a as f, f as g {\ndebug g\n}\ndebug f",
+             vec![(5, 7, "undefined variable 'f'")]),
+            ("x = [1]\nmatch x {\n[a, {'k' => b}] => debug a + b\n\
+              _ => debug a\n}", vec![(4, 12, "undefined variable 'a'")]),
+        ];
+        println!("Starting match_diagnostics() test..");
+        for (i, &(input, ref expected)) in tests.iter().enumerate() {
+            let program = match parse(input, "resolvematcher") {
+                Ok(program) => program,
+                Err(errors) => {
+                    println!("{}. unexpected errors({:?})", i, errors);
+                    continue;
+                },
+            };
+            let diagnostics = resolve(&program);
+            let found: Vec<(i32, i32, &str)> = diagnostics.iter()
+                .map(|d| (d.line_num, d.line_pos, d.message.as_str()))
+                .collect();
+            if found != *expected {
+                println!("{}. diagnostics({:?}) != expected diagnostics({:?})",
+                         i, found, expected);
+            }
+        }
+        println!("Ending match_diagnostics() test..");
+    }
+
+    pub fn match_expressions()
+    {
+        let tests = [("x = 1", vec![]),
+                     ("y + 1", vec![(1, 1, "undefined variable 'y'")]),
+                     ("(a) => a + b", vec![(1, 12, "undefined variable 'b'")]),
+                     ("[x for x in 1..3]", vec![])];
+        println!("Starting match_expressions() test..");
+        for (i, &(input, ref expected)) in tests.iter().enumerate() {
+            let expr = match parse_expr(input, "resolvematcher") {
+                Ok(expr) => expr,
+                Err(error) => {
+                    println!("{}. unexpected error({})", i, error);
+                    continue;
+                },
+            };
+            let diagnostics = resolve(&expr);
+            let found: Vec<(i32, i32, &str)> = diagnostics.iter()
+                .map(|d| (d.line_num, d.line_pos, d.message.as_str()))
+                .collect();
+            if found != *expected {
+                println!("{}. diagnostics({:?}) != expected diagnostics({:?})",
+                         i, found, expected);
+            }
+        }
+        println!("Ending match_expressions() test..");
+    }
+
+    pub fn match_all()
+    {
+        ResolveMatcher::match_diagnostics();
+        ResolveMatcher::match_expressions();
+    }
+}
@@ -1,6 +1,12 @@
 
+use std::char;
 use std::collections::HashMap;
+use std::collections::VecDeque;
 use std::str::FromStr;
+use std::fmt;
+use std::mem;
+use std::num::{IntErrorKind, ParseIntError};
+use std::rc::Rc;
 use token::*;
 use token::TokenType::*;
 use token::Value::*;
@@ -10,7 +16,7 @@ const EOF_CHAR: char = '\0';
 
 macro_rules! get_literal {
     ($program:expr, $s:expr, $e:expr) => (
-        $program[$s as usize..$e as usize].to_string();
+        &$program[$s as usize..$e as usize];
     );
 }
 
@@ -26,6 +32,219 @@ macro_rules! create_map {
     );
 }
 
+/*
+ * A lexical error. Like the parser, the scanner records
+ * errors and keeps going, so the caller gets every error
+ * in the input rather than just the first one.
+ */
+#[derive(Clone, Debug, PartialEq)]
+pub struct ScanError {
+    pub filename: String,
+    pub line_num: i32,
+    pub line_pos: i32,
+    pub message: String,
+}
+
+impl fmt::Display for ScanError
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+    {
+        return write!(f, "{}:{}:{}: {}", self.filename, self.line_num,
+                      self.line_pos, self.message);
+    }
+}
+
+impl ScanError
+{
+    /*
+     * The error followed by the line it was found on and a
+     * caret under the column, source being the text that
+     * was scanned.
+     */
+    pub fn render(&self, source: &str) -> String
+    {
+        return self.render_with_tabs(source, DEFAULT_TAB_WIDTH);
+    }
+
+    // As render, for a scanner given another tab width.
+    pub fn render_with_tabs(&self, source: &str, tab_width: usize) -> String
+    {
+        return format!("{}\n{}", self, excerpt(source, self.line_num,
+                                               self.line_pos, tab_width));
+    }
+}
+
+/*
+ * Columns a tab advances to the next multiple of, plus one,
+ * unless the scanner is told otherwise by set_tab_width.
+ */
+pub const DEFAULT_TAB_WIDTH: usize = 8;
+
+/*
+ * Longest identifier and string literal, in bytes, the
+ * scanner accepts unless told otherwise. Far beyond any
+ * hand written code, they only bound what a hostile input
+ * can make the scanner allocate.
+ */
+pub const DEFAULT_MAX_IDENT_LEN: usize = 64 * 1024;
+pub const DEFAULT_MAX_STRING_LEN: usize = 64 * 1024;
+
+/*
+ * Slices line line_num out of source and puts a '^' below
+ * column line_pos. Lines end where the scanner ends them,
+ * at '\n', '\r\n' or a lone '\r'. Tabs before the column
+ * are copied into the caret line so it lines up however
+ * wide the terminal draws them, the column is taken to
+ * count them tab_width wide, as the scanner did.
+ */
+pub fn excerpt(source: &str, line_num: i32, line_pos: i32,
+               tab_width: usize) -> String
+{
+    let tab_width = tab_width.max(1);
+    let line = source.split('\n')
+                     .flat_map(|line| {
+                         let line = line.trim_end_matches('\r');
+                         line.split('\r')
+                     })
+                     .nth((line_num - 1).max(0) as usize)
+                     .unwrap_or("");
+    let column = (line_pos - 1).max(0) as usize;
+    let mut caret = String::new();
+    let mut width = 0;
+
+    for ch in line.chars() {
+        if width >= column {
+            break;
+        }
+        if ch == '\t' {
+            caret.push('\t');
+            width = (width / tab_width + 1) * tab_width;
+        }
+        else {
+            caret.push(' ');
+            width += ch.len_utf8();
+        }
+    }
+    // Past the end of the line, as for end-of-file errors.
+    while width < column {
+        caret.push(' ');
+        width += 1;
+    }
+    caret.push('^');
+
+    return format!("{}\n{}", line, caret);
+}
+
+/*
+ * Scans the whole program into a vector of tokens, the
+ * last one being the EOF token.
+ */
+pub fn tokenize(source: &str, filename: &str)
+    -> Result<Vec<Token>, Vec<ScanError>>
+{
+    let module = Module::new(filename.to_string());
+    let mut scanner = Scanner::new(source, &module);
+    let tokens: Vec<Token> = scanner.by_ref().collect();
+
+    let errors = scanner.take_errors();
+    if errors.len() != 0 {
+        return Err(errors);
+    }
+    return Ok(tokens);
+}
+
+/*
+ * Spans of the whole of source as (type, start, end) byte
+ * offsets, for a highlighter. Whitespace and comments are
+ * included as WHITESPACE and COMMENT spans, newlines are
+ * NEWLINE tokens as always. Errors do not stop it, an
+ * unrecognized character is simply left out.
+ */
+pub fn highlight(source: &str) -> Vec<(TokenType, usize, usize)>
+{
+    let module = Module::new("highlight".to_string());
+    let mut scanner = Scanner::new(source, &module);
+
+    scanner.trivia = true;
+    return scanner.by_ref()
+                  .filter(|token| token.token_type != EOF)
+                  .map(|token| (token.token_type, token.start, token.end))
+                  .collect();
+}
+
+// A digit of a hex literal, 0-9 or a-f in either case.
+fn is_hexdigit(ch: char) -> bool
+{
+    return ch.is_digit(16);
+}
+
+/*
+ * The value of a hex float from the text after its '0x',
+ * eg. '1.8p3'. The digits make up an integer mantissa,
+ * each one after the point taking 4 off the binary
+ * exponent, which is applied last. Digits beyond what the
+ * mantissa can hold only scale it, they are too small to
+ * change an f64.
+ */
+fn hex_float(text: &str) -> f64
+{
+    let (digits, mut exponent) = match text.find(|c| c == 'p' || c == 'P') {
+        Some(i) => {
+            let power = &text[i + 1..];
+            let exponent = match power.parse::<i64>() {
+                Ok(exponent) => exponent.max(-10000).min(10000),
+                Err(_) if power.starts_with('-') => -10000,
+                Err(_) => 10000,
+            };
+            (&text[..i], exponent as i32)
+        },
+        None => (text, 0),
+    };
+    let mut mantissa: u64 = 0;
+    let mut fraction = false;
+    for ch in digits.chars() {
+        if ch == '.' {
+            fraction = true;
+            continue;
+        }
+        if mantissa < 1 << 56 {
+            mantissa = mantissa * 16 + ch.to_digit(16).unwrap() as u64;
+            if fraction {
+                exponent -= 4;
+            }
+        }
+        else if !fraction {
+            exponent += 4;
+        }
+    }
+    // In two steps, so a subnormal result does not round to 0.
+    let half = exponent / 2;
+    return mantissa as f64 * 2f64.powi(half) * 2f64.powi(exponent - half);
+}
+
+/*
+ * Why an integer literal, text as written, did not fit
+ * an i64. A literal is never negative, '-1' being a
+ * negation of 1, so -9223372036854775808 can not be
+ * written as a literal either.
+ */
+fn integer_error(text: &str, error: &ParseIntError) -> String
+{
+    return match *error.kind() {
+        IntErrorKind::PosOverflow => {
+            format!("integer literal {} is too large, integers range \
+                     from {} to {}", text, i64::min_value(),
+                    i64::max_value())
+        },
+        IntErrorKind::NegOverflow => {
+            format!("integer literal {} is too small, integers range \
+                     from {} to {}", text, i64::min_value(),
+                    i64::max_value())
+        },
+        _ => format!("invalid integer literal {}", text),
+    }
+}
+
 pub struct Scanner<'a> {
     program: &'a str,
     input: &'a [u8],
@@ -36,6 +255,19 @@ pub struct Scanner<'a> {
     ch: char,
     reserved_words: HashMap<&'static str,
                             TokenType>,
+    errors: Vec<ScanError>,
+    reached_eof: bool,
+    // Tokens scanned ahead by peek_token.
+    lookahead: VecDeque<Token>,
+    // Type of the last token scanned, decides what '/' means.
+    prev_type: TokenType,
+    word_operators: bool,
+    interner: Interner,
+    tab_width: usize,
+    // Whether whitespace and comments are returned as tokens.
+    trivia: bool,
+    max_ident_len: usize,
+    max_string_len: usize,
 }
 
 impl<'a> Scanner<'a>
@@ -53,13 +285,17 @@ impl<'a> Scanner<'a>
             ch: '\0',
             reserved_words: create_map!(
                 "def"    => DEF,
+                "const"  => CONST,
                 "if"     => IF,
                 "elif"   => ELIF,
                 "else"   => ELSE,
                 "for"    => FOR,
                 "while"  => WHILE,
                 "until"  => UNTIL,
+                "do"     => DO,
+                "with"   => WITH,
                 "switch" => SWITCH,
+                "match"  => MATCH,
                 "case"   => CASE,
                 "default"=> DEFAULT,
                 "in"     => IN,
@@ -68,24 +304,124 @@ impl<'a> Scanner<'a>
                 "false"  => FALSE,
                 "nil"    => NIL,
                 "debug"  => DEBUG,
-                "return" => RETURN
+                "assert" => ASSERT,
+                "return" => RETURN,
+                "yield"  => YIELD
             ),
+            errors: Vec::new(),
+            reached_eof: false,
+            lookahead: VecDeque::new(),
+            prev_type: NEWLINE,
+            word_operators: false,
+            interner: Interner::new(),
+            tab_width: DEFAULT_TAB_WIDTH,
+            trivia: false,
+            max_ident_len: DEFAULT_MAX_IDENT_LEN,
+            max_string_len: DEFAULT_MAX_STRING_LEN,
         };
         scanner.next_char();
 
         return scanner;
     }
 
-    fn error(&self, line_num: i32, line_pos: i32,
+    /*
+     * Starts over on program, as if the scanner was new,
+     * but keeps its settings, reserved words and interner,
+     * so scanning many small snippets does not rebuild
+     * them each time. Errors and tokens peeked at from the
+     * previous program are dropped.
+     */
+    pub fn reset(&mut self, program: &'a str)
+    {
+        self.program = program;
+        self.input = program.as_bytes();
+        self.line_num = 1;
+        self.line_pos = 0;
+        self.position = -1;
+        self.ch = '\0';
+        self.errors.clear();
+        self.reached_eof = false;
+        self.lookahead.clear();
+        self.prev_type = NEWLINE;
+        self.next_char();
+    }
+
+    fn error(&mut self, line_num: i32, line_pos: i32,
              message: String)
     {
-        /*
-         * For now we panic! once we locate a scanner
-         * error. Later we will patch in inline assembly
-         * jumping, to get out of heavy recursion.
-         */
-        panic!("{}:{}:{}: {}", self.module.filename, line_num,
-               line_pos, message.as_str());
+        self.errors.push(ScanError {
+            filename: self.module.filename.clone(),
+            line_num: line_num,
+            line_pos: line_pos,
+            message: message,
+        });
+    }
+
+    /*
+     * Hands over the errors found so far, leaving the
+     * scanner with an empty list.
+     */
+    pub fn take_errors(&mut self) -> Vec<ScanError>
+    {
+        return mem::replace(&mut self.errors, Vec::new());
+    }
+
+    /*
+     * With word operators on 'and', 'or' and 'not' scan as
+     * the tokens for '&&', '||' and '!', text included, so
+     * either spelling gives the same tree. They are off by
+     * default since turning them on takes the three words
+     * away from programs using them as names.
+     */
+    pub fn set_word_operators(&mut self, enabled: bool)
+    {
+        self.word_operators = enabled;
+    }
+
+    /*
+     * Sets how far a tab moves line_pos, so the columns on
+     * tokens and errors are those an editor with the same
+     * setting shows. Only the text scanned from here on is
+     * affected, a width of 1 counts a tab as one column.
+     */
+    pub fn set_tab_width(&mut self, tab_width: usize)
+    {
+        self.tab_width = tab_width.max(1);
+    }
+
+    /*
+     * Identifiers and string literals longer than these are
+     * reported, and only the first max bytes are kept. The
+     * scanner still reads on to the end of the token, so
+     * scanning carries on in step with the source.
+     */
+    pub fn set_max_ident_len(&mut self, max_ident_len: usize)
+    {
+        self.max_ident_len = max_ident_len;
+    }
+
+    pub fn set_max_string_len(&mut self, max_string_len: usize)
+    {
+        self.max_string_len = max_string_len;
+    }
+
+    /*
+     * The interner holding the text of every word and
+     * operator scanned so far, symbols on the tokens
+     * resolve through it.
+     */
+    pub fn interner(&self) -> &Interner
+    {
+        return &self.interner;
+    }
+
+    // Gives token text interned, sharing earlier copies.
+    fn intern(&mut self, token: &mut Token, text: &str)
+    {
+        let symbol = self.interner.intern(text);
+
+        token.text = self.interner.shared(symbol);
+        token.symbol = Some(symbol);
     }
 
     fn get_char(&self, position: usize) -> char
@@ -93,17 +429,37 @@ impl<'a> Scanner<'a>
         return self.input[position] as char;
     }
 
+    /*
+     * line_num and line_pos always describe self.ch, the
+     * line is bumped once we step past a line ending, so the
+     * newline itself is the last character of its line. A
+     * line ends in '\n', '\r\n' or a lone '\r'.
+     */
     fn next_char(&mut self) -> char
     {
+        if self.position >= self.program.len() as i32 {
+            /*
+             * Stay on the end of input, callers which step
+             * past an unterminated construct keep seeing EOF.
+             */
+            return EOF_CHAR;
+        }
+        if self.ch == '\n' ||
+           (self.ch == '\r' && self.peek_char(1) != '\n') {
+            self.line_num += 1;
+            self.line_pos = 0;
+        }
+        // Past a tab, to the column following the next tab stop.
+        if self.ch == '\t' {
+            let width = self.tab_width as i32;
+            self.line_pos = (self.line_pos - 1) / width * width + width;
+        }
         self.position += 1;
+        self.line_pos += 1;
         if self.position == self.program.len() as i32 {
             self.ch = EOF_CHAR;
         } else {
             self.ch = self.get_char(self.position as usize);
-            if self.ch == '\n' {
-                self.line_num += 1;
-            }
-            self.line_pos += 1;
         }
         return self.ch;
     }
@@ -128,60 +484,183 @@ impl<'a> Scanner<'a>
     }
 
     /*
-     * A whitespace is equal to a space, \t, or \r. If
-     * it finds '#' it loops until '\n' or '\0'.
+     * A whitespace is equal to a space or \t. If it finds
+     * '#' it loops until the end of the line or '\0'. A '\'
+     * ending a line continues it on the next one, the newline
+     * is skipped like any whitespace so no NEWLINE token
+     * comes of it.
      */
     fn whitespace(&mut self)
     {
-        while self.ch == ' '  || self.ch == '\r' ||
-              self.ch == '\t' || self.ch == '#' {
+        while self.ch == ' '  || self.ch == '\t' || self.ch == '#' ||
+              self.is_continuation() {
             if self.ch == '#' {
-                while self.ch != '\n' && self.ch != EOF_CHAR {
+                while self.ch != '\n' && self.ch != '\r' &&
+                      self.ch != EOF_CHAR {
                     self.next_char();
                 }
+            } else if self.ch == '\\' {
+                self.skip_continuation();
             } else {
                 self.next_char();
             }
         }
     }
 
+    fn skip_continuation(&mut self)
+    {
+        self.next_char();
+        if self.ch == '\r' && self.peek_char(1) == '\n' {
+            self.next_char();
+        }
+        self.next_char();
+    }
+
+    fn is_continuation(&self) -> bool
+    {
+        return self.ch == '\\' &&
+               (self.peek_char(1) == '\n' || self.peek_char(1) == '\r');
+    }
+
     /*
-     * Method parses a long comment '==='.
+     * Method parses a long comment '==='. Long comments
+     * nest: inside a comment, a '===' which starts a line and
+     * has more text after it on that line opens a nested
+     * comment, any other '===' closes the innermost one.
+     *
+     * === outer
+     * === inner
+     * ===
+     * ===
      */
     fn long_comment(&mut self)
     {
+        let mut depth = 1;
+        let mut line_start = false;
+
         self.next_charx(3);
         while self.ch != EOF_CHAR {
-            if self.ch == '=' {
-                if self.peek_char(1) == '=' && self.peek_char(2) == '=' {
-                    break;
+            if self.is_long_comment() {
+                if line_start && self.long_comment_has_text() {
+                    depth += 1;
                 }
+                else {
+                    depth -= 1;
+                }
+                self.next_charx(3);
+                if depth == 0 {
+                    return;
+                }
+                line_start = false;
+                continue;
+            }
+            if self.ch == '\n' || self.ch == '\r' {
+                line_start = true;
+            }
+            else if self.ch != ' ' && self.ch != '\t' {
+                line_start = false;
             }
             self.next_char();
         }
-        if self.ch == EOF_CHAR {
-            self.error(self.line_num, self.line_pos,
-                       "unterminated long comment".to_string());
+        self.error(self.line_num, self.line_pos,
+                   "unterminated long comment".to_string());
+    }
+
+    /*
+     * Whether the '===' under the cursor is followed by
+     * anything but whitespace before the end of its line.
+     */
+    fn long_comment_has_text(&self) -> bool
+    {
+        let mut num = 3;
+
+        loop {
+            match self.peek_char(num) {
+                ' ' | '\t' => num += 1,
+                '\n' | '\r' | EOF_CHAR => return false,
+                _ => return true,
+            }
         }
-        self.next_charx(3);
     }
 
     /*
-     * next_token returns a token filled with semantic
-     * information. It starts by skipping whitespace /
+     * Returns the next token, taking it from the tokens
+     * already scanned by peek_token if there are any.
+     */
+    pub fn next_token(&mut self) -> Token
+    {
+        if let Some(token) = self.lookahead.pop_front() {
+            return token;
+        }
+        return self.scan_token();
+    }
+
+    /*
+     * Calls f with every remaining token in turn, the EOF
+     * token last, without collecting them anywhere.
+     */
+    pub fn scan_with<F>(&mut self, mut f: F)
+        where F: FnMut(&Token)
+    {
+        loop {
+            let token = self.next_token();
+            f(&token);
+            if token.token_type == EOF {
+                return;
+            }
+        }
+    }
+
+    /*
+     * Returns the nth upcoming token without consuming it,
+     * peek_token(0) is the token next_token would return.
+     */
+    pub fn peek_token(&mut self, n: usize) -> &Token
+    {
+        while self.lookahead.len() <= n {
+            let token = self.scan_token();
+            self.lookahead.push_back(token);
+        }
+        return &self.lookahead[n];
+    }
+
+    /*
+     * scan_token returns a token filled with semantic
+     * information. An unrecognized character is reported
+     * and skipped by scan_one, which then gives None. Looping
+     * instead of recursing keeps a long run of them from
+     * overflowing the stack.
+     */
+    fn scan_token(&mut self) -> Token
+    {
+        loop {
+            if let Some(token) = self.scan_one() {
+                return token;
+            }
+        }
+    }
+
+    /*
+     * Scans one token. It starts by skipping whitespace /
      * comments and declares the token. The token will
      * be filled with data through the routine.
      */
-    pub fn next_token(&mut self) -> Token
+    fn scan_one(&mut self) -> Option<Token>
     {
+        if self.trivia {
+            if let Some(token) = self.trivia_token() {
+                return Some(token);
+            }
+        }
         self.whitespace();
-        if self.is_long_comment() {
+        while self.is_long_comment() {
             self.long_comment();
+            self.whitespace();
         }
-        let mut token = Token::new(self.line_num, self.line_pos);
+        let mut token = Token::new(self.line_num, self.line_pos,
+                                   self.position as usize);
 
         if self.ch == EOF_CHAR {
-            token.text = "".to_string();
             token.token_type = EOF;
         }
         else if self.is_letter() {
@@ -196,16 +675,21 @@ impl<'a> Scanner<'a>
         else if self.ch == '"' || self.ch == '\'' {
             self.string_token(&mut token);
         }
+        else if self.ch == '/' && self.regex_allowed() {
+            self.regex_token(&mut token);
+        }
         else {
-            token.text.push(self.ch);
+            let mut text = String::new();
+
+            text.push(self.ch);
             match self.ch {
                 '|' => {
                     if self.peek_char(1) == '|' {
-                        token.text.push(self.next_char());
+                        text.push(self.next_char());
                         token.token_type = LOGICAL_OR;
                     }
                     else if self.peek_char(1) == '=' {
-                        token.text.push(self.next_char());
+                        text.push(self.next_char());
                         token.token_type = BITWISE_OR_ASSIGN;
                     }
                     else {
@@ -214,11 +698,11 @@ impl<'a> Scanner<'a>
                 },
                 '&' => {
                     if self.peek_char(1) == '&' {
-                        token.text.push(self.next_char());
+                        text.push(self.next_char());
                         token.token_type = LOGICAL_AND;
                     }
                     else if self.peek_char(1) == '=' {
-                        token.text.push(self.next_char());
+                        text.push(self.next_char());
                         token.token_type = BITWISE_AND_ASSIGN;
                     }
                     else {
@@ -227,11 +711,11 @@ impl<'a> Scanner<'a>
                 },
                 '=' => {
                     if self.peek_char(1) == '=' {
-                        token.text.push(self.next_char());
+                        text.push(self.next_char());
                         token.token_type = EQL;
                     }
                     else if self.peek_char(1) == '>' {
-                        token.text.push(self.next_char());
+                        text.push(self.next_char());
                         token.token_type = ASSIGN_ARROW;
                     }
                     else {
@@ -240,7 +724,7 @@ impl<'a> Scanner<'a>
                 },
                 '!' => {
                     if self.peek_char(1) == '=' {
-                        token.text.push(self.next_char());
+                        text.push(self.next_char());
                         token.token_type = NOT_EQL;
                     }
                     else {
@@ -249,13 +733,13 @@ impl<'a> Scanner<'a>
                 },
                 '<' => {
                     if self.peek_char(1) == '=' {
-                        token.text.push(self.next_char());
+                        text.push(self.next_char());
                         token.token_type = LE;
                     }
                     else if self.peek_char(1) == '<' {
-                        token.text.push(self.next_char());
+                        text.push(self.next_char());
                         if self.peek_char(1) == '=' {
-                            token.text.push(self.next_char());
+                            text.push(self.next_char());
                             token.token_type = LEFT_SHIFT_ASSIGN;
                         }
                         else {
@@ -268,13 +752,13 @@ impl<'a> Scanner<'a>
                 },
                 '>' => {
                     if self.peek_char(1) == '=' {
-                        token.text.push(self.next_char());
+                        text.push(self.next_char());
                         token.token_type = GE;
                     }
                     else if self.peek_char(1) == '>' {
-                        token.text.push(self.next_char());
+                        text.push(self.next_char());
                         if self.peek_char(1) == '=' {
-                            token.text.push(self.next_char());
+                            text.push(self.next_char());
                             token.token_type = RIGHT_SHIFT_ASSIGN;
                         }
                         else {
@@ -287,7 +771,7 @@ impl<'a> Scanner<'a>
                 },
                 '^' => {
                     if self.peek_char(1) == '=' {
-                        token.text.push(self.next_char());
+                        text.push(self.next_char());
                         token.token_type = BITWISE_XOR_ASSIGN;
                     }
                     else {
@@ -296,7 +780,7 @@ impl<'a> Scanner<'a>
                 },
                 '.' => {
                     if self.peek_char(1) == '.' {
-                        token.text.push(self.next_char());
+                        text.push(self.next_char());
                         token.token_type = DOTDOT;
                     }
                     else {
@@ -305,7 +789,7 @@ impl<'a> Scanner<'a>
                 },
                 '+' => {
                     if self.peek_char(1) == '=' {
-                        token.text.push(self.next_char());
+                        text.push(self.next_char());
                         token.token_type = PLUS_ASSIGN;
                     }
                     else {
@@ -313,8 +797,13 @@ impl<'a> Scanner<'a>
                     }
                 },
                 '-' => {
+                    /*
+                     * There is no decrement operator, so '--' is
+                     * always two MINUS tokens and 'a--b' reads as
+                     * 'a - (-b)', the same as 'a - - b'.
+                     */
                     if self.peek_char(1) == '=' {
-                        token.text.push(self.next_char());
+                        text.push(self.next_char());
                         token.token_type = MINUS_ASSIGN;
                     }
                     else {
@@ -323,7 +812,7 @@ impl<'a> Scanner<'a>
                 },
                 '*' => {
                     if self.peek_char(1) == '=' {
-                        token.text.push(self.next_char());
+                        text.push(self.next_char());
                         token.token_type = MUL_ASSIGN;
                     }
                     else {
@@ -332,16 +821,20 @@ impl<'a> Scanner<'a>
                 },
                 '/' => {
                     if self.peek_char(1) == '=' {
-                        token.text.push(self.next_char());
+                        text.push(self.next_char());
                         token.token_type = DIV_ASSIGN;
                     }
+                    else if self.peek_char(1) == '/' {
+                        text.push(self.next_char());
+                        token.token_type = FLOOR_DIV;
+                    }
                     else {
                         token.token_type = DIV;
                     }
                 },
                 '%' => {
                     if self.peek_char(1) == '=' {
-                        token.text.push(self.next_char());
+                        text.push(self.next_char());
                         token.token_type = MODULO_ASSIGN;
                     }
                     else {
@@ -357,17 +850,73 @@ impl<'a> Scanner<'a>
                 '}'  => token.token_type = RBRACE,
                 ','  => token.token_type = COMMA,
                 ';'  => token.token_type = SEMICOLON,
-                '\n' => {
+                ':'  => token.token_type = COLON,
+                '?'  => token.token_type = QUESTION,
+                '\n' => token.token_type = NEWLINE,
+                '\r' => {
+                    // '\r\n' and a lone '\r' are one NEWLINE.
+                    if self.peek_char(1) == '\n' {
+                        text.push(self.next_char());
+                    }
                     token.token_type = NEWLINE;
-                    token.line_num -= 1; self.line_pos = 0;
                 },
-                _    => self.error(self.line_num, self.line_pos,
-                                   format!("unrecognized character '{}'",
-                                   self.ch)),
+                _    => {
+                    self.error(self.line_num, self.line_pos,
+                               format!("unrecognized character '{}'",
+                               self.ch));
+                    self.next_char();
+
+                    return None;
+                },
             }
             self.next_char();
+            self.intern(&mut token, text.as_str());
         }
-        return token;
+        token.end = self.position as usize;
+        self.prev_type = token.token_type;
+
+        return Some(token);
+    }
+
+    /*
+     * The whitespace or comment at the current position as
+     * a token of its own, a run of blanks and continuations
+     * making up one WHITESPACE token.
+     */
+    fn trivia_token(&mut self) -> Option<Token>
+    {
+        let mut token = Token::new(self.line_num, self.line_pos,
+                                   self.position as usize);
+        if self.ch == '#' {
+            while self.ch != '\n' && self.ch != '\r' &&
+                  self.ch != EOF_CHAR {
+                self.next_char();
+            }
+            token.token_type = COMMENT;
+        }
+        else if self.is_long_comment() {
+            self.long_comment();
+            token.token_type = COMMENT;
+        }
+        else if self.ch == ' ' || self.ch == '\t' || self.is_continuation() {
+            while self.ch == ' ' || self.ch == '\t' ||
+                  self.is_continuation() {
+                if self.ch == '\\' {
+                    self.skip_continuation();
+                }
+                else {
+                    self.next_char();
+                }
+            }
+            token.token_type = WHITESPACE;
+        }
+        else {
+            return None;
+        }
+        token.end = self.position as usize;
+        token.text = Rc::from(&self.program[token.start..token.end]);
+
+        return Some(token);
     }
 
     pub fn word_token(&mut self, token: &mut Token)
@@ -377,130 +926,409 @@ impl<'a> Scanner<'a>
         while self.is_letter() {
             self.next_char();
         }
-        token.text = get_literal!(self.program, position,
-                                  self.position);
-        if let Some(word) = self.reserved_words.get(
-            token.text.as_str())
+        let mut end = self.position as usize;
+        if end - position as usize > self.max_ident_len {
+            self.error(token.line_num, token.line_pos,
+                       format!("identifier is longer than the maximum of {} \
+                                bytes", self.max_ident_len));
+            end = position as usize + self.max_ident_len;
+        }
+        let program = self.program;
+        self.intern(token, get_literal!(program, position, end));
+        if let Some(word) = self.reserved_words.get(token.string())
         {
             token.token_type = *word;
             match word {
                 &TRUE  => token.value = BoolValue(true),
                 &FALSE => token.value = BoolValue(false),
+                &NIL   => token.value = NilValue,
                 _ => (),
             }
         }
-        else {
+        else if !self.word_operators || !self.word_operator(token) {
             token.token_type = IDENT;
         }
     }
 
+    fn word_operator(&mut self, token: &mut Token) -> bool
+    {
+        let (token_type, text) = match token.string() {
+            "and" => (LOGICAL_AND, "&&"),
+            "or"  => (LOGICAL_OR, "||"),
+            "not" => (BANG, "!"),
+            _ => return false,
+        };
+        token.token_type = token_type;
+        self.intern(token, text);
+
+        return true;
+    }
+
     pub fn number_token(&mut self, token: &mut Token)
     {
         token.token_type = INTEGER;
 
         let position = self.position;
-        while self.is_digit() {
-            self.next_char();
-        }
+        self.digits();
         if self.ch == '.' && self.peek_char(1) != '.' {
             self.next_char();
-            while self.is_digit() {
-                self.next_char();
-            }
+            self.digits();
             token.token_type = FLOAT;
         }
-        token.text = get_literal!(self.program, position,
-                                  self.position);
+        token.text = Rc::from(get_literal!(self.program, position,
+                                           self.position));
+        let digits = token.text.replace("_", "");
         if token.token_type == INTEGER {
-            let value = i64::from_str_radix(token.text.as_str(), 10);
-            if value.is_err() {
-                self.error(token.line_num, token.line_pos,
-                           format!("number literal was too large"));
+            match i64::from_str_radix(digits.as_str(), 10) {
+                Ok(value) => token.value = IntegerValue(value),
+                Err(error) => {
+                    let message = integer_error(&token.text, &error);
+                    self.error(token.line_num, token.line_pos, message);
+                },
             }
-            token.value = IntegerValue(value.unwrap());
         }
         else {
-            let value = f64::from_str(token.text.as_str());
+            let value = f64::from_str(digits.as_str());
             token.value = FloatValue(value.unwrap());
         }
     }
 
+    /*
+     * Reads a run of digits which may be grouped by '_' in
+     * any way, 1_00_000 is fine as the separators are purely
+     * cosmetic. A separator has to sit between two digits
+     * though, so doubled ones and one ending the run are
+     * reported at the offending '_', as is one leading the
+     * fraction. A number can not start with one, '_1' scans
+     * as an identifier.
+     */
+    fn digits(&mut self)
+    {
+        let position = self.position;
+
+        while self.is_digit() || self.ch == '_' {
+            if self.ch == '_' {
+                let next_char = self.peek_char(1);
+                if self.position == position {
+                    self.error(self.line_num, self.line_pos,
+                               "digit separator at start of fraction"
+                               .to_string());
+                }
+                else if next_char == '_' {
+                    self.error(self.line_num, self.line_pos + 1,
+                               "doubled digit separator".to_string());
+                }
+                else if next_char < '0' || next_char > '9' {
+                    self.error(self.line_num, self.line_pos,
+                               "digit separator at end of number"
+                               .to_string());
+                }
+            }
+            self.next_char();
+        }
+    }
+
+    /*
+     * 0xFF, or a hex float when a fraction or a binary
+     * exponent follows the digits, 0x1.8p3 being 1.5 * 2^3.
+     * The '.' has to be followed by a hex digit, so 0x1..3
+     * is still a range.
+     */
     fn number_token_hex(&mut self, token: &mut Token)
     {
         let position = self.position;
 
         self.next_charx(2);
-        while self.read_hexdigit() != 1 {
+        self.hex_digits();
+        let mut is_float = false;
+        if self.ch == '.' && is_hexdigit(self.peek_char(1)) {
             self.next_char();
+            self.hex_digits();
+            is_float = true;
+        }
+        if self.is_binary_exponent() {
+            self.next_char();
+            if self.ch == '+' || self.ch == '-' {
+                self.next_char();
+            }
+            while self.is_digit() {
+                self.next_char();
+            }
+            is_float = true;
+        }
+        token.text = Rc::from(get_literal!(self.program, position,
+                                           self.position));
+        if is_float {
+            token.token_type = FLOAT;
+            token.value = FloatValue(hex_float(&token.text[2..]));
+            return;
         }
-        token.text = get_literal!(self.program, position,
-                                  self.position);
         token.token_type = INTEGER;
 
-        let value = i64::from_str_radix(&token.text[2..], 16);
-        if value.is_err() {
-            self.error(token.line_num, token.line_pos,
-                       format!("number literal was too large"));
+        match i64::from_str_radix(&token.text[2..], 16) {
+            Ok(value) => token.value = IntegerValue(value),
+            Err(error) => {
+                let message = integer_error(&token.text, &error);
+                self.error(token.line_num, token.line_pos, message);
+            },
+        }
+    }
+
+    fn hex_digits(&mut self)
+    {
+        while self.read_hexdigit() != -1 {
+            self.next_char();
+        }
+    }
+
+    // A 'p' or 'P' followed by digits, optionally signed.
+    fn is_binary_exponent(&self) -> bool
+    {
+        if self.ch != 'p' && self.ch != 'P' {
+            return false;
         }
-        token.value = IntegerValue(value.unwrap());
+        let mut next_char = self.peek_char(1);
+        if next_char == '+' || next_char == '-' {
+            next_char = self.peek_char(2);
+        }
+        return next_char >= '0' && next_char <= '9';
     }
 
-    pub fn read_hex_escape(&mut self, delimit: char) -> char
+    /*
+     * '\xNN' stands for a single byte, not a code point, so
+     * '\xC3\xA9' spells out the UTF-8 encoding of 'é'.
+     */
+    pub fn read_hex_escape(&mut self, delimit: char) -> u8
     {
         let mut value = 0;
 
         for _ in 0..2 {
-            self.next_char();
-            if self.ch == delimit || self.ch == EOF_CHAR {
-                self.error(self.line_num, self.line_pos,
+            /*
+             * Never step onto the closing delimiter, it
+             * still has to terminate the string.
+             */
+            let next_char = self.peek_char(1);
+            if next_char == delimit || next_char == EOF_CHAR {
+                self.error(self.line_num, self.line_pos + 1,
                            "incomplete hex escape sequence".to_string());
+                break;
             }
+            self.next_char();
             let digit = self.read_hexdigit();
 
             if digit == -1 {
                 self.error(self.line_num, self.line_pos,
                            "incomplete hex escape sequence".to_string());
+                break;
             }
             value = (value * 16) + digit;
         }
-        return value as u8 as char;
+        return value as u8;
     }
 
+    /*
+     * '\u{E9}' names a code point by one to six hex digits,
+     * it goes into the string UTF-8 encoded.
+     */
+    fn read_unicode_escape(&mut self, delimit: char) -> Option<char>
+    {
+        if self.peek_char(1) != '{' {
+            self.error(self.line_num, self.line_pos,
+                       "expected '{' after \\u".to_string());
+            return None;
+        }
+        self.next_char();
+        let mut value: u32 = 0;
+        let mut digits = 0;
+        loop {
+            let next_char = self.peek_char(1);
+            if next_char == delimit || next_char == EOF_CHAR {
+                self.error(self.line_num, self.line_pos + 1,
+                           "incomplete unicode escape sequence".to_string());
+                return None;
+            }
+            self.next_char();
+            if self.ch == '}' {
+                break;
+            }
+            let digit = self.read_hexdigit();
+            if digit == -1 || digits == 6 {
+                self.error(self.line_num, self.line_pos,
+                           "invalid unicode escape sequence".to_string());
+                return None;
+            }
+            value = (value * 16) + digit as u32;
+            digits += 1;
+        }
+        if digits == 0 {
+            self.error(self.line_num, self.line_pos,
+                       "invalid unicode escape sequence".to_string());
+            return None;
+        }
+        let ch = char::from_u32(value);
+        if ch.is_none() {
+            self.error(self.line_num, self.line_pos,
+                       format!("invalid code point {:X}", value));
+        }
+        return ch;
+    }
+
+    /*
+     * The contents are gathered as bytes, the source is
+     * copied over byte by byte and '\x' escapes add raw
+     * bytes, the result only has to be valid UTF-8 once the
+     * string is complete.
+     */
     pub fn string_token(&mut self, token: &mut Token)
     {
-        let mut buf = String::new();
+        let mut buf: Vec<u8> = Vec::new();
         let delimit = self.ch;
+        // Length the text was cut to once it grew too long.
+        let mut kept: Option<usize> = None;
 
         self.next_char();
         while self.ch != delimit && self.ch != EOF_CHAR {
             if self.ch == '\\' {
                 self.next_char();
                 match self.ch {
-                    '"'  => buf.push('"'),
-                    '\\' => buf.push('\\'),
-                    '\'' => buf.push('\''),
-                    'n'  => buf.push('\n'),
-                    'r'  => buf.push('\r'),
-                    't'  => buf.push('\t'),
+                    '"'  => buf.push(b'"'),
+                    '\\' => buf.push(b'\\'),
+                    '\'' => buf.push(b'\''),
+                    'n'  => buf.push(b'\n'),
+                    'r'  => buf.push(b'\r'),
+                    't'  => buf.push(b'\t'),
+                    '0'  => buf.push(0x00),
+                    'a'  => buf.push(0x07),
+                    'b'  => buf.push(0x08),
+                    'f'  => buf.push(0x0c),
+                    'v'  => buf.push(0x0b),
                     'x'  => buf.push(self.read_hex_escape(delimit)),
+                    'u'  => {
+                        if let Some(ch) = self.read_unicode_escape(delimit) {
+                            let mut utf8 = [0; 4];
+                            buf.extend_from_slice(ch.encode_utf8(&mut utf8)
+                                                    .as_bytes());
+                        }
+                    },
                     _    => self.error(self.line_num, self.line_pos,
                                        format!("invalid escape character {}",
                                                self.ch)),
                 };
             }
             else {
-                buf.push(self.ch);
+                buf.push(self.ch as u8);
+            }
+            match kept {
+                Some(len) => buf.truncate(len),
+                None if buf.len() > self.max_string_len => {
+                    self.error(token.line_num, token.line_pos,
+                               format!("string literal is longer than the \
+                                        maximum of {} bytes",
+                                       self.max_string_len));
+                    /*
+                     * Cut once, at the last character boundary
+                     * within the limit so the text stays UTF-8.
+                     * Every byte after it is dropped.
+                     */
+                    let mut len = self.max_string_len;
+                    while len > 0 && buf[len] & 0xc0 == 0x80 {
+                        len -= 1;
+                    }
+                    buf.truncate(len);
+                    kept = Some(len);
+                },
+                None => (),
             }
             self.next_char();
         }
         if self.ch == EOF_CHAR {
-            self.error(self.line_num, self.line_pos,
+            self.error(token.line_num, token.line_pos,
                        "unterminated string literal".to_string());
         }
         self.next_char();
-        token.text = buf;
+        let text = match String::from_utf8(buf) {
+            Ok(text) => text,
+            Err(error) => {
+                self.error(token.line_num, token.line_pos,
+                           "invalid UTF-8 in string literal".to_string());
+                String::from_utf8_lossy(error.as_bytes()).into_owned()
+            },
+        };
+        token.text = Rc::from(text.as_str());
         token.token_type = STRING;
-        token.value = StringValue(token.text.clone());
+        token.value = StringValue(text);
+    }
+
+    /*
+     * A '/' can either divide or open a regex literal, which
+     * one depends on what came before it. After something
+     * that ends a value, like 'a / b' or 'f() / 2', it is a
+     * division, at any other point an expression may start
+     * so it opens a regex, as in 'x = /ab+/'. A '/=' is always
+     * DIV_ASSIGN, a pattern starting with '=' is written '/\=/',
+     * and '//' always FLOOR_DIV, there are no empty patterns.
+     */
+    fn regex_allowed(&self) -> bool
+    {
+        if self.peek_char(1) == '=' || self.peek_char(1) == '/' {
+            return false;
+        }
+        return match self.prev_type {
+            STRING | INTEGER | FLOAT | TRUE | FALSE | NIL |
+            REGEX | IDENT | RPAREN | RBRACK | RBRACE => false,
+            _ => true,
+        }
+    }
+
+    /*
+     * Escapes are left for the regex engine, except for '\/'
+     * which would otherwise end the pattern. The pattern is
+     * copied byte by byte like a string's contents and
+     * decoded once it is complete.
+     */
+    pub fn regex_token(&mut self, token: &mut Token)
+    {
+        let mut pattern: Vec<u8> = Vec::new();
+        let mut flags = String::new();
+        let position = self.position;
+
+        self.next_char();
+        while self.ch != '/' && self.ch != '\n' && self.ch != '\r' &&
+              self.ch != EOF_CHAR {
+            if self.ch == '\\' && self.peek_char(1) == '/' {
+                self.next_char();
+            }
+            else if self.ch == '\\' && self.peek_char(1) == '\\' {
+                pattern.push(self.next_char() as u8);
+            }
+            pattern.push(self.ch as u8);
+            self.next_char();
+        }
+        if self.ch != '/' {
+            self.error(token.line_num, token.line_pos,
+                       "unterminated regex literal".to_string());
+        }
+        else {
+            self.next_char();
+            while self.is_letter() {
+                match self.ch {
+                    'i' | 'm' | 's' | 'x' => flags.push(self.ch),
+                    _ => self.error(self.line_num, self.line_pos,
+                                    format!("unknown regex flag '{}'",
+                                            self.ch)),
+                }
+                self.next_char();
+            }
+        }
+        token.text = Rc::from(get_literal!(self.program, position,
+                                           self.position));
+        token.token_type = REGEX;
+        /*
+         * Only whole characters of the source were copied,
+         * the pattern ends at an ASCII delimiter.
+         */
+        token.value = RegexValue(String::from_utf8_lossy(&pattern)
+                                     .into_owned(), flags);
     }
 
     fn is_letter(&self) -> bool
@@ -518,8 +1346,7 @@ impl<'a> Scanner<'a>
     {
         let next_char = self.peek_char(1);
 
-        return self.ch == '0' && next_char == 'x' ||
-               next_char == 'X';
+        return self.ch == '0' && (next_char == 'x' || next_char == 'X');
     }
 
     fn read_hexdigit(&self) -> i32
@@ -541,4 +1368,117 @@ impl<'a> Scanner<'a>
         return self.ch == '=' && self.peek_char(1) == '=' &&
                self.peek_char(2) == '=';
     }
-}
\ No newline at end of file
+}
+
+/*
+ * Where the parser gets its tokens from. The scanner is
+ * the usual source, anything else handing out tokens, like
+ * a VecTokenSource, can stand in for it.
+ */
+pub trait TokenSource {
+    /*
+     * The token after the last one returned, once the end is
+     * reached this keeps returning EOF.
+     */
+    fn next_token(&mut self) -> Token;
+
+    // The nth upcoming token, peek_token(0) being the next.
+    fn peek_token(&mut self, n: usize) -> &Token;
+
+    // Errors met producing the tokens, none by default.
+    fn take_errors(&mut self) -> Vec<ScanError>
+    {
+        return Vec::new();
+    }
+}
+
+impl<'a> TokenSource for Scanner<'a>
+{
+    fn next_token(&mut self) -> Token
+    {
+        return Scanner::next_token(self);
+    }
+
+    fn peek_token(&mut self, n: usize) -> &Token
+    {
+        return Scanner::peek_token(self, n);
+    }
+
+    fn take_errors(&mut self) -> Vec<ScanError>
+    {
+        return Scanner::take_errors(self);
+    }
+}
+
+/*
+ * Hands out tokens built beforehand. After the last one
+ * it returns EOF, the given EOF token if the tokens end
+ * in one, otherwise one placed at the end of the last
+ * token.
+ */
+pub struct VecTokenSource<'a> {
+    tokens: &'a [Token],
+    position: usize,
+    eof: Token,
+}
+
+impl<'a> VecTokenSource<'a>
+{
+    pub fn new(tokens: &'a [Token]) -> VecTokenSource<'a>
+    {
+        let eof = match tokens.last() {
+            Some(token) if token.token_type == EOF => token.clone(),
+            Some(token) => Token::new_imag("".to_string(), EOF,
+                                           token.line_num,
+                                           token.line_pos +
+                                           (token.end - token.start) as i32,
+                                           token.end),
+            None => Token::new_imag("".to_string(), EOF, 1, 1, 0),
+        };
+        return VecTokenSource {
+            tokens: tokens,
+            position: 0,
+            eof: eof,
+        };
+    }
+}
+
+impl<'a> TokenSource for VecTokenSource<'a>
+{
+    fn next_token(&mut self) -> Token
+    {
+        let token = self.peek_token(0).clone();
+        self.position += 1;
+
+        return token;
+    }
+
+    fn peek_token(&mut self, n: usize) -> &Token
+    {
+        return match self.tokens.get(self.position + n) {
+            Some(token) => token,
+            None => &self.eof,
+        }
+    }
+}
+
+/*
+ * Iterating a scanner yields every token up to and
+ * including EOF, and then stops.
+ */
+impl<'a> Iterator for Scanner<'a>
+{
+    type Item = Token;
+
+    fn next(&mut self) -> Option<Token>
+    {
+        if self.reached_eof {
+            return None;
+        }
+        let token = self.next_token();
+        if token.token_type == EOF {
+            self.reached_eof = true;
+        }
+        return Some(token);
+    }
+}
@@ -6,7 +6,9 @@
 use scanner::scanner::*;
 use token::*;
 use token::TokenType::*;
+use token::Value::*;
 use module::Module;
+use std::rc::Rc;
 
 macro_rules! create_tests {
     ($($text:expr, $token_type:expr),+) => (
@@ -78,6 +80,23 @@ impl TokenMatcher
         println!("Ending match_datatypes() test..");
     }
 
+    pub fn match_word_values()
+    {
+        let expected = [BoolValue(true), BoolValue(false), NilValue];
+        println!("Starting match_word_values() test..");
+        let module = Module::new("tokenmatcher".to_string());
+        let mut scanner = Scanner::new("true false nil", &module);
+
+        for (i, value) in expected.iter().enumerate() {
+            let token = scanner.next_token();
+            if token.value != *value {
+                println!("{}. value({:?}) != expected value({:?})", i,
+                         token.value, value);
+            }
+        }
+        println!("Ending match_word_values() test..");
+    }
+
     pub fn match_symbols()
     {
         let tests = create_tests!("\n", NEWLINE,
@@ -87,21 +106,857 @@ impl TokenMatcher
                                   ">>=", RIGHT_SHIFT_ASSIGN,
                                   "<<=", LEFT_SHIFT_ASSIGN,
                                   "/=", DIV_ASSIGN,
+                                  "//", FLOOR_DIV,
                                   "%", MODULO,
                                   "%=", MODULO_ASSIGN,
                                   "[", LBRACK,
                                   "", EOF);
         println!("Starting match_symbols() test..");
         TokenMatcher::__match(&tests, "
-                              + - * >>= <<= /= % %= [");
+                              + - * >>= <<= /= // % %= [");
         println!("Ending match_symbols() test..");
     }
 
+    pub fn match_errors()
+    {
+        println!("Starting match_errors() test..");
+        match tokenize("x = 1 + 2", "tokenmatcher") {
+            Ok(tokens) => {
+                if tokens.len() != 6 || tokens[5].token_type != EOF {
+                    println!("tokenize returned {} tokens, expected 6 \
+                              ending with EOF", tokens.len());
+                }
+            },
+            Err(errors) => println!("unexpected errors({})", errors.len()),
+        }
+        match tokenize("a $ b\n'abc", "tokenmatcher") {
+            Ok(_) => println!("expected errors, tokenize succeeded"),
+            Err(errors) => {
+                let expected = [(1, 3, "unrecognized character '$'"),
+                                (2, 1, "unterminated string literal")];
+                if errors.len() != expected.len() {
+                    println!("errors({}) != expected errors({})",
+                             errors.len(), expected.len());
+                }
+                let rendered = errors[0].render("a $ b\n'abc");
+                if rendered != "tokenmatcher:1:3: unrecognized character \
+                                '$'\na $ b\n  ^" {
+                    println!("rendered({:?}) is missing the line or caret",
+                             rendered);
+                }
+                for (i, error) in errors.iter().enumerate() {
+                    if i >= expected.len() {
+                        break;
+                    }
+                    let (line_num, line_pos, message) = expected[i];
+                    if error.line_num != line_num ||
+                       error.line_pos != line_pos ||
+                       error.message != message {
+                        println!("{}. error({}) != expected error({}:{}: {})",
+                                 i, error, line_num, line_pos, message);
+                    }
+                }
+            },
+        }
+        // Skipping each one must not cost stack.
+        let input = format!("{}x", "$".repeat(200000));
+        match tokenize(input.as_str(), "tokenmatcher") {
+            Ok(_) => println!("expected errors, tokenize succeeded"),
+            Err(errors) => {
+                if errors.len() != 200000 {
+                    println!("errors({}) != expected errors(200000)",
+                             errors.len());
+                }
+            },
+        }
+        println!("Ending match_errors() test..");
+    }
+
+    pub fn match_escapes()
+    {
+        println!("Starting match_escapes() test..");
+        match tokenize("'\\0\\a\\b\\f\\v\\n\\r\\t'", "tokenmatcher") {
+            Ok(tokens) => {
+                let expected = "\0\x07\x08\x0c\x0b\n\r\t";
+                if tokens[0].value != StringValue(expected.to_string()) {
+                    println!("value({:?}) != expected value({:?})",
+                             tokens[0].value, expected);
+                }
+            },
+            Err(errors) => println!("unexpected errors({})", errors.len()),
+        }
+        match tokenize("'\\q'", "tokenmatcher") {
+            Ok(_) => println!("expected errors, tokenize succeeded"),
+            Err(errors) => {
+                if errors[0].message != "invalid escape character q" {
+                    println!("error({}) != expected error(invalid escape \
+                              character q)", errors[0]);
+                }
+            },
+        }
+        println!("Ending match_escapes() test..");
+    }
+
+    pub fn match_unicode_escapes()
+    {
+        println!("Starting match_unicode_escapes() test..");
+        let tests = [("'\\xC3\\xA9'", "\u{e9}"),
+                     ("'\\u{E9}'", "\u{e9}"),
+                     ("'\u{e9}'", "\u{e9}"),
+                     ("'a\\u{1F600}b'", "a\u{1f600}b"),
+                     ("'\\x41\\u{41}'", "AA")];
+        for (i, &(input, expected)) in tests.iter().enumerate() {
+            match tokenize(input, "tokenmatcher") {
+                Ok(tokens) => {
+                    if tokens[0].value != StringValue(expected.to_string()) {
+                        println!("{}. value({:?}) != expected value({:?})",
+                                 i, tokens[0].value, expected);
+                    }
+                },
+                Err(errors) => println!("{}. unexpected errors({})", i,
+                                        errors.len()),
+            }
+        }
+        /*
+         * Both spell the same string, but '\xC3\xA9' is two
+         * bytes where '\u{E9}' is one code point.
+         */
+        if "\u{e9}".as_bytes() != [0xC3, 0xA9] ||
+           "\u{e9}".chars().count() != 1 {
+            println!("expected 'é' to be one char of two bytes");
+        }
+        let tests = [("'\\xE9'", "invalid UTF-8 in string literal"),
+                     ("'\\u{110000}'", "invalid code point 110000"),
+                     ("'\\u{D800}'", "invalid code point D800"),
+                     ("'\\u{}'", "invalid unicode escape sequence"),
+                     ("'\\u{1234567}'", "invalid unicode escape sequence"),
+                     ("'\\uE9'", "expected '{' after \\u"),
+                     ("'\\u{E9'", "incomplete unicode escape sequence")];
+        for (i, &(input, message)) in tests.iter().enumerate() {
+            match tokenize(input, "tokenmatcher") {
+                Ok(_) => println!("{}. expected errors, tokenize succeeded", i),
+                Err(errors) => {
+                    if errors[0].message != message {
+                        println!("{}. error({}) != expected error({})", i,
+                                 errors[0], message);
+                    }
+                },
+            }
+        }
+        println!("Ending match_unicode_escapes() test..");
+    }
+
+    pub fn match_line_continuation()
+    {
+        let tests = create_tests!("1", INTEGER,
+                                  "+", PLUS,
+                                  "2", INTEGER,
+                                  "\n", NEWLINE,
+                                  "x", IDENT,
+                                  "", EOF);
+        println!("Starting match_line_continuation() test..");
+        TokenMatcher::__match(&tests, "1 +\\\n2\nx");
+
+        let module = Module::new("tokenmatcher".to_string());
+        let mut scanner = Scanner::new("a \\\r\n  b", &module);
+        scanner.next_token();
+        let token = scanner.next_token();
+        if token.token_type != IDENT || token.line_num != 2 ||
+           token.line_pos != 3 {
+            println!("token({}, {}:{}) != expected b at 2:3",
+                     token.string(), token.line_num, token.line_pos);
+        }
+        match tokenize("a \\ b", "tokenmatcher") {
+            Ok(_) => println!("expected errors, tokenize succeeded"),
+            Err(errors) => {
+                if errors[0].message != "unrecognized character '\\'" {
+                    println!("error({}) != expected error(unrecognized \
+                              character '\\')", errors[0]);
+                }
+            },
+        }
+        println!("Ending match_line_continuation() test..");
+    }
+
+    pub fn match_tab_width()
+    {
+        println!("Starting match_tab_width() test..");
+        let tests = [("\tx", DEFAULT_TAB_WIDTH, 9),
+                     ("abc\tx", DEFAULT_TAB_WIDTH, 9),
+                     ("\t\tx", 4, 9),
+                     ("ab\t x", 4, 6),
+                     ("\t'\t'\tx", 4, 13),
+                     ("\t\tx", 1, 3)];
+        for (i, &(input, tab_width, line_pos)) in tests.iter().enumerate() {
+            let module = Module::new("tokenmatcher".to_string());
+            let mut scanner = Scanner::new(input, &module);
+            scanner.set_tab_width(tab_width);
+            let mut token = scanner.next_token();
+            while token.string() != "x" {
+                token = scanner.next_token();
+            }
+            if token.line_pos != line_pos {
+                println!("{}. line_pos({}) != expected line_pos({})", i,
+                         token.line_pos, line_pos);
+            }
+        }
+        let rendered = match tokenize("\t$", "tokenmatcher") {
+            Ok(_) => String::new(),
+            Err(errors) => errors[0].render("\t$"),
+        };
+        if rendered != "tokenmatcher:1:9: unrecognized character '$'\n\t$\n\t^" {
+            println!("rendered({:?}) puts the caret off the tab", rendered);
+        }
+        // The caret follows the width the scanner counted tabs with.
+        let input = "\tab\t$";
+        let module = Module::new("tokenmatcher".to_string());
+        let mut scanner = Scanner::new(input, &module);
+        scanner.set_tab_width(4);
+        while scanner.next_token().token_type != EOF {
+        }
+        let errors = scanner.take_errors();
+        let rendered = errors[0].render_with_tabs(input, 4);
+        if errors[0].line_pos != 9 ||
+           rendered != "tokenmatcher:1:9: unrecognized character \
+                        '$'\n\tab\t$\n\t  \t^" {
+            println!("rendered({:?}) puts the caret off the tab", rendered);
+        }
+        println!("Ending match_tab_width() test..");
+    }
+
+    pub fn match_highlight()
+    {
+        println!("Starting match_highlight() test..");
+        let source = "if x { # check\n  s = 'a'\n}\n=== long\n===\n1.5";
+        let expected = [(IF, "if"), (WHITESPACE, " "), (IDENT, "x"),
+                        (WHITESPACE, " "), (LBRACE, "{"), (WHITESPACE, " "),
+                        (COMMENT, "# check"), (NEWLINE, "\n"),
+                        (WHITESPACE, "  "), (IDENT, "s"), (WHITESPACE, " "),
+                        (ASSIGN, "="), (WHITESPACE, " "), (STRING, "'a'"),
+                        (NEWLINE, "\n"), (RBRACE, "}"), (NEWLINE, "\n"),
+                        (COMMENT, "=== long\n==="), (NEWLINE, "\n"),
+                        (FLOAT, "1.5")];
+        let spans = highlight(source);
+        if spans.len() != expected.len() {
+            println!("spans({}) != expected spans({})", spans.len(),
+                     expected.len());
+        }
+        for (i, &(token_type, start, end)) in spans.iter().enumerate() {
+            if i >= expected.len() {
+                break;
+            }
+            let (expected_type, text) = expected[i];
+            if token_type != expected_type || &source[start..end] != text {
+                println!("{}. span({:?}, {:?}) != expected span({:?}, {:?})",
+                         i, token_type, &source[start..end], expected_type,
+                         text);
+            }
+        }
+        println!("Ending match_highlight() test..");
+    }
+
+    pub fn match_integer_range()
+    {
+        println!("Starting match_integer_range() test..");
+        let range = "integers range from -9223372036854775808 to \
+                     9223372036854775807";
+        let tests = [("99999999999999999999", 1, "99999999999999999999"),
+                     ("-9223372036854775809", 2, "9223372036854775809"),
+                     ("1_000_000_000_000_000_000_000", 1,
+                      "1_000_000_000_000_000_000_000")];
+        for (i, &(input, line_pos, literal)) in tests.iter().enumerate() {
+            let expected = format!("integer literal {} is too large, {}",
+                                   literal, range);
+            match tokenize(input, "tokenmatcher") {
+                Ok(_) => println!("{}. expected errors, tokenize succeeded", i),
+                Err(errors) => {
+                    if errors[0].message != expected ||
+                       errors[0].line_pos != line_pos {
+                        println!("{}. error({}) != expected error(1:{}: {})",
+                                 i, errors[0], line_pos, expected);
+                    }
+                },
+            }
+        }
+        match tokenize("9223372036854775807", "tokenmatcher") {
+            Ok(tokens) => {
+                if tokens[0].value != IntegerValue(i64::max_value()) {
+                    println!("value({:?}) != expected value({})",
+                             tokens[0].value, i64::max_value());
+                }
+            },
+            Err(errors) => println!("unexpected errors({})", errors.len()),
+        }
+        println!("Ending match_integer_range() test..");
+    }
+
+    pub fn match_iterator()
+    {
+        let expected = [("1", INTEGER), ("+", PLUS), ("2", INTEGER),
+                        ("", EOF)];
+        println!("Starting match_iterator() test..");
+        let module = Module::new("tokenmatcher".to_string());
+        let scanner = Scanner::new("1 + 2", &module);
+
+        let tokens: Vec<Token> = scanner.collect();
+        if tokens.len() != expected.len() {
+            println!("tokens({}) != expected tokens({})", tokens.len(),
+                     expected.len());
+        }
+        for (i, token) in tokens.iter().enumerate() {
+            if i >= expected.len() {
+                break;
+            }
+            let (text, token_type) = expected[i];
+            if token.string() != text || token.token_type != token_type {
+                println!("{}. token({}, {:?}) != expected token({}, {:?})",
+                         i, token.text, token.token_type, text, token_type);
+            }
+        }
+        println!("Ending match_iterator() test..");
+    }
+
+    pub fn match_spans()
+    {
+        let input = "x = 10\n'ab' ";
+        let expected = [(0, 1), (2, 3), (4, 6), (6, 7), (7, 11), (12, 12)];
+        println!("Starting match_spans() test..");
+        let module = Module::new("tokenmatcher".to_string());
+        let scanner = Scanner::new(input, &module);
+
+        for (i, token) in scanner.enumerate() {
+            if i >= expected.len() {
+                println!("{}. unexpected token({})", i, token.text);
+                break;
+            }
+            if (token.start, token.end) != expected[i] {
+                println!("{}. span({}, {}) != expected span{:?}",
+                         i, token.start, token.end, expected[i]);
+            }
+        }
+        println!("Ending match_spans() test..");
+    }
+
+    pub fn match_positions()
+    {
+        let input = "a = 1\n  b\n=== long\ncomment === 'multi\nline' c";
+        let expected = [("a", 1, 1), ("=", 1, 3), ("1", 1, 5),
+                        ("\n", 1, 6), ("b", 2, 3), ("\n", 2, 4),
+                        ("multi\nline", 4, 13), ("c", 5, 7), ("", 5, 8)];
+        println!("Starting match_positions() test..");
+        let module = Module::new("tokenmatcher".to_string());
+        let scanner = Scanner::new(input, &module);
+
+        for (i, token) in scanner.enumerate() {
+            if i >= expected.len() {
+                println!("{}. unexpected token({})", i, token.text);
+                break;
+            }
+            let (text, line_num, line_pos) = expected[i];
+            if token.string() != text || token.line_num != line_num ||
+               token.line_pos != line_pos {
+                println!("{}. token({}, {}:{}) != expected token({}, {}:{})",
+                         i, token.text, token.line_num, token.line_pos,
+                         text, line_num, line_pos);
+            }
+        }
+        println!("Ending match_positions() test..");
+    }
+
+    pub fn match_line_endings()
+    {
+        println!("Starting match_line_endings() test..");
+        for input in ["a\r\nb", "a\rb", "a # comment\r\nb"].iter() {
+            let expected = [(IDENT, 1), (NEWLINE, 1), (IDENT, 2), (EOF, 2)];
+            let module = Module::new("tokenmatcher".to_string());
+            let scanner = Scanner::new(input, &module);
+
+            let tokens: Vec<Token> = scanner.collect();
+            if tokens.len() != expected.len() {
+                println!("{:?}: tokens({}) != expected tokens({})",
+                         input, tokens.len(), expected.len());
+                continue;
+            }
+            for (i, token) in tokens.iter().enumerate() {
+                let (token_type, line_num) = expected[i];
+                if token.token_type != token_type ||
+                   token.line_num != line_num {
+                    println!("{:?}: {}. token({:?}, {}) != expected \
+                              token({:?}, {})", input, i, token.token_type,
+                             token.line_num, token_type, line_num);
+                }
+            }
+        }
+        println!("Ending match_line_endings() test..");
+    }
+
+    pub fn match_nested_comments()
+    {
+        let tests = create_tests!("a", IDENT,
+                                  "\n", NEWLINE,
+                                  "\n", NEWLINE,
+                                  "b", IDENT,
+                                  "\n", NEWLINE,
+                                  "c", IDENT,
+                                  "", EOF);
+        println!("Starting match_nested_comments() test..");
+        TokenMatcher::__match(&tests, "a
+                              === outer
+                                  === inner
+                                      === innermost
+                                      ===
+                                  ===
+                              ===
+                              b
+                              === comment === c");
+        println!("Ending match_nested_comments() test..");
+
+        println!("Starting match_unterminated_nested_comment() test..");
+        match tokenize("=== outer\n=== inner\n===\n", "tokenmatcher") {
+            Ok(_) => println!("expected error, tokenize succeeded"),
+            Err(errors) => {
+                if errors.len() != 1 ||
+                   errors[0].message != "unterminated long comment" {
+                    println!("errors({:?}) != expected error(unterminated \
+                              long comment)", errors);
+                }
+            },
+        }
+        println!("Ending match_unterminated_nested_comment() test..");
+
+        /*
+         * A '===' which does not start its line closes the
+         * comment, whatever follows it on that line is code.
+         */
+        println!("Starting match_comment_then_code() test..");
+        let inputs = ["=== comment === code",
+                      "=== comment\n    more === code",
+                      "x === comment === code === again === code"];
+        for input in inputs.iter() {
+            match tokenize(input, "tokenmatcher") {
+                Ok(tokens) => {
+                    let last = &tokens[tokens.len() - 2];
+                    if last.token_type != IDENT || last.string() != "code" {
+                        println!("{:?} ended in text({}) != expected \
+                                  text(code)", input, last.text);
+                    }
+                },
+                Err(errors) => println!("{:?} unexpected errors({:?})",
+                                        input, errors),
+            }
+        }
+        println!("Ending match_comment_then_code() test..");
+    }
+
+    pub fn match_peek_token()
+    {
+        println!("Starting match_peek_token() test..");
+        let module = Module::new("tokenmatcher".to_string());
+        let mut scanner = Scanner::new("a = b", &module);
+
+        let peeked = [scanner.peek_token(2).token_type,
+                      scanner.peek_token(0).token_type,
+                      scanner.peek_token(3).token_type];
+        if peeked != [IDENT, IDENT, EOF] ||
+           scanner.peek_token(2).string() != "b" {
+            println!("peeked({:?}) != expected peeked([IDENT, IDENT, EOF])",
+                     peeked);
+        }
+        let expected = ["a", "=", "b", ""];
+        for (i, text) in expected.iter().enumerate() {
+            let token = scanner.next_token();
+            if token.string() != *text {
+                println!("{}. text({}) != expected text({})", i,
+                         token.text, text);
+            }
+        }
+        println!("Ending match_peek_token() test..");
+    }
+
+    pub fn match_empty_input()
+    {
+        println!("Starting match_empty_input() test..");
+        let tests = ["", "   ", "# comment", "\n\n", "=== long ===",
+                     "=== long"];
+        let module = Module::new("tokenmatcher".to_string());
+        for (i, input) in tests.iter().enumerate() {
+            let mut scanner = Scanner::new(input, &module);
+            let mut types: Vec<TokenType> = Vec::new();
+            // Reading on past the end keeps giving EOF.
+            for _ in 0..4 {
+                types.push(scanner.next_token().token_type);
+            }
+            let types: Vec<TokenType> = types.into_iter()
+                                             .filter(|&t| t != NEWLINE)
+                                             .collect();
+            if types != [EOF, EOF, EOF, EOF][..types.len()] ||
+               types.len() < 2 {
+                println!("{}. types({:?}) != expected types([EOF, ..])", i,
+                         types);
+            }
+        }
+        println!("Ending match_empty_input() test..");
+    }
+
+    pub fn match_length_limits()
+    {
+        println!("Starting match_length_limits() test..");
+        let tests = [("x = 'abcdefgh'", None),
+                     ("x = 'abcdefghi'", Some((1, 5, "string literal is \
+                       longer than the maximum of 8 bytes"))),
+                     ("x = '\\x41\\x42cdefgh'", None),
+                     ("\n  \"abcdefghijklmnop\"", Some((2, 3, "string \
+                       literal is longer than the maximum of 8 bytes"))),
+                     ("abcdef = 1", None),
+                     ("x = abcdefg", Some((1, 5, "identifier is longer than \
+                       the maximum of 6 bytes")))];
+        let module = Module::new("tokenmatcher".to_string());
+        for (i, &(input, expected)) in tests.iter().enumerate() {
+            let mut scanner = Scanner::new(input, &module);
+            scanner.set_max_ident_len(6);
+            scanner.set_max_string_len(8);
+            let mut tokens: Vec<Token> = Vec::new();
+            loop {
+                let token = scanner.next_token();
+                if token.token_type == EOF {
+                    break;
+                }
+                tokens.push(token);
+            }
+            let errors = scanner.take_errors();
+            let found = errors.first()
+                              .map(|e| (e.line_num, e.line_pos,
+                                        e.message.as_str()));
+            if found != expected || errors.len() > 1 {
+                println!("{}. errors({:?}) != expected error({:?})", i,
+                         errors.iter().map(|e| e.to_string())
+                                      .collect::<Vec<String>>(), expected);
+            }
+            // The token is still whole, only its text is cut short.
+            let last = &tokens[tokens.len() - 1];
+            if last.string().len() > 8 || last.end != input.len() {
+                println!("{}. last token({}) was not cut short", i,
+                         last.string());
+            }
+        }
+        // Multibyte characters are never split, nor is anything
+        // after the first cut kept.
+        let tests = [("'ééééé'", 5, "éé"), ("'abcdéxyz'", 4, "abcd"),
+                     ("'abcdéxyz'", 5, "abcd"), ("'abcdéxyz'", 6, "abcdé"),
+                     ("'ab\\x41éxyz'", 4, "abA")];
+        for (i, &(input, max, expected)) in tests.iter().enumerate() {
+            let mut scanner = Scanner::new(input, &module);
+            scanner.set_max_string_len(max);
+            let token = scanner.next_token();
+            if token.string() != expected || scanner.take_errors().len() != 1 {
+                println!("{}. text({}) != expected text({})", i,
+                         token.string(), expected);
+            }
+        }
+        println!("Ending match_length_limits() test..");
+    }
+
+    pub fn match_scan_with()
+    {
+        println!("Starting match_scan_with() test..");
+        let module = Module::new("tokenmatcher".to_string());
+        let mut scanner = Scanner::new("if a {\ndebug 1\n}", &module);
+        let mut texts: Vec<String> = Vec::new();
+        let mut eofs = 0;
+        scanner.scan_with(|token| {
+            texts.push(token.string().to_string());
+            if token.token_type == EOF {
+                eofs += 1;
+            }
+        });
+        let expected = ["if", "a", "{", "\n", "debug", "1", "\n", "}", ""];
+        if texts != expected || eofs != 1 {
+            println!("texts({:?}) != expected texts({:?})", texts, expected);
+        }
+
+        // Tokens already peeked are passed on first.
+        let mut scanner = Scanner::new("a b", &module);
+        scanner.peek_token(1);
+        scanner.next_token();
+        let mut count = 0;
+        scanner.scan_with(|_| count += 1);
+        if count != 2 {
+            println!("count({}) != expected count(2)", count);
+        }
+        println!("Ending match_scan_with() test..");
+    }
+
+    pub fn match_reset()
+    {
+        println!("Starting match_reset() test..");
+        let module = Module::new("tokenmatcher".to_string());
+        let mut scanner = Scanner::new("a / 'unterminated", &module);
+        scanner.set_word_operators(true);
+        scanner.peek_token(2);
+        let programs = [
+            ("x = 1\nif y", vec![(IDENT, "x", 1, 1), (ASSIGN, "=", 1, 3),
+                                 (INTEGER, "1", 1, 5), (NEWLINE, "\n", 1, 6),
+                                 (IF, "if", 2, 1), (IDENT, "y", 2, 4),
+                                 (EOF, "", 2, 5)]),
+            // '/' at the start opens a regex, and 'not' is still '!'.
+            ("/b/ not", vec![(REGEX, "/b/", 1, 1), (BANG, "!", 1, 5),
+                             (EOF, "", 1, 8)])];
+        for (i, &(program, ref expected)) in programs.iter().enumerate() {
+            scanner.reset(program);
+            let mut found: Vec<(TokenType, String, i32, i32)> = Vec::new();
+            scanner.scan_with(|t| {
+                found.push((t.token_type, t.string().to_string(), t.line_num,
+                            t.line_pos))
+            });
+            let expected: Vec<(TokenType, String, i32, i32)> = expected.iter()
+                .map(|&(t, s, l, p)| (t, s.to_string(), l, p))
+                .collect();
+            if found != expected {
+                println!("{}. tokens({:?}) != expected tokens({:?})", i, found,
+                         expected);
+            }
+            let errors = scanner.take_errors();
+            if errors.len() != 0 {
+                println!("{}. unexpected errors({})", i, errors[0]);
+            }
+        }
+        println!("Ending match_reset() test..");
+    }
+
+    pub fn match_hex_numbers()
+    {
+        println!("Starting match_hex_numbers() test..");
+        let tests = [("0xFF", IntegerValue(255)),
+                     ("0X1f", IntegerValue(31)),
+                     ("0x10", IntegerValue(16)),
+                     ("0x1.8p3", FloatValue(12.0)),
+                     ("0x1.8", FloatValue(1.5)),
+                     ("0xAp-2", FloatValue(2.5)),
+                     ("0x1P+4", FloatValue(16.0)),
+                     ("0x.8p1", FloatValue(1.0)),
+                     ("0x1.fffffffffffffp1023", FloatValue(::std::f64::MAX)),
+                     ("0x1p-1074", FloatValue(5e-324)),
+                     ("0x1p2000", FloatValue(::std::f64::INFINITY))];
+        for (i, &(input, ref expected)) in tests.iter().enumerate() {
+            match tokenize(input, "tokenmatcher") {
+                Ok(tokens) => {
+                    if tokens[0].value != *expected {
+                        println!("{}. value({:?}) != expected value({:?})", i,
+                                 tokens[0].value, expected);
+                    }
+                },
+                Err(errors) => println!("{}. unexpected error({})", i,
+                                        errors[0]),
+            }
+        }
+        // No digit after the '.' or the 'p', these are not part of it.
+        let tests = [("0x1..3", vec![INTEGER, DOTDOT, INTEGER, EOF]),
+                     ("0x1.p", vec![INTEGER, DOT, IDENT, EOF]),
+                     ("0x1p", vec![INTEGER, IDENT, EOF]),
+                     ("0x1p-", vec![INTEGER, IDENT, MINUS, EOF])];
+        for (i, &(input, ref expected)) in tests.iter().enumerate() {
+            match tokenize(input, "tokenmatcher") {
+                Ok(tokens) => {
+                    let types: Vec<TokenType> = tokens.iter()
+                                                      .map(|t| t.token_type)
+                                                      .collect();
+                    if types != *expected {
+                        println!("{}. types({:?}) != expected types({:?})", i,
+                                 types, expected);
+                    }
+                },
+                Err(errors) => println!("{}. unexpected error({})", i,
+                                        errors[0]),
+            }
+        }
+        println!("Ending match_hex_numbers() test..");
+    }
+
+    pub fn match_regex()
+    {
+        let tests = create_tests!("x", IDENT,
+                                  "=", ASSIGN,
+                                  "/ab+/", REGEX,
+                                  "\n", NEWLINE,
+                                  "a", IDENT,
+                                  "/", DIV,
+                                  "b", IDENT,
+                                  "\n", NEWLINE,
+                                  "f", IDENT,
+                                  "(", LPAREN,
+                                  "/a\\/b/im", REGEX,
+                                  ")", RPAREN,
+                                  "/", DIV,
+                                  "2", INTEGER,
+                                  "", EOF);
+        println!("Starting match_regex() test..");
+        TokenMatcher::__match(&tests, "x = /ab+/
+                              a / b
+                              f(/a\\/b/im) / 2");
+
+        let module = Module::new("tokenmatcher".to_string());
+        let mut scanner = Scanner::new("/a\\/b\\d/im", &module);
+        let expected = RegexValue("a/b\\d".to_string(), "im".to_string());
+        let token = scanner.next_token();
+        if token.value != expected {
+            println!("regex value of {} is not a/b\\d with flags im",
+                     token.text);
+        }
+        let mut scanner = Scanner::new("/\u{e9}+ \u{2192}/", &module);
+        let expected = RegexValue("\u{e9}+ \u{2192}".to_string(),
+                                  String::new());
+        let token = scanner.next_token();
+        if token.value != expected {
+            println!("regex value of {} is not \u{e9}+ \u{2192}", token.text);
+        }
+        match tokenize("x = /ab\n", "tokenmatcher") {
+            Ok(_) => println!("expected error, tokenize succeeded"),
+            Err(errors) => {
+                if errors.len() != 1 ||
+                   errors[0].message != "unterminated regex literal" {
+                    println!("errors({:?}) != expected error(unterminated \
+                              regex literal)", errors);
+                }
+            },
+        }
+        println!("Ending match_regex() test..");
+    }
+
+    pub fn match_digit_separators()
+    {
+        println!("Starting match_digit_separators() test..");
+        let module = Module::new("tokenmatcher".to_string());
+        let mut scanner = Scanner::new("1_00_000 1_000.000_5", &module);
+        let expected = [IntegerValue(100000), FloatValue(1000.0005)];
+        for (i, value) in expected.iter().enumerate() {
+            let token = scanner.next_token();
+            if token.value != *value {
+                println!("{}. value of {} is not the expected value", i,
+                         token.text);
+            }
+        }
+        let tests = [("x = 1__000", 7, "doubled digit separator"),
+                     ("x = 1_", 6, "digit separator at end of number"),
+                     ("x = 1_.5", 6, "digit separator at end of number"),
+                     ("x = 1.5_", 8, "digit separator at end of number"),
+                     ("x = 1._5", 7, "digit separator at start of fraction")];
+        for (i, &(input, line_pos, message)) in tests.iter().enumerate() {
+            match tokenize(input, "tokenmatcher") {
+                Ok(_) => println!("{}. expected error, tokenize succeeded",
+                                  i),
+                Err(errors) => {
+                    if errors.len() != 1 || errors[0].line_pos != line_pos ||
+                       errors[0].message != message {
+                        println!("{}. errors({:?}) != expected error({}: {})",
+                                 i, errors, line_pos, message);
+                    }
+                },
+            }
+        }
+        match tokenize("_1", "tokenmatcher") {
+            Ok(tokens) => {
+                if tokens[0].token_type != IDENT {
+                    println!("_1 did not scan as an identifier");
+                }
+            },
+            Err(errors) => println!("unexpected errors({:?})", errors),
+        }
+        println!("Ending match_digit_separators() test..");
+    }
+
+    /*
+     * Scans a large generated program and checks that every
+     * spelling of a word or operator shares one allocation,
+     * so the 140000 words and operators cost six strings.
+     */
+    pub fn match_interning()
+    {
+        let lines = 20000;
+        let mut input = String::new();
+
+        for i in 0..lines {
+            input.push_str(format!("total = total + step * {}\n", i)
+                           .as_str());
+        }
+        println!("Starting match_interning() test..");
+        let module = Module::new("tokenmatcher".to_string());
+        let mut scanner = Scanner::new(input.as_str(), &module);
+        let tokens: Vec<Token> = scanner.by_ref().collect();
+
+        let mut words = 0;
+        let mut allocations: Vec<&Rc<str>> = Vec::new();
+        for (i, token) in tokens.iter().enumerate() {
+            let interned = token.token_type != INTEGER &&
+                           token.token_type != EOF;
+            if interned {
+                words += 1;
+                if !allocations.iter().any(|a| Rc::ptr_eq(a, &token.text)) {
+                    allocations.push(&token.text);
+                }
+            }
+            match token.symbol {
+                Some(symbol) if interned => {
+                    if scanner.interner().resolve(symbol) != token.string() {
+                        println!("{}. symbol({:?}) resolves to {}", i,
+                                 symbol,
+                                 scanner.interner().resolve(symbol));
+                    }
+                },
+                None if !interned => (),
+                symbol => println!("{}. symbol({:?}) for text({})", i,
+                                   symbol, token.text),
+            }
+        }
+        let total = &tokens[0];
+        for (i, token) in tokens.iter().enumerate() {
+            if token.symbol == total.symbol &&
+               !Rc::ptr_eq(&token.text, &total.text) {
+                println!("{}. text({}) is not shared", i, token.text);
+            }
+        }
+        // Six words and operators plus the newline per line.
+        if words != lines * 7 {
+            println!("words({}) != expected words({})", words, lines * 7);
+        }
+        // total, =, +, step, * and the newline.
+        if scanner.interner().len() != 6 {
+            println!("interner len({}) != expected len(6)",
+                     scanner.interner().len());
+        }
+        if allocations.len() != 6 {
+            println!("allocations({}) != expected allocations(6)",
+                     allocations.len());
+        }
+        println!("Ending match_interning() test..");
+    }
+
     pub fn match_all()
     {
         TokenMatcher::match_reserved_words();
         TokenMatcher::match_datatypes();
+        TokenMatcher::match_word_values();
         TokenMatcher::match_symbols();
+        TokenMatcher::match_errors();
+        TokenMatcher::match_escapes();
+        TokenMatcher::match_unicode_escapes();
+        TokenMatcher::match_line_continuation();
+        TokenMatcher::match_tab_width();
+        TokenMatcher::match_highlight();
+        TokenMatcher::match_integer_range();
+        TokenMatcher::match_iterator();
+        TokenMatcher::match_spans();
+        TokenMatcher::match_positions();
+        TokenMatcher::match_line_endings();
+        TokenMatcher::match_nested_comments();
+        TokenMatcher::match_peek_token();
+        TokenMatcher::match_empty_input();
+        TokenMatcher::match_length_limits();
+        TokenMatcher::match_scan_with();
+        TokenMatcher::match_reset();
+        TokenMatcher::match_hex_numbers();
+        TokenMatcher::match_regex();
+        TokenMatcher::match_digit_separators();
+        TokenMatcher::match_interning();
     }
 
     fn __match(tests: &[TokenMatcher], input: &'static str)
@@ -113,7 +968,7 @@ impl TokenMatcher
         for tt in tests {
             let token = scanner.next_token();
 
-            if token.text != tt.expected_text {
+            if token.string() != tt.expected_text {
                 println!("{}. text({}) != expected text({})",
                          i, token.text, tt.expected_text);
             }
@@ -0,0 +1,112 @@
+/*
+ * The symbol table gives an outline of a program, the
+ * subroutines it declares (with their nested declarations)
+ * and the variables assigned at the top level. It is meant
+ * for tooling, nothing here is needed to run a program.
+ */
+use token::TokenType::*;
+use intermediate::*;
+
+pub mod symbols_test;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum SymbolKind {
+    Subroutine,
+    Variable,
+    Constant,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Symbol {
+    pub name: String,
+    pub kind: SymbolKind,
+    pub params: Vec<String>,
+    pub line_num: i32,
+    pub line_pos: i32,
+    pub children: Vec<Symbol>,
+}
+
+pub struct SymbolTable {
+    pub symbols: Vec<Symbol>,
+}
+
+impl SymbolTable
+{
+    pub fn build(program: &Node) -> SymbolTable
+    {
+        let mut symbols: Vec<Symbol> = Vec::new();
+
+        for child in &program.children {
+            let targets = match child.get_type() {
+                ASSIGN => &child.children[..1],
+                MULTI_ASSIGN => &child.children[0].children[..],
+                CONST_DECL => {
+                    let mut symbol = SymbolTable::variable(&child.children[0]);
+                    symbol.kind = SymbolKind::Constant;
+                    symbols.push(symbol);
+                    continue;
+                },
+                _ => continue,
+            };
+            for target in targets {
+                if target.get_type() != IDENT {
+                    continue;
+                }
+                let name = target.string();
+                if !symbols.iter().any(|s| s.name == name) {
+                    symbols.push(SymbolTable::variable(target));
+                }
+            }
+        }
+        SymbolTable::subroutines(program, &mut symbols);
+
+        return SymbolTable {
+            symbols: symbols,
+        };
+    }
+
+    fn variable(node: &Node) -> Symbol
+    {
+        return Symbol {
+            name: node.string().to_string(),
+            kind: SymbolKind::Variable,
+            params: Vec::new(),
+            line_num: node.token.line_num,
+            line_pos: node.token.line_pos,
+            children: Vec::new(),
+        };
+    }
+
+    /*
+     * Collects every SUB_DECL below node. The search stops at
+     * a declaration, whatever is declared within its body
+     * becomes a child of that symbol instead.
+     */
+    fn subroutines(node: &Node, symbols: &mut Vec<Symbol>)
+    {
+        for child in &node.children {
+            if child.get_type() != SUB_DECL {
+                SymbolTable::subroutines(child, symbols);
+                continue;
+            }
+            let name = &child.children[0];
+            let mut symbol = Symbol {
+                name: name.string().to_string(),
+                kind: SymbolKind::Subroutine,
+                params: Vec::new(),
+                line_num: name.token.line_num,
+                line_pos: name.token.line_pos,
+                children: Vec::new(),
+            };
+            for param in &child.children[1].children {
+                match param.get_type() {
+                    REST_PARAM => symbol.params.push(
+                        param.children[0].string().to_string()),
+                    _ => symbol.params.push(param.string().to_string()),
+                }
+            }
+            SymbolTable::subroutines(&child.children[2], &mut symbol.children);
+            symbols.push(symbol);
+        }
+    }
+}
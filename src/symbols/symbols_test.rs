@@ -0,0 +1,93 @@
+/*
+ * Test that the symbol table outlines a program with
+ * nested subroutine declarations.
+ */
+use parser::*;
+use symbols::*;
+
+pub struct SymbolMatcher;
+
+impl SymbolMatcher
+{
+    pub fn match_nested_subroutines()
+    {
+        println!("Starting match_nested_subroutines() test..");
+        let input = "count = 0\n\
+                     def outer(a, b) {\n\
+                     \x20   def inner(c) {\n\
+                     \x20       return c\n\
+                     \x20   }\n\
+                     \x20   return inner(a)\n\
+                     }\n\
+                     count = 1";
+        let program = match parse(input, "symbolmatcher") {
+            Ok(program) => program,
+            Err(errors) => {
+                println!("unexpected errors({})", errors.len());
+                return;
+            },
+        };
+        let table = SymbolTable::build(&program);
+        let expected = Symbol {
+            name: "outer".to_string(),
+            kind: SymbolKind::Subroutine,
+            params: vec!["a".to_string(), "b".to_string()],
+            line_num: 2,
+            line_pos: 5,
+            children: vec![Symbol {
+                name: "inner".to_string(),
+                kind: SymbolKind::Subroutine,
+                params: vec!["c".to_string()],
+                line_num: 3,
+                line_pos: 9,
+                children: Vec::new(),
+            }],
+        };
+        if table.symbols.len() != 2 {
+            println!("symbols({}) != expected symbols(2)",
+                     table.symbols.len());
+            return;
+        }
+        if table.symbols[0].name != "count" ||
+           table.symbols[0].kind != SymbolKind::Variable ||
+           table.symbols[0].line_num != 1 ||
+           table.symbols[0].line_pos != 1 {
+            println!("0. symbol({:?}) != expected variable count",
+                     table.symbols[0]);
+        }
+        if table.symbols[1] != expected {
+            println!("1. symbol({:?}) != expected symbol({:?})",
+                     table.symbols[1], expected);
+        }
+        println!("Ending match_nested_subroutines() test..");
+    }
+
+    pub fn match_constants()
+    {
+        println!("Starting match_constants() test..");
+        let program = match parse("const MAX = 10\nn = MAX", "symbolmatcher") {
+            Ok(program) => program,
+            Err(errors) => {
+                println!("unexpected errors({})", errors.len());
+                return;
+            },
+        };
+        let table = SymbolTable::build(&program);
+        let kinds: Vec<(&str, SymbolKind)> = table.symbols.iter()
+            .map(|s| (s.name.as_str(), s.kind))
+            .collect();
+        let expected = [("MAX", SymbolKind::Constant),
+                        ("n", SymbolKind::Variable)];
+        if kinds != expected {
+            println!("symbols({:?}) != expected symbols({:?})", kinds,
+                     expected);
+        }
+        println!("Ending match_constants() test..");
+    }
+
+    pub fn match_all()
+    {
+        SymbolMatcher::match_nested_subroutines();
+        SymbolMatcher::match_constants();
+    }
+}
@@ -11,9 +11,23 @@ macro_rules! is_between {
     );
 }
 
+use std::collections::HashMap;
+use std::fmt;
+use std::rc::Rc;
+/*
+ * With the serde feature tokens and values, and so whole
+ * trees, can be written out and read back, eg. to cache
+ * parsed programs. Token text needs serde's rc feature.
+ */
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+pub mod token_test;
+
 #[allow(non_camel_case_types)]
-#[derive(Copy, Clone, Debug, PartialEq,
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash,
          PartialOrd)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum TokenType {
     // DATATYPES
     STRING,
@@ -22,23 +36,30 @@ pub enum TokenType {
     TRUE,
     FALSE,
     NIL,
+    REGEX,
     IDENT,
 
     // RESERVED WORDS
     DEF,
+    CONST,
     IF,
     ELIF,
     ELSE,
     FOR,
     WHILE,
     UNTIL,
+    DO,
+    WITH,
     SWITCH,
+    MATCH,
     CASE,
     DEFAULT,
     IN,
     IMPORT,
     DEBUG,
+    ASSERT,
     RETURN,
+    YIELD,
 
     // SYMBOLS
     LOGICAL_OR,
@@ -60,6 +81,7 @@ pub enum TokenType {
     MINUS,
     MUL,
     DIV,
+    FLOOR_DIV,
     MODULO,
     BANG,
     COMPL,
@@ -71,6 +93,8 @@ pub enum TokenType {
     RBRACE,
     COMMA,
     SEMICOLON,
+    COLON,
+    QUESTION,
     ASSIGN_ARROW,
     NEWLINE,
 
@@ -89,73 +113,680 @@ pub enum TokenType {
 
     // Imaginary tokens
     BLOCK,
+    // 'a, b = 1, 2', holding ASSIGN_TARGETS and ASSIGN_VALUES.
+    MULTI_ASSIGN,
+    ASSIGN_TARGETS,
+    ASSIGN_VALUES,
     SUB_DECL,
+    // 'const name = value'.
+    CONST_DECL,
     SUB_LITERAL,
     SUB_PARAMS,
+    REST_PARAM,
+    // ': Type' after a parameter or an assigned name.
+    TYPE_ANNOT,
     ARRAY_DECL,
     HASH_DECL,
     HASH_ELEM,
+    ARRAY_COMP,
+    HASH_COMP,
+    // Loop variables of a comprehension.
+    COMP_VARS,
     CALL,
+    // name = value in the arguments of a call.
+    KWARG,
     SUBSCRIPT,
+    // a[range], the range may leave out its bounds.
+    SLICE,
+    // Stands in for a bound left out of a slice.
+    OPEN_BOUND,
+    MEMBER,
+    TERNARY,
+    IN_TEST,
+    // a < b <= c, the operators hold the operands they add.
+    CHAIN_CMP,
+    RANGE,
     // MINUS is changed into NEGATE on parsing time.
     NEGATE,
+    // And a PLUS into UNARY_PLUS, which leaves a number as is.
+    UNARY_PLUS,
     SWITCH_BRANCH,
     SWITCH_EXPRS,
+    // A pattern and its body in a match statement.
+    MATCH_ARM,
+    // The '_' pattern, which matches anything.
+    WILDCARD,
+    DO_EXPR,
+    STEP,
+    // The module named by an import.
+    MODULE_REF,
+    // Stands in for a statement which failed to parse.
+    ERROR,
+    // Skipped by the scanner, except when highlighting.
+    WHITESPACE,
+    COMMENT,
 
     EOF,
 }
 
+/*
+ * The classes below are ranges of TokenType, so moving a
+ * type across one of their bounds changes its class.
+ */
+impl TokenType
+{
+    /*
+     * The datatypes, literals and identifiers alike, STRING
+     * up to IDENT.
+     */
+    pub fn is_literal(&self) -> bool
+    {
+        return is_between!(*self, TokenType::STRING, TokenType::IDENT);
+    }
+
+    /*
+     * The reserved words from DEF up to IMPORT. DEBUG,
+     * ASSERT, RETURN and YIELD come after IMPORT and are not
+     * included.
+     */
+    pub fn is_keyword(&self) -> bool
+    {
+        return is_between!(*self, TokenType::DEF, TokenType::IMPORT);
+    }
+
+    /*
+     * The symbols from LOGICAL_OR up to COMPL, the unary and
+     * binary operators, except for '.' and '..' which sit
+     * among them but build members and ranges. The brackets
+     * and other punctuation after COMPL are not included.
+     */
+    pub fn is_operator(&self) -> bool
+    {
+        if *self == TokenType::DOT || *self == TokenType::DOTDOT {
+            return false;
+        }
+        return is_between!(*self, TokenType::LOGICAL_OR,
+                           TokenType::COMPL);
+    }
+
+    // ASSIGN up to MODULO_ASSIGN, all of the assignments.
+    pub fn is_assignment(&self) -> bool
+    {
+        return is_between!(*self, TokenType::ASSIGN,
+                           TokenType::MODULO_ASSIGN);
+    }
+}
+
+/*
+ * Describes a token type the way a diagnostic would,
+ * PLUS is "'+'" and IF is "keyword 'if'". Imaginary
+ * types never come from source and print their name.
+ */
+impl fmt::Display for TokenType
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+    {
+        use self::TokenType::*;
+
+        let text = match *self {
+            STRING  => "string literal",
+            INTEGER => "integer literal",
+            FLOAT   => "float literal",
+            REGEX   => "regex literal",
+            TRUE    => "'true'",
+            FALSE   => "'false'",
+            NIL     => "'nil'",
+            IDENT   => "identifier",
+            DEF     => "keyword 'def'",
+            CONST   => "keyword 'const'",
+            IF      => "keyword 'if'",
+            ELIF    => "keyword 'elif'",
+            ELSE    => "keyword 'else'",
+            FOR     => "keyword 'for'",
+            WHILE   => "keyword 'while'",
+            UNTIL   => "keyword 'until'",
+            DO      => "keyword 'do'",
+            WITH    => "keyword 'with'",
+            SWITCH  => "keyword 'switch'",
+            MATCH   => "keyword 'match'",
+            CASE    => "keyword 'case'",
+            DEFAULT => "keyword 'default'",
+            IN      => "keyword 'in'",
+            IMPORT  => "keyword 'import'",
+            DEBUG   => "keyword 'debug'",
+            ASSERT  => "keyword 'assert'",
+            RETURN  => "keyword 'return'",
+            YIELD   => "keyword 'yield'",
+            LOGICAL_OR => "'||'",
+            LOGICAL_AND => "'&&'",
+            EQL     => "'=='",
+            NOT_EQL => "'!='",
+            LT      => "'<'",
+            LE      => "'<='",
+            GT      => "'>'",
+            GE      => "'>='",
+            BITWISE_OR => "'|'",
+            BITWISE_XOR => "'^'",
+            BITWISE_AND => "'&'",
+            LEFT_SHIFT => "'<<'",
+            RIGHT_SHIFT => "'>>'",
+            DOT     => "'.'",
+            DOTDOT  => "'..'",
+            PLUS    => "'+'",
+            MINUS   => "'-'",
+            MUL     => "'*'",
+            DIV     => "'/'",
+            FLOOR_DIV => "'//'",
+            MODULO  => "'%'",
+            BANG    => "'!'",
+            COMPL   => "'~'",
+            LPAREN  => "'('",
+            RPAREN  => "')'",
+            LBRACK  => "'['",
+            RBRACK  => "']'",
+            LBRACE  => "'{'",
+            RBRACE  => "'}'",
+            COMMA   => "','",
+            SEMICOLON => "';'",
+            COLON   => "':'",
+            QUESTION => "'?'",
+            ASSIGN_ARROW => "'=>'",
+            ASSIGN  => "'='",
+            BITWISE_OR_ASSIGN => "'|='",
+            BITWISE_XOR_ASSIGN => "'^='",
+            BITWISE_AND_ASSIGN => "'&='",
+            LEFT_SHIFT_ASSIGN => "'<<='",
+            RIGHT_SHIFT_ASSIGN => "'>>='",
+            PLUS_ASSIGN => "'+='",
+            MINUS_ASSIGN => "'-='",
+            MUL_ASSIGN => "'*='",
+            DIV_ASSIGN => "'/='",
+            MODULO_ASSIGN => "'%='",
+            NEWLINE => "newline",
+            EOF     => "end of file",
+            // Imaginary tokens named in errors about a node.
+            CALL    => "call",
+            ARRAY_DECL => "array literal",
+            HASH_DECL => "hash literal",
+            ARRAY_COMP => "array comprehension",
+            HASH_COMP => "hash comprehension",
+            SUB_LITERAL => "subroutine literal",
+            TERNARY => "conditional expression",
+            RANGE   => "range",
+            SLICE   => "slice",
+            IN_TEST => "'in'",
+            CHAIN_CMP => "comparison",
+            NEGATE  => "'-'",
+            UNARY_PLUS => "'+'",
+            DO_EXPR => "do expression",
+            _ => return write!(f, "{:?}", self),
+        };
+        return write!(f, "{}", text);
+    }
+}
+
+/*
+ * Type names as reported to ares programs. Every
+ * runtime check on the kind of a value should go
+ * through these so the names stay consistent.
+ */
+pub const TYPE_STRING: &'static str = "string";
+pub const TYPE_INTEGER: &'static str = "int";
+pub const TYPE_FLOAT: &'static str = "float";
+pub const TYPE_BOOL: &'static str = "bool";
+pub const TYPE_REGEX: &'static str = "regex";
+pub const TYPE_ARRAY: &'static str = "array";
+pub const TYPE_NIL: &'static str = "nil";
+
+/*
+ * The longest string repetition may build, in bytes. Past
+ * it the program gets an error rather than the process
+ * failing to allocate.
+ */
+pub const MAX_STRING_LEN: usize = 1 << 30;
+
 #[derive(Clone, PartialEq, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum Value {
     StringValue(String),
     IntegerValue(i64),
     FloatValue(f64),
     BoolValue(bool),
+    // The pattern and the flags following the closing '/'.
+    RegexValue(String, String),
+    ArrayValue(Vec<Value>),
+    NilValue,
+}
+
+impl Value
+{
+    pub fn type_name(&self) -> &'static str
+    {
+        return match *self {
+            Value::StringValue(_)  => TYPE_STRING,
+            Value::IntegerValue(_) => TYPE_INTEGER,
+            Value::FloatValue(_)   => TYPE_FLOAT,
+            Value::BoolValue(_)    => TYPE_BOOL,
+            Value::RegexValue(..)  => TYPE_REGEX,
+            Value::ArrayValue(_)   => TYPE_ARRAY,
+            Value::NilValue        => TYPE_NIL,
+        }
+    }
+
+    pub fn is_a(&self, type_name: &str) -> bool
+    {
+        return self.type_name() == type_name;
+    }
+
+    pub fn is_string(&self) -> bool
+    {
+        return self.is_a(TYPE_STRING);
+    }
+
+    pub fn is_int(&self) -> bool
+    {
+        return self.is_a(TYPE_INTEGER);
+    }
+
+    pub fn is_float(&self) -> bool
+    {
+        return self.is_a(TYPE_FLOAT);
+    }
+
+    pub fn is_bool(&self) -> bool
+    {
+        return self.is_a(TYPE_BOOL);
+    }
+
+    pub fn is_regex(&self) -> bool
+    {
+        return self.is_a(TYPE_REGEX);
+    }
+
+    pub fn is_array(&self) -> bool
+    {
+        return self.is_a(TYPE_ARRAY);
+    }
+
+    pub fn is_nil(&self) -> bool
+    {
+        return self.is_a(TYPE_NIL);
+    }
+
+    /*
+     * Brings two numbers to a common kind, an int paired
+     * with a float becomes a float. Any other pair is
+     * returned as it is.
+     */
+    pub fn coerce_numeric(a: Value, b: Value) -> (Value, Value)
+    {
+        return match (a, b) {
+            (Value::IntegerValue(a), Value::FloatValue(b)) => {
+                (Value::FloatValue(a as f64), Value::FloatValue(b))
+            },
+            (Value::FloatValue(a), Value::IntegerValue(b)) => {
+                (Value::FloatValue(a), Value::FloatValue(b as f64))
+            },
+            pair => pair,
+        }
+    }
+
+    // Two strings are concatenated, 'a' + 'b' is 'ab'.
+    pub fn add(&self, other: &Value) -> Result<Value, String>
+    {
+        if let (&Value::StringValue(ref a), &Value::StringValue(ref b)) =
+            (self, other) {
+            return Ok(Value::StringValue(format!("{}{}", a, b)));
+        }
+        return self.arithmetic(other, "+", i64::checked_add, |a, b| a + b);
+    }
+
+    pub fn sub(&self, other: &Value) -> Result<Value, String>
+    {
+        return self.arithmetic(other, "-", i64::checked_sub, |a, b| a - b);
+    }
+
+    /*
+     * A string and an int, in either order, repeat the
+     * string, 'ab' * 3 is 'ababab' and 'ab' * 0 is ''. A
+     * negative count is an error rather than an empty
+     * string, it is more likely a bug than intended.
+     */
+    pub fn mul(&self, other: &Value) -> Result<Value, String>
+    {
+        return match (self, other) {
+            (&Value::StringValue(ref s), &Value::IntegerValue(n)) |
+            (&Value::IntegerValue(n), &Value::StringValue(ref s)) => {
+                repeat(s, n)
+            },
+            _ => self.arithmetic(other, "*", i64::checked_mul, |a, b| a * b),
+        }
+    }
+
+    /*
+     * '/' always gives a float, 7 / 2 is 3.5 rather than a
+     * silently truncated 3. Floor division, 7 // 2, keeps
+     * two ints an int and rounds towards negative infinity,
+     * so -7 // 2 is -4. With a float operand it gives the
+     * floor as a float, 7.5 // 2 is 3.0.
+     */
+    pub fn div(&self, other: &Value) -> Result<Value, String>
+    {
+        if let (&Value::IntegerValue(a), &Value::IntegerValue(b)) =
+            (self, other) {
+            return Value::FloatValue(a as f64)
+                .arithmetic(&Value::FloatValue(b as f64), "/",
+                            i64::checked_div, |a, b| a / b);
+        }
+        return self.arithmetic(other, "/", i64::checked_div, |a, b| a / b);
+    }
+
+    pub fn floor_div(&self, other: &Value) -> Result<Value, String>
+    {
+        return self.arithmetic(other, "//", floor_div,
+                               |a, b| (a / b).floor());
+    }
+
+    pub fn modulo(&self, other: &Value) -> Result<Value, String>
+    {
+        return self.arithmetic(other, "%", i64::checked_rem, |a, b| a % b);
+    }
+
+    /*
+     * Ints stay ints as long as the result fits, a float on
+     * either side makes the result a float. Dividing by zero
+     * is an error for both kinds.
+     */
+    fn arithmetic(&self, other: &Value, op: &str,
+                  int_op: fn(i64, i64) -> Option<i64>,
+                  float_op: fn(f64, f64) -> f64)
+        -> Result<Value, String>
+    {
+        let divides = op == "/" || op == "//" || op == "%";
+
+        return match Value::coerce_numeric(self.clone(), other.clone()) {
+            (Value::IntegerValue(_), Value::IntegerValue(0)) if divides => {
+                Err("division by zero".to_string())
+            },
+            (Value::IntegerValue(a), Value::IntegerValue(b)) => {
+                match int_op(a, b) {
+                    Some(i) => Ok(Value::IntegerValue(i)),
+                    None => Err("integer overflow".to_string()),
+                }
+            },
+            (Value::FloatValue(_), Value::FloatValue(b))
+                if divides && b == 0.0 => {
+                Err("division by zero".to_string())
+            },
+            (Value::FloatValue(a), Value::FloatValue(b)) => {
+                Ok(Value::FloatValue(float_op(a, b)))
+            },
+            _ => Err(format!("bad operand types for '{}': {} and {}", op,
+                             self.type_name(), other.type_name())),
+        }
+    }
+
+    pub fn bit_or(&self, other: &Value) -> Result<Value, String>
+    {
+        let (a, b) = self.integers(other, "|")?;
+        return Ok(Value::IntegerValue(a | b));
+    }
+
+    pub fn bit_and(&self, other: &Value) -> Result<Value, String>
+    {
+        let (a, b) = self.integers(other, "&")?;
+        return Ok(Value::IntegerValue(a & b));
+    }
+
+    pub fn bit_xor(&self, other: &Value) -> Result<Value, String>
+    {
+        let (a, b) = self.integers(other, "^")?;
+        return Ok(Value::IntegerValue(a ^ b));
+    }
+
+    pub fn shl(&self, other: &Value) -> Result<Value, String>
+    {
+        let (a, b) = self.integers(other, "<<")?;
+        return Ok(Value::IntegerValue(a << shift_amount(b)?));
+    }
+
+    // An arithmetic shift, the sign bit is kept.
+    pub fn shr(&self, other: &Value) -> Result<Value, String>
+    {
+        let (a, b) = self.integers(other, ">>")?;
+        return Ok(Value::IntegerValue(a >> shift_amount(b)?));
+    }
+
+    pub fn compl(&self) -> Result<Value, String>
+    {
+        return match *self {
+            Value::IntegerValue(i) => Ok(Value::IntegerValue(!i)),
+            _ => Err(format!("bad operand type for '~': {}",
+                             self.type_name())),
+        }
+    }
+
+    // Bitwise operators take ints only, floats are not promoted.
+    fn integers(&self, other: &Value, op: &str) -> Result<(i64, i64), String>
+    {
+        return match (self, other) {
+            (&Value::IntegerValue(a), &Value::IntegerValue(b)) => Ok((a, b)),
+            _ => Err(format!("bad operand types for '{}': {} and {}", op,
+                             self.type_name(), other.type_name())),
+        }
+    }
+}
+
+fn floor_div(a: i64, b: i64) -> Option<i64>
+{
+    let quotient = a.checked_div(b)?;
+    if a % b != 0 && (a < 0) != (b < 0) {
+        return Some(quotient - 1);
+    }
+    return Some(quotient);
+}
+
+fn repeat(s: &str, count: i64) -> Result<Value, String>
+{
+    if count < 0 {
+        return Err(format!("cannot repeat a string {} times", count));
+    }
+    let len = (count as u64).checked_mul(s.len() as u64);
+    match len {
+        Some(len) if len <= MAX_STRING_LEN as u64 => (),
+        _ => return Err("repeated string is too long".to_string()),
+    }
+    return Ok(Value::StringValue(s.repeat(count as usize)));
+}
+
+// Shifting an i64 by 64 or more places, or by a negative amount, is an error.
+fn shift_amount(amount: i64) -> Result<u32, String>
+{
+    if amount < 0 || amount > 63 {
+        return Err(format!("invalid shift amount {}", amount));
+    }
+    return Ok(amount as u32);
+}
+
+/*
+ * How a value reads in output such as the debug
+ * statement, strings are written as they are.
+ */
+impl fmt::Display for Value
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+    {
+        return match *self {
+            Value::StringValue(ref s) => write!(f, "{}", s),
+            Value::IntegerValue(i) => write!(f, "{}", i),
+            Value::FloatValue(x) => {
+                // Keep 3.0 from reading like the integer 3.
+                if x.is_finite() && x.fract() == 0.0 {
+                    write!(f, "{:.1}", x)
+                }
+                else {
+                    write!(f, "{}", x)
+                }
+            },
+            Value::BoolValue(b) => write!(f, "{}", b),
+            Value::RegexValue(ref pattern, ref flags) => {
+                write!(f, "/{}/{}", pattern, flags)
+            },
+            // The elements as Debug writes them, ["a", 1].
+            Value::ArrayValue(ref elements) => {
+                write!(f, "[")?;
+                for (i, element) in elements.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{:?}", element)?;
+                }
+                write!(f, "]")
+            },
+            Value::NilValue => write!(f, "nil"),
+        }
+    }
+}
+
+/*
+ * Like Display, except strings are quoted and escaped so
+ * "1" and 1 can be told apart.
+ */
+impl fmt::Debug for Value
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+    {
+        return match *self {
+            Value::StringValue(ref s) => write!(f, "{:?}", s),
+            _ => write!(f, "{}", self),
+        }
+    }
+}
+
+/*
+ * Handle for an interned string, see Interner. Two symbols
+ * from the same interner are equal exactly when their
+ * strings are.
+ */
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Symbol(pub u32);
+
+/*
+ * Keeps one copy of every string handed to it. The scanner
+ * interns identifiers, keywords and operators, so a name
+ * used a thousand times is allocated once and every token
+ * spelling it shares that allocation.
+ */
+pub struct Interner {
+    symbols: HashMap<Rc<str>, Symbol>,
+    strings: Vec<Rc<str>>,
+}
+
+impl Interner
+{
+    pub fn new() -> Interner
+    {
+        return Interner {
+            symbols: HashMap::new(),
+            strings: Vec::new(),
+        }
+    }
+
+    pub fn intern(&mut self, text: &str) -> Symbol
+    {
+        if let Some(symbol) = self.symbols.get(text) {
+            return *symbol;
+        }
+        let symbol = Symbol(self.strings.len() as u32);
+        let shared: Rc<str> = Rc::from(text);
+
+        self.strings.push(shared.clone());
+        self.symbols.insert(shared, symbol);
+        return symbol;
+    }
+
+    pub fn resolve(&self, symbol: Symbol) -> &str
+    {
+        return &self.strings[symbol.0 as usize];
+    }
+
+    /*
+     * The interned string itself, for tokens to hold on to
+     * without copying it.
+     */
+    pub fn shared(&self, symbol: Symbol) -> Rc<str>
+    {
+        return self.strings[symbol.0 as usize].clone();
+    }
+
+    // Number of distinct strings interned.
+    pub fn len(&self) -> usize
+    {
+        return self.strings.len();
+    }
 }
 
 // A semantic bombshell :)
 #[derive(Clone, PartialEq, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Token {
-    pub text: String,
+    /*
+     * Shared with the interner for words and operators, so
+     * cloning a token never copies its text.
+     */
+    pub text: Rc<str>,
+    // Set when text came out of the scanner's interner.
+    pub symbol: Option<Symbol>,
     pub token_type: TokenType,
     pub value: Value,
     pub line_num: i32,
     pub line_pos: i32,
+    // Byte offsets of the lexeme, end is exclusive.
+    pub start: usize,
+    pub end: usize,
 }
 
 impl Token
 {
-    pub fn new(line_num: i32, line_pos: i32) -> Token
+    pub fn new(line_num: i32, line_pos: i32, start: usize) -> Token
     {
         return Token {
-            text: String::from(""),
+            text: Rc::from(""),
+            symbol: None,
             token_type: TokenType::STRING,
             value: Value::IntegerValue(0i64),
             line_num: line_num,
-            line_pos: line_pos, 
+            line_pos: line_pos,
+            start: start,
+            end: start,
         }
     }
 
     pub fn new_imag(text: String, token_type: TokenType,
-                    line_num: i32, line_pos: i32)
+                    line_num: i32, line_pos: i32, start: usize)
         -> Token
     {
         /*
          * Imaginary tokens dont really require the text
          * field, but are added to give readable output
-         * when generating a node string tree.
+         * when generating a node string tree. They span
+         * zero bytes at the position they were made.
          */
         return Token {
-            text: text,
+            text: Rc::from(text),
+            symbol: None,
             token_type: token_type,
             value: Value::IntegerValue(0i64),
             line_num: line_num,
             line_pos: line_pos,
+            start: start,
+            end: start,
         }
     }
 
-    pub fn string(&self) -> String
+    pub fn string(&self) -> &str
     {
-        return self.text.clone();
+        return &self.text;
     }
 }
\ No newline at end of file
@@ -0,0 +1,269 @@
+/*
+ * Test that the helpers on Value agree with each
+ * other for every variant.
+ */
+use token::*;
+use token::Value::*;
+use token::TokenType::*;
+
+pub struct ValueMatcher;
+
+impl ValueMatcher
+{
+    pub fn match_type_predicates()
+    {
+        let values = [StringValue("ares".to_string()),
+                      IntegerValue(1),
+                      FloatValue(1.5),
+                      BoolValue(true),
+                      RegexValue("ab+".to_string(), "i".to_string()),
+                      ArrayValue(vec![IntegerValue(1)]),
+                      NilValue];
+        let names = [TYPE_STRING, TYPE_INTEGER, TYPE_FLOAT,
+                     TYPE_BOOL, TYPE_REGEX, TYPE_ARRAY, TYPE_NIL];
+        println!("Starting match_type_predicates() test..");
+        for (i, value) in values.iter().enumerate() {
+            if value.type_name() != names[i] {
+                println!("{}. type_name({}) != expected type_name({})",
+                         i, value.type_name(), names[i]);
+            }
+            for (j, name) in names.iter().enumerate() {
+                if value.is_a(name) != (i == j) {
+                    println!("{}. is_a({}) returned {}", i, name,
+                             value.is_a(name));
+                }
+            }
+            let predicates = [value.is_string(), value.is_int(),
+                              value.is_float(), value.is_bool(),
+                              value.is_regex(), value.is_array(),
+                              value.is_nil()];
+            for (j, matched) in predicates.iter().enumerate() {
+                if *matched != (i == j) {
+                    println!("{}. predicate for {} returned {}", i,
+                             names[j], matched);
+                }
+            }
+        }
+        println!("Ending match_type_predicates() test..");
+    }
+
+    pub fn match_display()
+    {
+        let tests = [(StringValue("a \"b\"".to_string()), "a \"b\"",
+                      "\"a \\\"b\\\"\""),
+                     (IntegerValue(-42), "-42", "-42"),
+                     (FloatValue(1.5), "1.5", "1.5"),
+                     (FloatValue(3.0), "3.0", "3.0"),
+                     (FloatValue(0.1 + 0.2), "0.30000000000000004",
+                      "0.30000000000000004"),
+                     (BoolValue(false), "false", "false"),
+                     (RegexValue("ab+".to_string(), "i".to_string()),
+                      "/ab+/i", "/ab+/i"),
+                     (ArrayValue(vec![StringValue("a".to_string()),
+                                      ArrayValue(vec![IntegerValue(1)])]),
+                      "[\"a\", [1]]", "[\"a\", [1]]"),
+                     (NilValue, "nil", "nil")];
+        println!("Starting match_display() test..");
+        for (i, &(ref value, display, debug)) in tests.iter().enumerate() {
+            if format!("{}", value) != display {
+                println!("{}. display({}) != expected display({})", i,
+                         value, display);
+            }
+            if format!("{:?}", value) != debug {
+                println!("{}. debug({:?}) != expected debug({})", i,
+                         value, debug);
+            }
+        }
+        println!("Ending match_display() test..");
+    }
+
+    pub fn match_token_type_display()
+    {
+        let tests = [(PLUS, "'+'"), (RIGHT_SHIFT_ASSIGN, "'>>='"),
+                     (IF, "keyword 'if'"), (RETURN, "keyword 'return'"),
+                     (INTEGER, "integer literal"), (NIL, "'nil'"),
+                     (IDENT, "identifier"), (NEWLINE, "newline"),
+                     (EOF, "end of file"), (CALL, "call"),
+                     (NEGATE, "'-'"), (SUB_DECL, "SUB_DECL")];
+        println!("Starting match_token_type_display() test..");
+        for (i, &(token_type, text)) in tests.iter().enumerate() {
+            if token_type.to_string() != text {
+                println!("{}. display({}) != expected display({})", i,
+                         token_type, text);
+            }
+        }
+        println!("Ending match_token_type_display() test..");
+    }
+
+    /*
+     * Pins each class at its edges, so reordering
+     * TokenType shows up here.
+     */
+    pub fn match_token_classes()
+    {
+        let tests: [(fn(&TokenType) -> bool, &str, &[TokenType],
+                     &[TokenType]); 4] = [
+            (TokenType::is_literal, "is_literal",
+             &[STRING, INTEGER, FLOAT, TRUE, NIL, REGEX, IDENT],
+             &[DEF, EOF, BLOCK]),
+            (TokenType::is_keyword, "is_keyword",
+             &[DEF, CONST, IF, WITH, MATCH, IN, IMPORT],
+             &[IDENT, DEBUG, RETURN, LOGICAL_OR]),
+            (TokenType::is_operator, "is_operator",
+             &[LOGICAL_OR, EQL, GE, RIGHT_SHIFT, PLUS, FLOOR_DIV, BANG,
+               COMPL],
+             &[YIELD, DOT, DOTDOT, LPAREN, ASSIGN, NEGATE]),
+            (TokenType::is_assignment, "is_assignment",
+             &[ASSIGN, PLUS_ASSIGN, DIV_ASSIGN, MODULO_ASSIGN],
+             &[NEWLINE, ASSIGN_ARROW, BLOCK]),
+        ];
+        println!("Starting match_token_classes() test..");
+        for (i, &(class, name, members, others)) in tests.iter().enumerate() {
+            for token_type in members {
+                if !class(token_type) {
+                    println!("{}. {} is not {}", i, token_type, name);
+                }
+            }
+            for token_type in others {
+                if class(token_type) {
+                    println!("{}. {} is {}", i, token_type, name);
+                }
+            }
+        }
+        println!("Ending match_token_classes() test..");
+    }
+
+    pub fn match_arithmetic()
+    {
+        let int = IntegerValue;
+        let float = FloatValue;
+        let tests = [
+            (int(1).add(&int(2)), Ok(int(3))),
+            (int(1).add(&float(2.0)), Ok(float(3.0))),
+            (float(0.5).mul(&int(4)), Ok(float(2.0))),
+            (int(7).div(&int(2)), Ok(float(3.5))),
+            (int(6).div(&int(2)), Ok(float(3.0))),
+            (int(7).div(&float(2.0)), Ok(float(3.5))),
+            (int(7).floor_div(&int(2)), Ok(int(3))),
+            (int(-7).floor_div(&int(2)), Ok(int(-4))),
+            (int(7).floor_div(&int(-2)), Ok(int(-4))),
+            (int(-6).floor_div(&int(2)), Ok(int(-3))),
+            (float(7.5).floor_div(&int(2)), Ok(float(3.0))),
+            (int(-7).floor_div(&float(2.0)), Ok(float(-4.0))),
+            (int(1).floor_div(&int(0)), Err("division by zero")),
+            (int(1).div(&int(0)), Err("division by zero")),
+            (int(-7).modulo(&int(3)), Ok(int(-1))),
+            (int(2).sub(&float(0.5)), Ok(float(1.5))),
+            (float(1.0).div(&int(0)), Err("division by zero")),
+            (float(1.0).div(&float(0.0)), Err("division by zero")),
+            (int(1).modulo(&int(0)), Err("division by zero")),
+            (int(i64::max_value()).add(&int(1)), Err("integer overflow")),
+            (int(i64::min_value()).floor_div(&int(-1)),
+             Err("integer overflow")),
+            (StringValue("a".to_string()).add(&int(1)),
+             Err("bad operand types for '+': string and int")),
+            (NilValue.mul(&float(1.0)),
+             Err("bad operand types for '*': nil and float")),
+        ];
+        println!("Starting match_arithmetic() test..");
+        for (i, &(ref result, ref expected)) in tests.iter().enumerate() {
+            let expected = expected.clone().map_err(|e| e.to_string());
+            if *result != expected {
+                println!("{}. result({:?}) != expected result({:?})", i,
+                         result, expected);
+            }
+        }
+        let pairs = [(int(1), float(2.5), float(1.0), float(2.5)),
+                     (float(2.5), int(1), float(2.5), float(1.0)),
+                     (int(1), int(2), int(1), int(2)),
+                     (NilValue, int(2), NilValue, int(2))];
+        for (i, &(ref a, ref b, ref x, ref y)) in pairs.iter().enumerate() {
+            let coerced = Value::coerce_numeric(a.clone(), b.clone());
+            if coerced != (x.clone(), y.clone()) {
+                println!("{}. coerced({:?}) != expected coerced(({:?}, {:?}))",
+                         i, coerced, x, y);
+            }
+        }
+        println!("Ending match_arithmetic() test..");
+    }
+
+    pub fn match_strings()
+    {
+        let string = |s: &str| StringValue(s.to_string());
+        let tests = [
+            (string("a").add(&string("b")), Ok(string("ab"))),
+            (string("").add(&string("")), Ok(string(""))),
+            (string("ab").mul(&IntegerValue(3)), Ok(string("ababab"))),
+            (IntegerValue(2).mul(&string("é")), Ok(string("éé"))),
+            (string("ab").mul(&IntegerValue(0)), Ok(string(""))),
+            (string("ab").mul(&IntegerValue(-1)),
+             Err("cannot repeat a string -1 times")),
+            (string("ab").mul(&IntegerValue(i64::max_value())),
+             Err("repeated string is too long")),
+            (string("a").mul(&IntegerValue(i64::max_value())),
+             Err("repeated string is too long")),
+            (string("ab").mul(&IntegerValue(MAX_STRING_LEN as i64 / 2 + 1)),
+             Err("repeated string is too long")),
+            (string("").mul(&IntegerValue(i64::max_value())),
+             Ok(string(""))),
+            (string("a").sub(&string("b")),
+             Err("bad operand types for '-': string and string")),
+            (string("a").add(&IntegerValue(1)),
+             Err("bad operand types for '+': string and int")),
+            (string("a").mul(&FloatValue(2.0)),
+             Err("bad operand types for '*': string and float")),
+            (string("a").mul(&string("b")),
+             Err("bad operand types for '*': string and string")),
+        ];
+        println!("Starting match_strings() test..");
+        for (i, &(ref result, ref expected)) in tests.iter().enumerate() {
+            let expected = expected.clone().map_err(|e| e.to_string());
+            if *result != expected {
+                println!("{}. result({:?}) != expected result({:?})", i,
+                         result, expected);
+            }
+        }
+        println!("Ending match_strings() test..");
+    }
+
+    pub fn match_bitwise()
+    {
+        let int = IntegerValue;
+        let tests = [
+            (int(0).compl(), Ok(int(-1))),
+            (int(1).shl(&int(3)), Ok(int(8))),
+            (int(1).shl(&int(63)), Ok(int(i64::min_value()))),
+            (int(-16).shr(&int(2)), Ok(int(-4))),
+            (int(12).bit_or(&int(3)), Ok(int(15))),
+            (int(12).bit_and(&int(6)), Ok(int(4))),
+            (int(12).bit_xor(&int(6)), Ok(int(10))),
+            (int(1).shl(&int(64)), Err("invalid shift amount 64")),
+            (int(1).shr(&int(-1)), Err("invalid shift amount -1")),
+            (FloatValue(1.0).bit_or(&int(1)),
+             Err("bad operand types for '|': float and int")),
+            (int(1).shl(&StringValue("a".to_string())),
+             Err("bad operand types for '<<': int and string")),
+            (FloatValue(0.0).compl(), Err("bad operand type for '~': float")),
+        ];
+        println!("Starting match_bitwise() test..");
+        for (i, &(ref result, ref expected)) in tests.iter().enumerate() {
+            let expected = expected.clone().map_err(|e| e.to_string());
+            if *result != expected {
+                println!("{}. result({:?}) != expected result({:?})", i,
+                         result, expected);
+            }
+        }
+        println!("Ending match_bitwise() test..");
+    }
+
+    pub fn match_all()
+    {
+        ValueMatcher::match_type_predicates();
+        ValueMatcher::match_arithmetic();
+        ValueMatcher::match_strings();
+        ValueMatcher::match_bitwise();
+        ValueMatcher::match_display();
+        ValueMatcher::match_token_type_display();
+        ValueMatcher::match_token_classes();
+    }
+}